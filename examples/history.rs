@@ -0,0 +1,48 @@
+use std::sync::Arc;
+
+use smol_macros::Executor;
+use tradingview_websocket_client::{DefaultTradingViewMessageProcessor, TradingViewClient, TradingViewClientConfig, TradingViewClientMode, TradingViewHistoryTarget, TradingViewMessageProcessor};
+
+#[macro_rules_attribute::apply(smol_macros::main!)]
+async fn main(executor: Arc<Executor<'static>>) -> anyhow::Result<()> {
+    // init logging
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("debug,websocket_client=info,rustls=info,http_client=info")).init();
+
+    // init env vars
+    dotenvy::from_filename("./.env").expect("failed to load env vars");
+    let auth_token = std::env::var("AUTH_TOKEN").expect("failed to get AUTH_TOKEN");
+
+    // build message processor
+    let message_processor: Arc<Box<dyn TradingViewMessageProcessor + Send + Sync>> = Arc::new(Box::new(DefaultTradingViewMessageProcessor {}));
+
+    // get symbol + bar count
+    let args = std::env::args().collect::<Vec<_>>();
+    let symbol = &args[1];
+    let bars = args.get(2).map(|bars| bars.parse::<usize>().expect("failed to parse bar count")).unwrap_or(5000);
+    let config = TradingViewClientConfig {
+        name: symbol.to_string(),
+        endpoint: None,
+        auth_token: auth_token.clone(),
+        chart_symbols: vec![],
+        quote_symbols: vec![],
+        quote_fields: None,
+        indicators: vec![],
+        timeframe: "5".to_string(),
+        range: 300,
+        mode: TradingViewClientMode::Standard,
+        backlog_capacity: None,
+        reconnect: None,
+        bar_close_delay_ms: None
+    };
+    let client: TradingViewClient = config.to_client(message_processor);
+
+    // page back through history
+    let history = client.fetch_history(executor, symbol, "5", TradingViewHistoryTarget::Bars(bars)).await?;
+    log::info!("fetched {} bars", history.len());
+    if let (Some(first), Some(last)) = (history.first(), history.last()) {
+        log::info!("first = {first:?}");
+        log::info!("last = {last:?}");
+    }
+
+    Ok(())
+}
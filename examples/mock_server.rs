@@ -0,0 +1,44 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use smol_macros::Executor;
+use tradingview_websocket_client::{DefaultTradingViewMessageProcessor, MockTradingViewScript, MockTradingViewServer, TradingViewClientConfig, TradingViewClientMode, TradingViewEndpointConfig, TradingViewHistoryTarget, TradingViewIndicator, TradingViewMessageProcessor, TradingViewStudyMetadata, SPY5_REG_SYMBOL};
+
+#[macro_rules_attribute::apply(smol_macros::main!)]
+async fn main(executor: Arc<Executor<'static>>) -> anyhow::Result<()> {
+    // init logging
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
+
+    // build message processor
+    let message_processor: Arc<Box<dyn TradingViewMessageProcessor + Send + Sync>> = Arc::new(Box::new(DefaultTradingViewMessageProcessor {}));
+
+    // start mock server
+    let server = MockTradingViewServer::start(executor.clone(), MockTradingViewScript::new().with_ping_interval(Duration::from_millis(100))).await?;
+    let config = TradingViewClientConfig {
+        name: "MOCK".to_string(),
+        endpoint: Some(TradingViewEndpointConfig::new(&server.uri())),
+        auth_token: "unauthorized_user_token".to_string(),
+        chart_symbols: vec![SPY5_REG_SYMBOL.to_string()],
+        quote_symbols: vec!["AMEX:SPY".to_string()],
+        quote_fields: None,
+        indicators: vec![TradingViewIndicator::new("{}".to_string()).with_metadata(TradingViewStudyMetadata::from_titles(&["Close"]))],
+        timeframe: "5".to_string(),
+        range: 300,
+        mode: TradingViewClientMode::Standard,
+        backlog_capacity: None,
+        reconnect: None,
+        bar_close_delay_ms: None
+    };
+
+    // full session setup against the canned replies
+    let client = config.to_client(message_processor);
+    let scrape_result = client.run(executor.clone()).await?;
+    log::info!("scrape_result = {scrape_result:?}");
+    log::info!("server received {} commands", server.received_commands().await.len());
+
+    // paging back through history
+    let bars = client.fetch_history(executor.clone(), "AMEX:SPY", "5", TradingViewHistoryTarget::Bars(2500)).await?;
+    log::info!("history: {} bars, first = {:?}, last = {:?}", bars.len(), bars.first(), bars.last());
+
+    Ok(())
+}
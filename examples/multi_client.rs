@@ -2,7 +2,7 @@ use std::sync::Arc;
 use std::time::Duration;
 
 use smol_macros::Executor;
-use tradingview_websocket_client::{DefaultTradingViewMessageProcessor, TradingViewClientConfig, TradingViewClientMode, TradingViewIndicators, TradingViewMessageProcessor, SPY5_EXT_SYMBOL, SPY5_REG_SYMBOL};
+use tradingview_websocket_client::{DefaultTradingViewMessageProcessor, TradingViewClientConfig, TradingViewClientMode, TradingViewIndicator, TradingViewIndicators, TradingViewMessageProcessor, SPY5_EXT_SYMBOL, SPY5_REG_SYMBOL};
 
 #[macro_rules_attribute::apply(smol_macros::main!)]
 async fn main(executor: Arc<Executor<'static>>) -> anyhow::Result<()> {
@@ -31,28 +31,40 @@ async fn main(executor: Arc<Executor<'static>>) -> anyhow::Result<()> {
     let clients = vec![
         TradingViewClientConfig {
             name: "SPY5REG".to_string(),
+            endpoint: None,
             auth_token: auth_token.clone(),
             chart_symbols: vec![SPY5_REG_SYMBOL.to_string()],
             quote_symbols: vec![SPY5_REG_SYMBOL.to_string()],
+            quote_fields: None,
             indicators: vec![
-              vwap_mvwap_ema_crossover.clone()
+              TradingViewIndicator::new(vwap_mvwap_ema_crossover.clone())
+                .with_metadata(TradingViewIndicators::vwap_mvwap_ema_crossover_metadata())
             ],
             timeframe: "5".to_string(),
             range: 300,
-            mode: TradingViewClientMode::Streaming
+            mode: TradingViewClientMode::Streaming,
+            backlog_capacity: None,
+            reconnect: None,
+            bar_close_delay_ms: None
         }.to_client(message_processor1),
 
         TradingViewClientConfig {
             name: "SPY5EXT".to_string(),
+            endpoint: None,
             auth_token: auth_token.clone(),
             chart_symbols: vec![SPY5_EXT_SYMBOL.to_string()],
             quote_symbols: vec![SPY5_EXT_SYMBOL.to_string()],
+            quote_fields: None,
             indicators: vec![
-              vwap_mvwap_ema_crossover.clone()
+              TradingViewIndicator::new(vwap_mvwap_ema_crossover.clone())
+                .with_metadata(TradingViewIndicators::vwap_mvwap_ema_crossover_metadata())
             ],
             timeframe: "5".to_string(),
             range: 300,
-            mode: TradingViewClientMode::Streaming
+            mode: TradingViewClientMode::Streaming,
+            backlog_capacity: None,
+            reconnect: None,
+            bar_close_delay_ms: None
         }.to_client(message_processor2),
     ];
 
@@ -20,13 +20,18 @@ async fn main(executor: Arc<Executor<'static>>) -> anyhow::Result<()> {
     let symbol = &args[1];
     let config = TradingViewClientConfig {
         name: symbol.to_string(),
+        endpoint: None,
         auth_token: auth_token.clone(),
         chart_symbols: vec![],
         quote_symbols: vec![symbol.to_string()],
+        quote_fields: None,
         indicators: vec![],
         timeframe: "5".to_string(),
         range: 300,
-        mode: TradingViewClientMode::Standard
+        mode: TradingViewClientMode::Standard,
+        backlog_capacity: None,
+        reconnect: None,
+        bar_close_delay_ms: None
     };
     let client: TradingViewClient = config.to_client(message_processor);
 
@@ -0,0 +1,248 @@
+use miniserde::Serialize;
+
+use crate::parsed_message::{DataUpdateMessage, SeriesUpdate, TimescaleUpdate, TimescaleUpdatedMessage};
+use crate::timestamp::TradingViewTimestamp;
+
+/// One OHLCV candle, `index` is the bar index the server assigned to it.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Bar {
+    pub index: i64,
+    /// When the bar opened
+    pub timestamp: TradingViewTimestamp,
+    pub open: f64,
+    pub high: f64,
+    pub low: f64,
+    pub close: f64,
+    pub volume: f64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum BarSeriesChange {
+    /// An existing index was patched, usually the forming bar ticking
+    UpdatedInPlace,
+    /// An index that wasn't in the series yet
+    NewBar,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BarSeriesUpdate {
+    pub change: BarSeriesChange,
+    pub bar: Bar,
+    /// Final state of the previous bar when this update opened a new one
+    pub closed: Option<Bar>,
+}
+
+/// Current bars of one series of a chart session, kept sorted by index.
+#[derive(Debug, Clone, Serialize)]
+pub struct BarSeries {
+    pub chart_session_id: String,
+    pub series_id: String,
+    bars: Vec<Bar>,
+    /// Highest index already reported closed, so a timer close and the next bar never report the same bar twice
+    closed_index: Option<i64>,
+}
+
+impl From<&SeriesUpdate> for Bar {
+    fn from(update: &SeriesUpdate) -> Self {
+        Bar {
+            index: update.index,
+            timestamp: update.timestamp,
+            open: update.open,
+            high: update.high,
+            low: update.low,
+            close: update.close,
+            volume: update.volume,
+        }
+    }
+}
+
+impl From<&TimescaleUpdate> for Bar {
+    fn from(update: &TimescaleUpdate) -> Self {
+        Bar {
+            index: update.index,
+            timestamp: update.timestamp,
+            open: update.open,
+            high: update.high,
+            low: update.low,
+            close: update.close,
+            volume: update.volume,
+        }
+    }
+}
+
+impl BarSeries {
+    pub fn new(chart_session_id: &str, series_id: &str) -> Self {
+        Self {
+            chart_session_id: chart_session_id.to_string(),
+            series_id: series_id.to_string(),
+            bars: vec![],
+            closed_index: None,
+        }
+    }
+
+    pub fn bars(&self) -> &[Bar] {
+        &self.bars
+    }
+
+    /// The newest bar, the one still forming while the market is open.
+    pub fn last(&self) -> Option<&Bar> {
+        self.bars.last()
+    }
+
+    pub fn get(&self, index: i64) -> Option<&Bar> {
+        self.bars.binary_search_by_key(&index, |bar| bar.index).ok().map(|position| &self.bars[position])
+    }
+
+    pub fn len(&self) -> usize {
+        self.bars.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bars.is_empty()
+    }
+
+    /// Moves every bar `offset` indexes up, for history prepended to the chart session's time scale. Call before applying the
+    /// bars of the `timescale_update` that carried the offset, for every series of the session.
+    pub fn renumber(&mut self, offset: i64) {
+        if offset == 0 {
+            return;
+        }
+        for bar in &mut self.bars {
+            bar.index += offset;
+        }
+        self.closed_index = self.closed_index.map(|closed_index| closed_index + offset);
+    }
+
+    /// Applies the bars of a `timescale_update` for this series, ignoring updates for other series. Indexes have to be renumbered
+    /// by the update's `zoffset` first.
+    pub fn apply_timescale_update(&mut self, message: &TimescaleUpdatedMessage) -> Vec<BarSeriesUpdate> {
+        if message.chart_session_id != self.chart_session_id || message.update_key.as_deref() != Some(self.series_id.as_str()) {
+            return vec![];
+        }
+        let updates = message.updates.as_deref().unwrap_or_default();
+        updates.iter().map(|update| self.apply_bar(Bar::from(update))).collect()
+    }
+
+    /// Applies the bars of a `du` for this series, ignoring updates for other series and studies.
+    pub fn apply_data_update(&mut self, message: &DataUpdateMessage) -> Vec<BarSeriesUpdate> {
+        if message.chart_session_id != self.chart_session_id || message.update_key != self.series_id {
+            return vec![];
+        }
+        let updates = message.series_updates.as_deref().unwrap_or_default();
+        updates.iter().map(|update| self.apply_bar(Bar::from(update))).collect()
+    }
+
+    /// Replaces the bar with the same index or inserts it in order. A bar appended after the newest one closes it.
+    pub fn apply_bar(&mut self, bar: Bar) -> BarSeriesUpdate {
+        match self.bars.binary_search_by_key(&bar.index, |existing| existing.index) {
+            Ok(position) => {
+                self.bars[position] = bar.clone();
+                BarSeriesUpdate {
+                    change: BarSeriesChange::UpdatedInPlace,
+                    bar,
+                    closed: None,
+                }
+            },
+            Err(position) => {
+                let closed = match self.bars.last() {
+                    Some(previous) if position == self.bars.len() => self.close(previous.clone()),
+                    _ => None,
+                };
+                self.bars.insert(position, bar.clone());
+                BarSeriesUpdate {
+                    change: BarSeriesChange::NewBar,
+                    bar,
+                    closed,
+                }
+            }
+        }
+    }
+
+    /// Closes the newest bar once `now` is `delay_seconds` past the end of its timeframe and no new bar has replaced it.
+    pub fn close_expired(&mut self, now: i64, timeframe_seconds: i64, delay_seconds: i64) -> Option<Bar> {
+        let last = self.bars.last()?.clone();
+        if now < last.timestamp.seconds() + timeframe_seconds + delay_seconds {
+            return None;
+        }
+        self.close(last)
+    }
+
+    fn close(&mut self, bar: Bar) -> Option<Bar> {
+        if self.closed_index.map(|closed_index| bar.index <= closed_index).unwrap_or(false) {
+            return None;
+        }
+        self.closed_index = Some(bar.index);
+        Some(bar)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bar(index: i64, close: f64) -> Bar {
+        Bar {
+            index,
+            timestamp: TradingViewTimestamp::Seconds(1_700_000_100 + index * 300),
+            open: 100.0,
+            high: close.max(100.0),
+            low: close.min(100.0),
+            close,
+            volume: 1000.0,
+        }
+    }
+
+    #[test]
+    fn same_index_updates_in_place_and_next_index_is_new() {
+        let mut series = BarSeries::new("cs_000000000001", "sds_1");
+        assert_eq!(series.apply_bar(bar(0, 100.0)).change, BarSeriesChange::NewBar);
+
+        let update = series.apply_bar(bar(0, 100.5));
+        assert_eq!(update.change, BarSeriesChange::UpdatedInPlace);
+        assert_eq!(update.closed, None);
+        assert_eq!(series.len(), 1);
+        assert_eq!(series.last().map(|bar| bar.close), Some(100.5));
+
+        let update = series.apply_bar(bar(1, 101.0));
+        assert_eq!(update.change, BarSeriesChange::NewBar);
+        assert_eq!(series.len(), 2);
+    }
+
+    #[test]
+    fn append_closes_the_previous_bar_once() {
+        let mut series = BarSeries::new("cs_000000000001", "sds_1");
+        assert_eq!(series.apply_bar(bar(0, 100.0)).closed, None);
+        series.apply_bar(bar(0, 100.25));
+
+        // the close carries the last state of the bar
+        assert_eq!(series.apply_bar(bar(1, 101.0)).closed, Some(bar(0, 100.25)));
+        assert_eq!(series.apply_bar(bar(1, 101.5)).closed, None);
+        assert_eq!(series.apply_bar(bar(2, 102.0)).closed, Some(bar(1, 101.5)));
+
+        // history inserted before the newest bar closes nothing
+        let update = series.apply_bar(bar(-1, 99.0));
+        assert_eq!(update.change, BarSeriesChange::NewBar);
+        assert_eq!(update.closed, None);
+        assert_eq!(series.bars().iter().map(|bar| bar.index).collect::<Vec<_>>(), vec![-1, 0, 1, 2]);
+    }
+
+    #[test]
+    fn bar_seen_again_after_its_close_is_not_closed_twice() {
+        let mut series = BarSeries::new("cs_000000000001", "sds_1");
+        series.apply_bar(bar(0, 100.0));
+
+        // the timer closes bar 0, a late tick for it and the next bar don't close it again
+        let expired_at = bar(0, 100.0).timestamp.seconds() + 300;
+        assert_eq!(series.close_expired(expired_at - 1, 300, 0), None);
+        assert_eq!(series.close_expired(expired_at, 300, 0), Some(bar(0, 100.0)));
+        assert_eq!(series.close_expired(expired_at + 1, 300, 0), None);
+        assert_eq!(series.apply_bar(bar(0, 100.1)).closed, None);
+        assert_eq!(series.apply_bar(bar(1, 101.0)).closed, None);
+
+        // bar 1 closes normally when bar 2 opens, even seen again in between
+        series.apply_bar(bar(1, 101.2));
+        assert_eq!(series.apply_bar(bar(2, 102.0)).closed, Some(bar(1, 101.2)));
+        assert_eq!(series.apply_bar(bar(1, 101.3)).closed, None);
+        assert_eq!(series.apply_bar(bar(2, 102.1)).closed, None);
+    }
+}
@@ -1,58 +1,318 @@
+use std::collections::HashMap;
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
-use async_executor::Executor;
+use async_channel::{Receiver, Sender};
+use async_executor::{Executor, Task};
+use async_io::Timer;
 use async_lock::RwLock;
-use http::{Request, Uri, Version};
-use http_client::HttpClient;
+use http::{HeaderName, HeaderValue, Request, Uri, Version};
 
 use websocket_client::{WebSocketHelpers, WebSocketReader, WebSocketWriter};
-use futures_lite::io::{BufReader, BufWriter};
-
-use crate::parsed_message::ParsedTradingViewMessage;
+use futures_lite::io::{AsyncWrite, BufReader, BufWriter};
+
+use crate::parsed_message::{ParsedTradingViewMessage, ServerHelloMessage, TickmarkUpdateMessage, TimescaleUpdate};
+use crate::command::TradingViewCommand;
+use crate::bar_series::BarSeries;
+use crate::study_join::{StudyBarJoin, TimestampedStudyRow};
+use crate::quote_book::{QuoteSnapshot, TradingViewQuoteBook};
+use crate::quote_field::QuoteField;
+use crate::history::{TradingViewHistory, TradingViewHistoryTarget};
 use crate::utilities;
+use crate::error::TradingViewError;
+use crate::transport;
 use crate::client_config::TradingViewClientConfig;
 use crate::reader::TradingViewReader;
 use crate::writer::TradingViewWriter;
+use crate::session_registry::{TradingViewObjectStatus, TradingViewSessionRegistry};
 use crate::message_wrapper::TradingViewMessageWrapper;
-use crate::scrape_result::TradingViewScrapeResult;
+use crate::scrape_result::{QuoteMissingFields, TradingViewScrapeResult};
 use crate::message_processor::TradingViewMessageProcessor;
+use crate::client_event::TradingViewClientEvent;
+use crate::correlation::TradingViewCorrelationKey;
+use crate::dispatcher::{TradingViewBacklogStats, TradingViewMessageDispatcher};
+
+/// Bars requested per `request_more_data` page when fetching history.
+const HISTORY_PAGE_SIZE: usize = 1000;
+
+struct TradingViewConnection<W>
+where
+    W: AsyncWrite + Unpin,
+{
+    writer: TradingViewWriter<W>,
+    dispatcher: Arc<TradingViewMessageDispatcher>,
+    registry: TradingViewSessionRegistry,
+    server_hello_receiver: Receiver<TradingViewMessageWrapper>,
+    _reader_handle: Task<()>
+}
+
+/// Waits up to `timeout` for the reply on `receiver`. A `critical_error`, `protocol_error` or `study_error` arriving on one of
+/// `error_receivers` first fails the wait with the error it stands for instead of letting it time out.
+async fn wait_for_reply(receiver: &Receiver<TradingViewMessageWrapper>, error_receivers: &[&Receiver<TradingViewMessageWrapper>], timeout: Duration, failure: &str) -> anyhow::Result<TradingViewMessageWrapper> {
+    let reply = async {
+        receiver.recv().await.map_err(|_| anyhow::anyhow!("{failure}"))
+    };
+    let rejection = async {
+        let message = utilities::recv_any(error_receivers).await;
+        let err = message.parsed_message.to_error().ok_or(anyhow::anyhow!("unexpected {} while waiting", message.parsed_message.message_type()))?;
+        Err(anyhow::Error::from(err))
+    };
+    utilities::run_with_timeout(timeout, Box::pin(futures_lite::future::or(reply, rejection)))
+        .await
+        .ok_or(anyhow::anyhow!("timed out"))?
+}
+
+/// What woke the streaming loop up.
+enum TradingViewLiveInput {
+    Message(Option<Box<TradingViewMessageWrapper>>),
+    Command(TradingViewCommand)
+}
+
+struct ReconnectState {
+    attempts: usize,
+    disconnected_at: Option<Instant>
+}
 
 pub struct TradingViewClient {
     config: TradingViewClientConfig,
-    message_processor: Arc<Box<dyn TradingViewMessageProcessor + Send + Sync>>
+    message_processor: Arc<Box<dyn TradingViewMessageProcessor + Send + Sync>>,
+    dispatcher: RwLock<Option<Arc<TradingViewMessageDispatcher>>>,
+    registry: RwLock<Option<TradingViewSessionRegistry>>,
+    bar_series: RwLock<HashMap<(String, String), BarSeries>>,
+    study_joins: RwLock<HashMap<(String, String), StudyBarJoin>>,
+    quote_book: RwLock<TradingViewQuoteBook>,
+    /// Milliseconds the server clock is ahead of ours, from the last server hello
+    clock_offset_ms: RwLock<Option<i64>>,
+    /// Commands for the streaming loop to write, it owns the writer
+    command_sender: Sender<TradingViewCommand>,
+    command_receiver: Receiver<TradingViewCommand>
 }
 
 impl TradingViewClient {
     pub fn new(config: TradingViewClientConfig, message_processor: Arc<Box<dyn TradingViewMessageProcessor + Send + Sync>>) -> Self {
+        let (command_sender, command_receiver) = async_channel::unbounded();
         Self {
             config,
-            message_processor
+            message_processor,
+            dispatcher: RwLock::new(None),
+            registry: RwLock::new(None),
+            bar_series: RwLock::new(HashMap::new()),
+            study_joins: RwLock::new(HashMap::new()),
+            quote_book: RwLock::new(TradingViewQuoteBook::new()),
+            clock_offset_ms: RwLock::new(None),
+            command_sender,
+            command_receiver
         }
     }
 
+    /// Backlog of unmatched messages on the current connection, `None` before the first connect.
+    pub async fn backlog_stats(&self) -> Option<TradingViewBacklogStats> {
+        self.dispatcher.read().await.as_ref().map(|dispatcher| dispatcher.stats())
+    }
+
+    /// Loading status of a series or study created on the current connection.
+    pub async fn object_status(&self, chart_session_id: &str, object_id: &str) -> Option<TradingViewObjectStatus> {
+        self.registry.read().await.as_ref().and_then(|registry| registry.status(chart_session_id, object_id))
+    }
+
+    /// Current bars of a series created on the current connection.
+    pub async fn bar_series(&self, chart_session_id: &str, series_id: &str) -> Option<BarSeries> {
+        self.bar_series.read().await.get(&(chart_session_id.to_string(), series_id.to_string())).cloned()
+    }
+
+    /// Merged quote for a symbol, as added with `quote_add_symbols`.
+    pub async fn quote(&self, symbol: &str) -> Option<QuoteSnapshot> {
+        self.quote_book.read().await.get(symbol).cloned()
+    }
+
+    /// Milliseconds the server clock is ahead of the local one (negative when behind), measured from the last server hello.
+    /// `None` before the first hello.
+    pub async fn clock_offset_ms(&self) -> Option<i64> {
+        *self.clock_offset_ms.read().await
+    }
+
+    /// Current time on the server clock, the local clock when no offset is known yet.
+    pub async fn server_time(&self) -> SystemTime {
+        let now = SystemTime::now();
+        match self.clock_offset_ms().await {
+            Some(offset_ms) if offset_ms >= 0 => now + Duration::from_millis(offset_ms as u64),
+            Some(offset_ms) => now - Duration::from_millis(offset_ms.unsigned_abs()),
+            None => now
+        }
+    }
+
+    /// Copy of every quote received so far.
+    pub async fn quote_book(&self) -> TradingViewQuoteBook {
+        self.quote_book.read().await.clone()
+    }
+
+    /// Asks for `range` more time axis labels of `series_id` and waits for the `tickmark_update` that answers. Only a streaming
+    /// client can send, its connection stays open after setup.
+    pub async fn request_more_tickmarks(&self, chart_session_id: &str, series_id: &str, range: usize) -> anyhow::Result<TickmarkUpdateMessage> {
+        if !matches!(self.config.mode, crate::TradingViewClientMode::Streaming) {
+            return Err(anyhow::anyhow!("request_more_tickmarks needs a streaming client"));
+        }
+        let dispatcher = self.dispatcher.read().await.clone().ok_or(anyhow::anyhow!("not connected"))?;
+        let tickmark_update_receiver = dispatcher.register(TradingViewCorrelationKey::for_session("tickmark_update", chart_session_id)).await;
+        // an unknown series comes back as a critical_error for this chart session, errors for other sessions are left to whoever
+        // waits for them
+        let error_session_id = chart_session_id.to_string();
+        let critical_error_receiver = dispatcher.register_with(TradingViewCorrelationKey::new("critical_error"), move |message| {
+            match &message.parsed_message {
+                ParsedTradingViewMessage::CriticalError(critical_error_message) => critical_error_message.session_id == error_session_id,
+                _ => false
+            }
+        }).await;
+        let error_receivers = [&critical_error_receiver];
+        self.command_sender.send(TradingViewCommand::RequestMoreTickmarks {
+            chart_session_id: chart_session_id.to_string(),
+            series_id: series_id.to_string(),
+            range
+        }).await?;
+        let tickmark_update_message = wait_for_reply(&tickmark_update_receiver, &error_receivers, Duration::from_secs(5), "failed to get tickmark update message").await?;
+        let tickmark_update_message = tickmark_update_message.parsed_message.as_tickmark_update().ok_or(anyhow::anyhow!("failed to cast"))?;
+        Ok(tickmark_update_message.clone())
+    }
+
+    /// Connects and sets up every session, then returns (`Standard`) or keeps streaming until the connection fails for good
+    /// (`Streaming`). Failures are retried per `TradingViewClientConfig::reconnect` in both modes.
     pub async fn run(&self, executor: Arc<Executor<'static>>) -> anyhow::Result<TradingViewScrapeResult> {
+        let mut reconnect_state = ReconnectState {
+            attempts: 0,
+            disconnected_at: None
+        };
+        loop {
+            match self.run_connection(executor.clone(), &mut reconnect_state).await {
+                Ok(scrape_result) => return Ok(scrape_result),
+                Err(err) => {
+                    // the server said no, asking again on a new connection won't change its mind
+                    if err.downcast_ref::<TradingViewError>().map(|err| err.is_rejection()).unwrap_or(false) {
+                        return Err(err);
+                    }
+                    reconnect_state.attempts += 1;
+                    let reconnect = self.config.reconnect();
+                    if reconnect_state.attempts > reconnect.max_attempts {
+                        return Err(err);
+                    }
+                    if reconnect_state.disconnected_at.is_none() {
+                        reconnect_state.disconnected_at = Some(Instant::now());
+                    }
+                    let backoff = reconnect.backoff(reconnect_state.attempts);
+                    log::warn!("[{}] connection failed, reconnecting in {backoff:?} (attempt {}/{}): {err:?}", self.config.name, reconnect_state.attempts, reconnect.max_attempts);
+                    Timer::after(backoff).await;
+                }
+            }
+        }
+    }
+
+    /// Fetches history for `symbol` on a connection of its own, paging back with `request_more_data` until `target` is met or the server runs out of bars.
+    pub async fn fetch_history(&self, executor: Arc<Executor<'static>>, symbol: &str, timeframe: &str, target: TradingViewHistoryTarget) -> anyhow::Result<Vec<TimescaleUpdate>> {
+        let TradingViewConnection { writer: mut tv_writer, dispatcher, server_hello_receiver, _reader_handle, .. } = self.connect(executor).await?;
+
+        // wait for server hello message
+        let server_hello_message = wait_for_reply(&server_hello_receiver, &[], Duration::from_secs(1), "failed to get server hello message").await?;
+        self.record_server_hello(&server_hello_message).await?;
+
+        // set auth token + locale, a rejection comes back as critical_error or protocol_error
+        let critical_error_receiver = dispatcher.register(TradingViewCorrelationKey::new("critical_error")).await;
+        let protocol_error_receiver = dispatcher.register(TradingViewCorrelationKey::new("protocol_error")).await;
+        let error_receivers = [&critical_error_receiver, &protocol_error_receiver];
+        tv_writer.set_auth_token(&self.config.auth_token).await?;
+        tv_writer.set_locale("en", "US").await?;
+
+        // create chart session
+        let chart_session_id = "cs_000000000001";
+        let symbol_id = "sds_sym_1";
+        let series_id = "sds_1";
+        tv_writer.chart_create_session(chart_session_id).await?;
+
+        // resolve symbol
+        let symbol_resolved_receiver = dispatcher.register(TradingViewCorrelationKey::for_object("symbol_resolved", chart_session_id, symbol_id)).await;
+        tv_writer.resolve_symbol(chart_session_id, symbol_id, symbol).await?;
+        wait_for_reply(&symbol_resolved_receiver, &error_receivers, Duration::from_secs(2), "failed to get symbol resolved message").await?;
+
+        // first page comes from create_series, every following one from request_more_data
+        let mut history = TradingViewHistory::new();
+        let mut page = 0;
+        loop {
+            let series_loading_receiver = dispatcher.register(TradingViewCorrelationKey::for_object("series_loading", chart_session_id, series_id)).await;
+            let series_completed_receiver = dispatcher.register(TradingViewCorrelationKey::for_object("series_completed", chart_session_id, series_id)).await;
+            if page == 0 {
+                let range = match target {
+                    TradingViewHistoryTarget::Bars(count) => count.min(HISTORY_PAGE_SIZE),
+                    TradingViewHistoryTarget::Since(_) => HISTORY_PAGE_SIZE
+                };
+                tv_writer.create_series(chart_session_id, series_id, "s1", symbol_id, timeframe, range).await?;
+            } else {
+                tv_writer.request_more_data(chart_session_id, series_id, HISTORY_PAGE_SIZE).await?;
+            }
+            page += 1;
+
+            // wait for the series_loading -> timescale_update -> series_completed cycle
+            wait_for_reply(&series_loading_receiver, &error_receivers, Duration::from_secs(5), "failed to get series loading message").await?;
+            wait_for_reply(&series_completed_receiver, &error_receivers, Duration::from_secs(10), "failed to get series completed message").await?;
+
+            // messages are dispatched in order, so every timescale_update of this page is on the backlog by now, a page can be
+            // split across several of them. Pings are answered on the way to keep the connection alive between pages
+            let mut added = 0;
+            while let Some(message) = dispatcher.try_next_message() {
+                match &message.parsed_message {
+                    ParsedTradingViewMessage::TimescaleUpdate(timescale_update_message) if timescale_update_message.chart_session_id == chart_session_id && timescale_update_message.update_key.as_deref().map(|update_key| update_key == series_id).unwrap_or(true) => {
+                        // the update is numbered from 0, the bars before it moved up behind it
+                        history.renumber(timescale_update_message.zoffset());
+                        added += history.merge(timescale_update_message.updates.as_deref().unwrap_or_default());
+                    },
+                    ParsedTradingViewMessage::Ping(nonce) => tv_writer.pong(*nonce).await?,
+                    _ => ()
+                }
+            }
+            log::info!("[{}] history page {page}: {added} new bars, {} total, earliest = {:?}", self.config.name, history.len(), history.earliest_timestamp());
+
+            if history.is_satisfied(target) {
+                break;
+            }
+            if added == 0 {
+                log::info!("[{}] no more history available", self.config.name);
+                break;
+            }
+        }
+
+        tv_writer.close().await?;
+        Ok(history.into_bars(target))
+    }
+
+    /// Opens the websocket and starts the reader task, nothing is sent yet.
+    async fn connect(&self, executor: Arc<Executor<'static>>) -> anyhow::Result<TradingViewConnection<impl AsyncWrite + Unpin>> {
         // Build the URI for the request
-        let uri: Uri = "wss://data.tradingview.com/socket.io/websocket?type=chart".parse()?;
+        let endpoint = self.config.endpoint();
+        let uri: Uri = endpoint.uri.parse()?;
+        let host = uri.authority().ok_or(anyhow::anyhow!("uri without host: {uri}"))?.to_string();
 
         // Build the GET request
-        let request = Request::builder()
+        let sec_websocket_key = WebSocketHelpers::generate_sec_websocket_key();
+        let mut request = Request::builder()
             .method("GET")
             .version(Version::HTTP_11)
             .uri(uri)
-            .header("User-Agent", "Mozilla/5.0 (Macintosh; Intel Mac OS X 10_15_7) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/129.0.0.0 Safari/537.36")
-            .header("Host", "data.tradingview.com")
-            .header("Origin", "https://www.tradingview.com")            
+            .header("User-Agent", &endpoint.user_agent)
+            .header("Host", host)
+            .header("Origin", &endpoint.origin)
             .header("Connection", "Upgrade")
             .header("Upgrade", "websocket")      
             .header("Sec-WebSocket-Version", "13")                        
-            .header("Sec-WebSocket-Key", WebSocketHelpers::generate_sec_websocket_key())    
+            .header("Sec-WebSocket-Key", &sec_websocket_key)    
             .body(())?;
 
+        // extra headers win over the defaults
+        for (name, value) in &endpoint.headers {
+            request.headers_mut().insert(HeaderName::from_bytes(name.as_bytes())?, HeaderValue::from_str(value)?);
+        }
+
         // Get the response
-        let mut stream = HttpClient::connect(&request).await?;
-        let response = HttpClient::send::<(), String>(&mut stream, &request).await?;
+        let mut stream = transport::connect(&request).await?;
+        let response = transport::upgrade(&mut stream, &request).await?;
         log::info!("response = {response:?}");
+        transport::validate_upgrade(&response, &sec_websocket_key)?;
 
         // split
         let (reader, writer) = futures_lite::io::split(stream);
@@ -64,41 +324,23 @@ impl TradingViewClient {
         let ws_writer = WebSocketWriter::new(writer);        
 
         // Create the TradingViewClient
-        let mut tv_reader = TradingViewReader::new(ws_reader);
-        let mut tv_writer = TradingViewWriter::new(ws_writer);
+        let registry = TradingViewSessionRegistry::new();
+        let mut tv_reader = TradingViewReader::new(ws_reader, registry.clone());
+        let tv_writer = TradingViewWriter::new(ws_writer, registry.clone());
 
-        // prepare buffer + references
-        let buffer: Vec<TradingViewMessageWrapper> = Vec::new();
-        let buffer = RwLock::new(buffer);
-        let buffer_arc = Arc::new(buffer);
-        let reader_handle_buffer_ref = buffer_arc.clone();
-
-        // scrape result
-        let mut scrape_result = TradingViewScrapeResult {
-            server_hello_messages: vec![],
-            symbol_resolved_messages: vec![],
-            series_loading_messages: vec![],
-            timescale_update_messages: vec![],
-            series_completed_messages: vec![],
-            study_loading_messages: vec![],
-            study_completed_messages: vec![],
-            quote_completed_messages: vec![],
-            quote_last_price_messages: vec![],
-            study_data_update_messages: vec![],
-            series_data_update_messages: vec![],
-        };
+        // prepare dispatcher, the server hello waiter has to exist before the reader starts
+        let dispatcher = Arc::new(TradingViewMessageDispatcher::new(self.config.backlog_capacity()));
+        let server_hello_receiver = dispatcher.register(TradingViewCorrelationKey::new("server_hello")).await;
+        let reader_handle_dispatcher_ref = dispatcher.clone();
 
         // Spawn the reader task
-        let _reader_handle = executor.spawn(async move {
+        let reader_handle = executor.spawn(async move {
             loop {
                 match tv_reader.read_message().await {
                     Ok(result) => {
                         match result {
                             Some(message) => {
-                                // add message to buffer
-                                let mut write_lock = reader_handle_buffer_ref.write().await;
-                                write_lock.push(message);
-                                drop(write_lock);
+                                reader_handle_dispatcher_ref.dispatch(message).await;
                             },
                             None => {
                                 log::warn!("received none");
@@ -106,21 +348,368 @@ impl TradingViewClient {
                             }
                         }
                     },
-                    Err(err) => panic!("{err:?}"),
+                    Err(err) if !err.is_fatal() => {
+                        // a single bad message, the stream itself is still in sync
+                        log::error!("failed to parse message: {err}");
+                    },
+                    Err(err) => {
+                        log::error!("read failed: {err:?}");
+                        break;
+                    }
                 }
             }
+            reader_handle_dispatcher_ref.close().await;
         });
-        
+
+        Ok(TradingViewConnection {
+            writer: tv_writer,
+            dispatcher,
+            registry,
+            server_hello_receiver,
+            _reader_handle: reader_handle
+        })
+    }
+
+    async fn run_connection(&self, executor: Arc<Executor<'static>>, reconnect_state: &mut ReconnectState) -> anyhow::Result<TradingViewScrapeResult> {
+        let TradingViewConnection { writer: mut tv_writer, dispatcher, registry, server_hello_receiver, _reader_handle } = self.connect(executor).await?;
+        *self.dispatcher.write().await = Some(dispatcher.clone());
+        *self.registry.write().await = Some(registry);
+
+        // bar indexes start over on a new connection, and commands meant for the old one are stale
+        self.bar_series.write().await.clear();
+        self.study_joins.write().await.clear();
+        while self.command_receiver.try_recv().is_ok() {}
+
+        // replay every session on this connection
+        let scrape_result = self.setup_sessions(&mut tv_writer, &dispatcher, server_hello_receiver).await?;
+
+        // let the processor know a gap may exist
+        if let Some(disconnected_at) = reconnect_state.disconnected_at.take() {
+            let event = TradingViewClientEvent::Reconnected {
+                attempts: reconnect_state.attempts,
+                downtime: disconnected_at.elapsed()
+            };
+            self.message_processor.process_event(self.config.name.clone(), event).await;
+        }
+        reconnect_state.attempts = 0;
+
+        // exit if simple
+        match self.config.mode {
+            crate::TradingViewClientMode::Standard => {
+                let backlog_stats = dispatcher.stats();
+                if backlog_stats.len > 0 {
+                    log::warn!("closing with unmatched messages in backlog: {backlog_stats:?}");
+                }
+
+                // notices that came in during setup would go down with the backlog
+                while let Some(message) = dispatcher.try_next_message() {
+                    self.raise_notification(&message.parsed_message).await;
+                }
+
+                // close socket?
+                tv_writer.close().await?;
+
+                // return
+                return Ok(scrape_result);
+            },
+            _ => ()
+        }
+
+        // read all messages, checking every second for bars nobody ticked
+        let bar_close_delay = self.config.bar_close_delay_ms.map(Duration::from_millis);
+        let mut next_bar_close_check = Instant::now();
+        let mut reported_drops = 0;
+        loop {
+            let input = match bar_close_delay {
+                Some(bar_close_delay) => {
+                    if Instant::now() >= next_bar_close_check {
+                        self.close_expired_bars(bar_close_delay).await;
+                        next_bar_close_check = Instant::now() + Duration::from_secs(1);
+                    }
+                    let timeout = next_bar_close_check.saturating_duration_since(Instant::now());
+                    match utilities::run_with_timeout(timeout, Box::pin(self.next_live_input(&dispatcher))).await {
+                        Some(input) => input,
+                        None => continue
+                    }
+                },
+                None => self.next_live_input(&dispatcher).await
+            };
+            let result = match input {
+                TradingViewLiveInput::Command(command) => {
+                    log::debug!("[{}] sending {}", self.config.name, command.method());
+                    tv_writer.write_command(&command).await?;
+                    continue;
+                },
+                TradingViewLiveInput::Message(result) => result.map(|message| *message)
+            };
+            match result {
+                Some(message) => {
+                    // the backlog is the live feed, so anything it dropped is a gap the processor has to hear about
+                    self.report_dropped_messages(&dispatcher, &mut reported_drops).await;
+
+                    let parsed_message = message.parsed_message;
+                    match &parsed_message {
+                        ParsedTradingViewMessage::Ping(nonce) => {
+                            log::info!("ping nonce = {nonce}");
+                            tv_writer.pong(*nonce).await?;
+                        },
+                        _ => {
+                            // keep bar series and quotes current before the processor sees the raw delta
+                            self.apply_bar_updates(&parsed_message).await;
+                            self.apply_study_updates(&parsed_message).await;
+                            self.apply_quote_update(&parsed_message).await;
+                            self.raise_notification(&parsed_message).await;
+
+                            // send to message processor
+                            self.message_processor.process_message(self.config.name.clone(), parsed_message).await;
+                        }
+                    }
+                },
+                None => return Err(anyhow::anyhow!("connection closed"))
+            }
+        }
+    }
+
+    /// Tells the processor about backlog drops since the last call, `reported` is the running total it already knows about.
+    async fn report_dropped_messages(&self, dispatcher: &TradingViewMessageDispatcher, reported: &mut usize) {
+        let total = dispatcher.stats().dropped;
+        if total > *reported {
+            let event = TradingViewClientEvent::MessagesDropped {
+                dropped: total - *reported,
+                total
+            };
+            *reported = total;
+            self.message_processor.process_event(self.config.name.clone(), event).await;
+        }
+    }
+
+    /// Hands a `notify_user` to the processor as a notification.
+    async fn raise_notification(&self, parsed_message: &ParsedTradingViewMessage) {
+        if let ParsedTradingViewMessage::NotifyUser(message) = parsed_message {
+            self.message_processor.process_notification(self.config.name.clone(), message.clone()).await;
+        }
+    }
+
+    /// Waits for the next unmatched message or a command queued by the public API, whichever comes first.
+    async fn next_live_input(&self, dispatcher: &TradingViewMessageDispatcher) -> TradingViewLiveInput {
+        let message = async {
+            TradingViewLiveInput::Message(dispatcher.next_message().await.map(Box::new))
+        };
+        let command = async {
+            match self.command_receiver.recv().await {
+                Ok(command) => TradingViewLiveInput::Command(command),
+                // never happens, the client holds the sender
+                Err(_) => futures_lite::future::pending().await
+            }
+        };
+        futures_lite::future::or(message, command).await
+    }
+
+    /// Patches the tracked series with a `du` or `timescale_update` and tells the processor what changed.
+    async fn apply_bar_updates(&self, parsed_message: &ParsedTradingViewMessage) {
+        if let ParsedTradingViewMessage::TimescaleUpdate(message) = parsed_message {
+            self.renumber_chart_session(&message.chart_session_id, message.zoffset()).await;
+        }
+        let (chart_session_id, series_id) = match parsed_message {
+            ParsedTradingViewMessage::DataUpdate(message) if message.series_updates.is_some() => (&message.chart_session_id, &message.update_key),
+            ParsedTradingViewMessage::TimescaleUpdate(message) => match &message.update_key {
+                Some(update_key) => (&message.chart_session_id, update_key),
+                None => return
+            },
+            _ => return
+        };
+        let updates = {
+            let mut bar_series = self.bar_series.write().await;
+            let Some(bar_series) = bar_series.get_mut(&(chart_session_id.clone(), series_id.clone())) else {
+                return;
+            };
+            match parsed_message {
+                ParsedTradingViewMessage::DataUpdate(message) => bar_series.apply_data_update(message),
+                ParsedTradingViewMessage::TimescaleUpdate(message) => bar_series.apply_timescale_update(message),
+                _ => vec![]
+            }
+        };
+        for update in updates {
+            if let Some(closed) = &update.closed {
+                let event = TradingViewClientEvent::BarClosed {
+                    chart_session_id: chart_session_id.clone(),
+                    series_id: series_id.clone(),
+                    bar: closed.clone(),
+                    by_timer: false
+                };
+                self.message_processor.process_event(self.config.name.clone(), event).await;
+            }
+            let event = TradingViewClientEvent::BarUpdated {
+                chart_session_id: chart_session_id.clone(),
+                series_id: series_id.clone(),
+                update
+            };
+            self.message_processor.process_event(self.config.name.clone(), event).await;
+        }
+
+        // rows that arrived ahead of these bars can be stamped now
+        let released = {
+            let bar_series = self.bar_series.read().await;
+            let mut study_joins = self.study_joins.write().await;
+            let Some(bar_series) = bar_series.get(&(chart_session_id.clone(), series_id.clone())) else {
+                return;
+            };
+            study_joins.values_mut()
+                .filter(|study_join| &study_join.chart_session_id == chart_session_id && &study_join.series_id == series_id)
+                .map(|study_join| (study_join.study_id.clone(), study_join.apply_bars(bar_series)))
+                .filter(|(_, rows)| !rows.is_empty())
+                .collect::<Vec<_>>()
+        };
+        for (study_id, rows) in released {
+            self.emit_study_rows(chart_session_id, &study_id, rows).await;
+        }
+    }
+
+    /// Moves the bars and held back study rows of every series in the chart session `offset` indexes up.
+    async fn renumber_chart_session(&self, chart_session_id: &str, offset: i64) {
+        if offset == 0 {
+            return;
+        }
+        log::debug!("[{}] {chart_session_id} time scale moved by {offset}", self.config.name);
+        for bar_series in self.bar_series.write().await.values_mut().filter(|bar_series| bar_series.chart_session_id == chart_session_id) {
+            bar_series.renumber(offset);
+        }
+        for study_join in self.study_joins.write().await.values_mut().filter(|study_join| study_join.chart_session_id == chart_session_id) {
+            study_join.renumber(offset);
+        }
+    }
+
+    /// Joins the rows of a study `du` with the bars of its series and hands the stamped rows to the processor.
+    async fn apply_study_updates(&self, parsed_message: &ParsedTradingViewMessage) {
+        let ParsedTradingViewMessage::DataUpdate(message) = parsed_message else {
+            return;
+        };
+        let Some(study_rows) = &message.study_rows else {
+            return;
+        };
+        let rows = {
+            let bar_series = self.bar_series.read().await;
+            let mut study_joins = self.study_joins.write().await;
+            let Some(study_join) = study_joins.get_mut(&(message.chart_session_id.clone(), message.update_key.clone())) else {
+                return;
+            };
+            let Some(bar_series) = bar_series.get(&(study_join.chart_session_id.clone(), study_join.series_id.clone())) else {
+                return;
+            };
+            study_join.apply_rows(study_rows, bar_series)
+        };
+        if !rows.is_empty() {
+            self.emit_study_rows(&message.chart_session_id, &message.update_key, rows).await;
+        }
+    }
+
+    async fn emit_study_rows(&self, chart_session_id: &str, study_id: &str, rows: Vec<TimestampedStudyRow>) {
+        let event = TradingViewClientEvent::StudyUpdated {
+            chart_session_id: chart_session_id.to_string(),
+            study_id: study_id.to_string(),
+            rows
+        };
+        self.message_processor.process_event(self.config.name.clone(), event).await;
+    }
+
+    /// Merges a `qsd` into the quote book and tells the processor which fields changed.
+    async fn apply_quote_update(&self, parsed_message: &ParsedTradingViewMessage) {
+        let ParsedTradingViewMessage::QuoteSeriesData(message) = parsed_message else {
+            return;
+        };
+        let (changed_fields, snapshot) = {
+            let mut quote_book = self.quote_book.write().await;
+            let changed_fields = quote_book.apply(message);
+            (changed_fields, quote_book.get(&message.quote_update.symbol).cloned())
+        };
+        if let Some(snapshot) = snapshot {
+            if !changed_fields.is_empty() {
+                let event = TradingViewClientEvent::QuoteChanged {
+                    quote_session_id: message.quote_session_id.clone(),
+                    changed_fields,
+                    missing_fields: snapshot.fields.missing(&self.config.quote_fields()),
+                    snapshot
+                };
+                self.message_processor.process_event(self.config.name.clone(), event).await;
+            }
+        }
+    }
+
+    /// Requested quote fields the quote book has nothing for yet.
+    async fn missing_quote_fields(&self, symbol: &str) -> Vec<QuoteField> {
+        match self.quote_book.read().await.get(symbol) {
+            Some(snapshot) => snapshot.fields.missing(&self.config.quote_fields()),
+            None => self.config.quote_fields()
+        }
+    }
+
+    /// Updates the clock offset from a server hello and returns the decoded hello.
+    async fn record_server_hello(&self, message: &TradingViewMessageWrapper) -> anyhow::Result<ServerHelloMessage> {
+        let server_hello_message = message.parsed_message.as_server_hello().ok_or(anyhow::anyhow!("failed to cast"))?;
+        if let Some(offset_ms) = server_hello_message.clock_offset_ms(message.received_at) {
+            log::debug!("server clock offset = {offset_ms}ms");
+            *self.clock_offset_ms.write().await = Some(offset_ms);
+        }
+        Ok(server_hello_message.clone())
+    }
+
+    /// Closes the newest bar of every tracked series whose timeframe ended `delay` ago without a new bar.
+    async fn close_expired_bars(&self, delay: Duration) {
+        let Some(timeframe_seconds) = utilities::timeframe_seconds(&self.config.timeframe) else {
+            return;
+        };
+        // bars open on the server clock
+        let now = self.server_time().await.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs() as i64;
+        let closed = {
+            let mut bar_series = self.bar_series.write().await;
+            bar_series.values_mut()
+                .filter_map(|bar_series| {
+                    let bar = bar_series.close_expired(now, timeframe_seconds as i64, delay.as_secs() as i64)?;
+                    Some((bar_series.chart_session_id.clone(), bar_series.series_id.clone(), bar))
+                })
+                .collect::<Vec<_>>()
+        };
+        for (chart_session_id, series_id, bar) in closed {
+            let event = TradingViewClientEvent::BarClosed {
+                chart_session_id,
+                series_id,
+                bar,
+                by_timer: true
+            };
+            self.message_processor.process_event(self.config.name.clone(), event).await;
+        }
+    }
+
+    async fn setup_sessions<W>(&self, tv_writer: &mut TradingViewWriter<W>, dispatcher: &TradingViewMessageDispatcher, server_hello_receiver: Receiver<TradingViewMessageWrapper>) -> anyhow::Result<TradingViewScrapeResult>
+    where
+        W: AsyncWrite + Unpin,
+    {
+        // scrape result
+        let mut scrape_result = TradingViewScrapeResult {
+            server_hello_messages: vec![],
+            symbol_resolved_messages: vec![],
+            series_loading_messages: vec![],
+            timescale_update_messages: vec![],
+            series_completed_messages: vec![],
+            study_loading_messages: vec![],
+            study_completed_messages: vec![],
+            quote_completed_messages: vec![],
+            quote_last_price_messages: vec![],
+            quote_missing_fields: vec![],
+            study_data_update_messages: vec![],
+            series_data_update_messages: vec![],
+        };
+
         // Wait for server hello message with timeout
-        let server_hello_message = utilities::run_with_timeout(Duration::from_secs(1), Box::pin(utilities::wait_for_message(buffer_arc.clone(), |message| message.payload.contains("javastudies"))))
-            .await
-            .ok_or(anyhow::anyhow!("timed out"))?
-            .ok_or(anyhow::anyhow!("failed to get server hello message"))?;
-        let server_hello_message = server_hello_message.parsed_message.as_server_hello().ok_or(anyhow::anyhow!("failed to cast"))?;
+        let server_hello_message = wait_for_reply(&server_hello_receiver, &[], Duration::from_secs(1), "failed to get server hello message").await?;
+        let server_hello_message = self.record_server_hello(&server_hello_message).await?;
         log::info!("server_hello_message = {server_hello_message:?}");
-        scrape_result.server_hello_messages.push(server_hello_message.clone());
+        scrape_result.server_hello_messages.push(server_hello_message);
 
-        // set auth token
+        // set auth token, a rejection comes back as critical_error or protocol_error
+        let critical_error_receiver = dispatcher.register(TradingViewCorrelationKey::new("critical_error")).await;
+        let protocol_error_receiver = dispatcher.register(TradingViewCorrelationKey::new("protocol_error")).await;
+        let error_receivers = [&critical_error_receiver, &protocol_error_receiver];
         tv_writer.set_auth_token(&self.config.auth_token).await?;
         
         // set locale
@@ -137,47 +726,47 @@ impl TradingViewClient {
 
             // resolve symbol
             let symbol_id = "sds_sym_1";
+            let symbol_resolved_receiver = dispatcher.register(TradingViewCorrelationKey::for_object("symbol_resolved", &chart_session_id, symbol_id)).await;
             tv_writer.resolve_symbol(&chart_session_id, symbol_id, &chart_symbol).await?;
 
             // wait for symbol resolved message
-            let symbol_resolved_message = utilities::run_with_timeout(Duration::from_secs(2), Box::pin(utilities::wait_for_message(buffer_arc.clone(), |message| message.payload.contains("symbol_resolved"))))
-                .await
-                .ok_or(anyhow::anyhow!("timed out"))?
-                .ok_or(anyhow::anyhow!("failed to get symbol resolved message"))?;
+            let symbol_resolved_message = wait_for_reply(&symbol_resolved_receiver, &error_receivers, Duration::from_secs(2), "failed to get symbol resolved message").await?;
             let symbol_resolved_message = symbol_resolved_message.parsed_message.as_symbol_resolved().ok_or(anyhow::anyhow!("failed to cast"))?;
             log::info!("symbol_resolved_message = {symbol_resolved_message:?}");
             scrape_result.symbol_resolved_messages.push(symbol_resolved_message.clone());
 
+            // the waiter consumed it, hand the symbol info to the processor as well
+            self.message_processor.process_message(self.config.name.clone(), ParsedTradingViewMessage::SymbolResolved(symbol_resolved_message.clone())).await;
+
             // add symbol to chart session as series
             let series_id = "sds_1";
+            let series_loading_receiver = dispatcher.register(TradingViewCorrelationKey::for_object("series_loading", &chart_session_id, series_id)).await;
+            let timescale_update_receiver = dispatcher.register(TradingViewCorrelationKey::for_object("timescale_update", &chart_session_id, series_id)).await;
+            let series_completed_receiver = dispatcher.register(TradingViewCorrelationKey::for_object("series_completed", &chart_session_id, series_id)).await;
             tv_writer.create_series(&chart_session_id, series_id, "s1",  symbol_id, &self.config.timeframe, self.config.range).await?;
 
             // switch chart timezone
             tv_writer.switch_timezone(&chart_session_id, "exchange").await?;
 
             // wait for series loading message
-            let series_loading_message = utilities::run_with_timeout(Duration::from_secs(2), Box::pin(utilities::wait_for_message(buffer_arc.clone(), |message| message.payload.contains("series_loading"))))
-                .await
-                .ok_or(anyhow::anyhow!("timed out"))?
-                .ok_or(anyhow::anyhow!("failed to get series loading message"))?;
+            let series_loading_message = wait_for_reply(&series_loading_receiver, &error_receivers, Duration::from_secs(2), "failed to get series loading message").await?;
             log::info!("series_loading_message = {series_loading_message:?}");
             let series_loading_message = series_loading_message.parsed_message.as_series_loading().ok_or(anyhow::anyhow!("failed to cast"))?;
             scrape_result.series_loading_messages.push(series_loading_message.clone());
 
             // wait for timescale update message
-            let timescale_update_message = utilities::run_with_timeout(Duration::from_secs(5), Box::pin(utilities::wait_for_message(buffer_arc.clone(), |message| message.payload.contains("timescale_update"))))
-                .await
-                .ok_or(anyhow::anyhow!("timed out"))?
-                .ok_or(anyhow::anyhow!("failed to get timesale update message"))?;
+            let timescale_update_message = wait_for_reply(&timescale_update_receiver, &error_receivers, Duration::from_secs(5), "failed to get timescale update message").await?;
             log::info!("timescale_update_message = {timescale_update_message:?}");
             let timescale_update_message = timescale_update_message.parsed_message.as_timescale_update().ok_or(anyhow::anyhow!("failed to cast"))?;
             scrape_result.timescale_update_messages.push(timescale_update_message.clone());
 
+            // the first timescale_update is the snapshot the series starts from
+            let mut bar_series = BarSeries::new(&chart_session_id, series_id);
+            bar_series.apply_timescale_update(timescale_update_message);
+            self.bar_series.write().await.insert((chart_session_id.clone(), series_id.to_string()), bar_series);
+
             // wait for series completed message
-            let series_completed_message = utilities::run_with_timeout(Duration::from_secs(2), Box::pin(utilities::wait_for_message(buffer_arc.clone(), |message| message.payload.contains("series_completed"))))
-                .await
-                .ok_or(anyhow::anyhow!("timed out"))?
-                .ok_or(anyhow::anyhow!("failed to get series completed message"))?;
+            let series_completed_message = wait_for_reply(&series_completed_receiver, &error_receivers, Duration::from_secs(2), "failed to get series completed message").await?;
             log::info!("series_completed_message = {series_completed_message:?}");
             let series_completed_message = series_completed_message.parsed_message.as_series_completed().ok_or(anyhow::anyhow!("failed to cast"))?;
             scrape_result.series_completed_messages.push(series_completed_message.clone());
@@ -185,71 +774,74 @@ impl TradingViewClient {
             // optionally create study session
             if self.config.indicators.len() > 0 {
                 let study_session_id = "st1";
+                let study_loading_receiver = dispatcher.register(TradingViewCorrelationKey::for_object("study_loading", &chart_session_id, study_session_id)).await;
+                let study_completed_receiver = dispatcher.register(TradingViewCorrelationKey::for_object("study_completed", &chart_session_id, study_session_id)).await;
+                let study_error_receiver = dispatcher.register(TradingViewCorrelationKey::for_object("study_error", &chart_session_id, study_session_id)).await;
+                let study_error_receivers = [&critical_error_receiver, &protocol_error_receiver, &study_error_receiver];
                 tv_writer.create_study(&chart_session_id, study_session_id, "sessions_1", series_id, "Sessions@tv-basicstudies-241", "{}").await?;
 
                 // wait for study loading message
-                let study_loading_message = utilities::run_with_timeout(Duration::from_secs(2), Box::pin(utilities::wait_for_message(buffer_arc.clone(), |message| message.payload.contains("study_loading"))))
-                    .await
-                    .ok_or(anyhow::anyhow!("timed out"))?
-                    .ok_or(anyhow::anyhow!("failed to get study loading message"))?;
+                let study_loading_message = wait_for_reply(&study_loading_receiver, &study_error_receivers, Duration::from_secs(2), "failed to get study loading message").await?;
                 let study_loading_message = study_loading_message.parsed_message.as_study_loading().ok_or(anyhow::anyhow!("failed to cast"))?;
                 log::info!("study_loading_message = {study_loading_message:?}");
                 scrape_result.study_loading_messages.push(study_loading_message.clone());
 
                 // wait for study completed message
-                let study_completed_message = utilities::run_with_timeout(Duration::from_secs(5), Box::pin(utilities::wait_for_message(buffer_arc.clone(), |message| message.payload.contains("study_completed"))))
-                    .await
-                    .ok_or(anyhow::anyhow!("timed out"))?
-                    .ok_or(anyhow::anyhow!("failed to get study completed message"))?;
+                let study_completed_message = wait_for_reply(&study_completed_receiver, &study_error_receivers, Duration::from_secs(5), "failed to get study completed message").await?;
                 let study_completed_message = study_completed_message.parsed_message.as_study_completed().ok_or(anyhow::anyhow!("failed to cast"))?;
                 log::info!("study_completed_message = {study_completed_message:?}");
                 scrape_result.study_completed_messages.push(study_completed_message.clone());
 
                 let mut index = 2;
-                for indciator in &self.config.indicators {
-                    let study_value = indciator;
+                for indicator in &self.config.indicators {
                     let study_id = format!("st{index}");
-                    tv_writer.create_study(&chart_session_id, &study_id, study_session_id, series_id, "Script@tv-scripting-101!", study_value).await?;
+                    let study_loading_receiver = dispatcher.register(TradingViewCorrelationKey::for_object("study_loading", &chart_session_id, &study_id)).await;
+                    let study_completed_receiver = dispatcher.register(TradingViewCorrelationKey::for_object("study_completed", &chart_session_id, &study_id)).await;
+                    let study_error_receiver = dispatcher.register(TradingViewCorrelationKey::for_object("study_error", &chart_session_id, &study_id)).await;
+                    let study_error_receivers = [&critical_error_receiver, &protocol_error_receiver, &study_error_receiver];
+                    let study_data_update_receiver = dispatcher.register_with(TradingViewCorrelationKey::for_object("du", &chart_session_id, &study_id), |message| {
+                        match &message.parsed_message {
+                            ParsedTradingViewMessage::DataUpdate(data_update_message) => {
+                                match &data_update_message.study_updates {
+                                    Some(study_updates) => {
+                                        return study_updates.len() > 0
+                                    },
+                                    None => return false
+                                }
+                            },
+                            _ => false
+                        }
+                    }).await;
+                    tv_writer.create_indicator_study(&chart_session_id, &study_id, study_session_id, series_id, indicator).await?;
+                    self.study_joins.write().await.insert((chart_session_id.clone(), study_id.clone()), StudyBarJoin::new(&chart_session_id, &study_id, series_id));
                     index += 1;
 
                     // wait for study loading message
-                    let study_loading_message = utilities::run_with_timeout(Duration::from_secs(2), Box::pin(utilities::wait_for_message(buffer_arc.clone(), |message| message.payload.contains("study_loading"))))
-                        .await
-                        .ok_or(anyhow::anyhow!("timed out"))?
-                        .ok_or(anyhow::anyhow!("failed to get study loading message"))?;
+                    let study_loading_message = wait_for_reply(&study_loading_receiver, &study_error_receivers, Duration::from_secs(2), "failed to get study loading message").await?;
                     let study_loading_message = study_loading_message.parsed_message.as_study_loading().ok_or(anyhow::anyhow!("failed to cast"))?;
                     log::info!("study_loading_message = {study_loading_message:?}");
                     scrape_result.study_loading_messages.push(study_loading_message.clone());
 
                     // wait for study completed message
-                    let study_completed_message = utilities::run_with_timeout(Duration::from_secs(5), Box::pin(utilities::wait_for_message(buffer_arc.clone(), |message| message.payload.contains("study_completed"))))
-                        .await
-                        .ok_or(anyhow::anyhow!("timed out"))?
-                        .ok_or(anyhow::anyhow!("failed to get study completed message"))?;
+                    let study_completed_message = wait_for_reply(&study_completed_receiver, &study_error_receivers, Duration::from_secs(5), "failed to get study completed message").await?;
                     let study_completed_message = study_completed_message.parsed_message.as_study_completed().ok_or(anyhow::anyhow!("failed to cast"))?;
                     log::info!("study_completed_message = {study_completed_message:?}");
                     scrape_result.study_completed_messages.push(study_completed_message.clone());
 
                     // wait for study data update
-                    let study_data_update_message = utilities::run_with_timeout(Duration::from_secs(5), Box::pin(utilities::wait_for_message(buffer_arc.clone(), |message| {
-                        match &message.parsed_message {
-                            ParsedTradingViewMessage::DataUpdate(data_update_message) => {
-                                match &data_update_message.study_updates {
-                                    Some(study_updates) => {
-                                        return study_updates.len() > 0
-                                    },
-                                    None => return false
-                                }
-                            },
-                            _ => false
-                        }
-                    })))
-                    .await
-                    .ok_or(anyhow::anyhow!("timed out"))?
-                    .ok_or(anyhow::anyhow!("failed to get series data update"))?;
+                    let study_data_update_message = wait_for_reply(&study_data_update_receiver, &study_error_receivers, Duration::from_secs(5), "failed to get study data update").await?;
                     let study_data_update_message = study_data_update_message.parsed_message.as_data_update().ok_or(anyhow::anyhow!("failed to cast"))?;
                     log::info!("study_data_update_message = {study_data_update_message:?}");
                     scrape_result.study_data_update_messages.push(study_data_update_message.clone());
+
+                    // like the bar series snapshot, the first rows are joined without telling the processor
+                    if let Some(study_rows) = &study_data_update_message.study_rows {
+                        let bar_series = self.bar_series.read().await;
+                        let mut study_joins = self.study_joins.write().await;
+                        if let (Some(bar_series), Some(study_join)) = (bar_series.get(&(chart_session_id.clone(), series_id.to_string())), study_joins.get_mut(&(chart_session_id.clone(), study_id.clone()))) {
+                            study_join.apply_rows(study_rows, bar_series);
+                        }
+                    }
                 }
             }
 
@@ -265,85 +857,60 @@ impl TradingViewClient {
             tv_writer.quote_create_session(&quote_session_id).await?;
 
             // set quote session fields
-            tv_writer.quote_set_fields(&quote_session_id).await?;
+            let quote_fields = self.config.quote_fields();
+            tv_writer.quote_set_fields(&quote_session_id, &quote_fields).await?;
 
             // add symbol to quote session
+            let quote_completed_receiver = dispatcher.register(TradingViewCorrelationKey::for_object("quote_completed", &quote_session_id, quote_symbol)).await;
+            // presets like fundamentals_fields have neither lp nor rtc, so there is no last price to wait for
+            let waits_for_last_price = quote_fields.iter().any(|field| matches!(field, QuoteField::Lp | QuoteField::Rtc));
+            // without it the first qsd stays on the backlog for the quote book and the processor
+            let quote_last_price_receiver = if waits_for_last_price {
+                Some(dispatcher.register_with(TradingViewCorrelationKey::for_object("qsd", &quote_session_id, quote_symbol), |message| {
+                    match &message.parsed_message {
+                        ParsedTradingViewMessage::QuoteSeriesData(quote_series_data_message) => {
+                            quote_series_data_message.quote_update.rtc.is_some() || quote_series_data_message.quote_update.lp.is_some()
+                        },
+                        _ => false
+                    }
+                }).await)
+            } else {
+                None
+            };
             tv_writer.quote_add_symbols(&quote_session_id, &quote_symbol).await?;
 
             // turn on quote fast symbols for quote session
             tv_writer.quote_fast_symbols(&quote_session_id, &quote_symbol).await?;
 
             // wait for quote completed message
-            let quote_completed_message = utilities::run_with_timeout(Duration::from_secs(1), Box::pin(utilities::wait_for_message(buffer_arc.clone(), |message| message.payload.contains("quote_completed"))))
-                .await
-                .ok_or(anyhow::anyhow!("timed out"))?
-                .ok_or(anyhow::anyhow!("failed to get quote completed message"))?;
+            let quote_completed_message = wait_for_reply(&quote_completed_receiver, &error_receivers, Duration::from_secs(1), "failed to get quote completed message").await?;
             let quote_completed_message = quote_completed_message.parsed_message.as_quote_completed().ok_or(anyhow::anyhow!("failed to cast"))?;
             log::info!("quote_completed_message = {quote_completed_message:?}");
             scrape_result.quote_completed_messages.push(quote_completed_message.clone());
 
             // wait for quote last price
-            let quote_last_price_message = utilities::run_with_timeout(Duration::from_secs(1), Box::pin(utilities::wait_for_message(buffer_arc.clone(), |message| {
-                    match &message.parsed_message {
-                        ParsedTradingViewMessage::QuoteSeriesData(quote_series_data_message) => {
-                            quote_series_data_message.quote_update.rtc.is_some() || quote_series_data_message.quote_update.lp.is_some()
-                        },
-                        _ => false
-                    }
-                })))
-                .await
-                .ok_or(anyhow::anyhow!("timed out"))?
-                .ok_or(anyhow::anyhow!("failed to get quote last price message"))?;
-            let quote_last_price_message = quote_last_price_message.parsed_message.as_quote_series_data().ok_or(anyhow::anyhow!("failed to cast"))?;
-            log::info!("quote_last_price_message = {quote_last_price_message:?}");
-            scrape_result.quote_last_price_messages.push(quote_last_price_message.clone());
+            if let Some(quote_last_price_receiver) = &quote_last_price_receiver {
+                let quote_last_price_message = wait_for_reply(quote_last_price_receiver, &error_receivers, Duration::from_secs(1), "failed to get quote last price message").await?;
+                let quote_last_price_message = quote_last_price_message.parsed_message.as_quote_series_data().ok_or(anyhow::anyhow!("failed to cast"))?;
+                log::info!("quote_last_price_message = {quote_last_price_message:?}");
+                scrape_result.quote_last_price_messages.push(quote_last_price_message.clone());
+                self.quote_book.write().await.apply(quote_last_price_message);
+            }
+
+            // report the requested fields that haven't arrived yet
+            let missing_fields = self.missing_quote_fields(quote_symbol).await;
+            if !missing_fields.is_empty() {
+                scrape_result.quote_missing_fields.push(QuoteMissingFields {
+                    quote_session_id: quote_session_id.clone(),
+                    symbol: quote_symbol.clone(),
+                    fields: missing_fields,
+                });
+            }
 
             // increment index
             index += 1;
         }
 
-        // request more data from series?
-        /*for _ in 0..20 {
-            tv_writer.request_more_data(chart_session_id1, series_id, 1000).await?;
-
-            // TODO: wait for individual sries_loading / study_loading / study_completed messages
-
-            async_io::Timer::after(Duration::from_secs(1)).await;
-        }*/
-
-        // exit if simple
-        match self.config.mode {
-            crate::TradingViewClientMode::Standard => {
-                // TODO: make sure buffer is empty (no missed message extraction)
-
-                // close socket?
-                tv_writer.close().await?;
-
-                // return
-                return Ok(scrape_result);
-            },
-            _ => ()
-        }
-
-        // read all messages
-        loop {
-            let result = utilities::wait_for_message(buffer_arc.clone(), |_| true).await;
-            match result {
-                Some(message) => {
-                    let parsed_message = ParsedTradingViewMessage::from_string(&message.payload)?;
-                    match &parsed_message {
-                        ParsedTradingViewMessage::Ping(nonce) => {
-                            log::info!("ping nonce = {nonce}");
-                            tv_writer.pong(*nonce).await?;
-                        },
-                        _ => {
-                            // send to message processor
-                            self.message_processor.process_message(self.config.name.clone(), parsed_message).await;
-                        }
-                    }
-                },
-                None => panic!("closed")
-            }
-        }
+        Ok(scrape_result)
     }
 }
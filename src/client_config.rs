@@ -1,9 +1,24 @@
+use std::collections::BTreeMap;
 use std::sync::Arc;
+use std::time::Duration;
 
 use miniserde::Deserialize;
 
 use crate::message_processor::TradingViewMessageProcessor;
 use crate::client::TradingViewClient;
+use crate::quote_field::QuoteField;
+use crate::indicators::TradingViewIndicator;
+
+/// Websocket endpoint the browser chart connects to.
+pub static TRADINGVIEW_DATA_URI: &str = "wss://data.tradingview.com/socket.io/websocket?type=chart";
+/// Endpoint for accounts with a paid plan.
+pub static TRADINGVIEW_PRODATA_URI: &str = "wss://prodata.tradingview.com/socket.io/websocket?type=chart";
+/// Endpoint embedded widgets connect to.
+pub static TRADINGVIEW_WIDGETDATA_URI: &str = "wss://widgetdata.tradingview.com/socket.io/websocket?type=chart";
+pub static DEFAULT_USER_AGENT: &str = "Mozilla/5.0 (Macintosh; Intel Mac OS X 10_15_7) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/129.0.0.0 Safari/537.36";
+pub static DEFAULT_ORIGIN: &str = "https://www.tradingview.com";
+/// Backlog size when the config doesn't set one.
+pub const DEFAULT_BACKLOG_CAPACITY: usize = 1024;
 
 #[derive(Deserialize, Clone)]
 pub enum TradingViewClientMode {
@@ -11,20 +26,119 @@ pub enum TradingViewClientMode {
     Streaming
 }
 
+/// How `TradingViewClient::run` retries a connection that failed, dropped or timed out during setup. This applies to
+/// `Standard` mode too: with the defaults an unreachable server is retried 10 times with backoff growing to 60 s, so a
+/// scrape can take around 5 minutes to give up. Set `max_attempts` to 0 to fail on the first error instead.
+#[derive(Deserialize, Clone)]
+pub struct TradingViewReconnectConfig {
+    /// Maximum number of consecutive reconnect attempts, 0 disables reconnecting
+    pub max_attempts: usize,
+    pub initial_backoff_ms: u64,
+    pub max_backoff_ms: u64,
+}
+
+impl Default for TradingViewReconnectConfig {
+    fn default() -> Self {
+        Self {
+            max_attempts: 10,
+            initial_backoff_ms: 1000,
+            max_backoff_ms: 60000,
+        }
+    }
+}
+
+#[derive(Deserialize, Clone)]
+pub struct TradingViewEndpointConfig {
+    /// Websocket endpoint, `wss://` for TradingView or `ws://` for a local stand-in
+    pub uri: String,
+    pub user_agent: String,
+    pub origin: String,
+    /// Sent on the upgrade request after the defaults, replacing any header with the same name
+    pub headers: BTreeMap<String, String>,
+}
+
+impl Default for TradingViewEndpointConfig {
+    fn default() -> Self {
+        Self::data()
+    }
+}
+
+impl TradingViewEndpointConfig {
+    /// Any endpoint with the browser user agent and origin.
+    pub fn new(uri: &str) -> Self {
+        Self {
+            uri: uri.to_string(),
+            user_agent: DEFAULT_USER_AGENT.to_string(),
+            origin: DEFAULT_ORIGIN.to_string(),
+            headers: BTreeMap::new(),
+        }
+    }
+
+    pub fn data() -> Self {
+        Self::new(TRADINGVIEW_DATA_URI)
+    }
+
+    pub fn prodata() -> Self {
+        Self::new(TRADINGVIEW_PRODATA_URI)
+    }
+
+    pub fn widgetdata() -> Self {
+        Self::new(TRADINGVIEW_WIDGETDATA_URI)
+    }
+}
+
+impl TradingViewReconnectConfig {
+    /// Exponential backoff for the given (1-based) attempt, capped at `max_backoff_ms`.
+    pub fn backoff(&self, attempt: usize) -> Duration {
+        let exponent = attempt.saturating_sub(1).min(16) as u32;
+        let backoff_ms = self.initial_backoff_ms.saturating_mul(2u64.pow(exponent));
+        Duration::from_millis(backoff_ms.min(self.max_backoff_ms))
+    }
+}
+
 #[derive(Deserialize, Clone)]
 pub struct TradingViewClientConfig {
     pub name: String,
+    /// Defaults to `TradingViewEndpointConfig::data()`
+    pub endpoint: Option<TradingViewEndpointConfig>,
     pub auth_token: String,
     pub chart_symbols: Vec<String>,
     pub quote_symbols: Vec<String>,
-    pub indicators: Vec<String>,
+    /// Fields requested with `quote_set_fields`, see `QuoteField::default_fields` (the default) and the other presets
+    pub quote_fields: Option<Vec<QuoteField>>,
+    pub indicators: Vec<TradingViewIndicator>,
     pub timeframe: String,
     pub range: usize,
-    pub mode: TradingViewClientMode
+    pub mode: TradingViewClientMode,
+    /// Maximum number of unmatched messages held between reads, the oldest are dropped beyond this and a streaming client
+    /// reports them with `TradingViewClientEvent::MessagesDropped`. Defaults to `DEFAULT_BACKLOG_CAPACITY`
+    pub backlog_capacity: Option<usize>,
+    /// Defaults to `TradingViewReconnectConfig::default()`
+    pub reconnect: Option<TradingViewReconnectConfig>,
+    /// When set, a bar with no successor is reported closed this long after its timeframe ends
+    pub bar_close_delay_ms: Option<u64>
 }
 
 impl TradingViewClientConfig {
     pub fn to_client(&self, message_processor: Arc<Box<dyn TradingViewMessageProcessor + Send + Sync>>) -> TradingViewClient {
         TradingViewClient::new(self.clone(), message_processor)
     }
+
+    /// The configured endpoint, or the default one.
+    pub fn endpoint(&self) -> TradingViewEndpointConfig {
+        self.endpoint.clone().unwrap_or_default()
+    }
+
+    /// The configured quote fields, or `QuoteField::default_fields()`.
+    pub fn quote_fields(&self) -> Vec<QuoteField> {
+        self.quote_fields.clone().unwrap_or_else(QuoteField::default_fields)
+    }
+
+    pub fn backlog_capacity(&self) -> usize {
+        self.backlog_capacity.unwrap_or(DEFAULT_BACKLOG_CAPACITY)
+    }
+
+    pub fn reconnect(&self) -> TradingViewReconnectConfig {
+        self.reconnect.clone().unwrap_or_default()
+    }
 }
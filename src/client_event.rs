@@ -0,0 +1,48 @@
+use std::time::Duration;
+
+use crate::bar_series::{Bar, BarSeriesUpdate};
+use crate::quote_book::QuoteSnapshot;
+use crate::quote_field::QuoteField;
+use crate::study_join::TimestampedStudyRow;
+
+#[derive(Debug, Clone)]
+pub enum TradingViewClientEvent {
+    /// The connection dropped and every session was replayed on a fresh socket; messages may have been missed in between.
+    Reconnected {
+        attempts: usize,
+        downtime: Duration,
+    },
+    /// The backlog overflowed while streaming and its oldest unread messages were dropped, so bars, studies and quotes may
+    /// have missed updates. `total` counts every drop on this connection.
+    MessagesDropped {
+        dropped: usize,
+        total: usize,
+    },
+    /// A tracked series changed while streaming, either the forming bar ticking or a new bar opening.
+    BarUpdated {
+        chart_session_id: String,
+        series_id: String,
+        update: BarSeriesUpdate,
+    },
+    /// A bar of a tracked series is final, either because the next bar opened or because its timeframe ran out (`by_timer`).
+    BarClosed {
+        chart_session_id: String,
+        series_id: String,
+        bar: Bar,
+        by_timer: bool,
+    },
+    /// Rows of a study paired with the bars they belong to, either fresh from a `du` or held back until their bar arrived.
+    StudyUpdated {
+        chart_session_id: String,
+        study_id: String,
+        rows: Vec<TimestampedStudyRow>,
+    },
+    /// A `qsd` changed the quote of a symbol, `snapshot` is the merged quote after the update.
+    QuoteChanged {
+        quote_session_id: String,
+        changed_fields: Vec<QuoteField>,
+        /// Requested fields the server still hasn't sent for this symbol.
+        missing_fields: Vec<QuoteField>,
+        snapshot: QuoteSnapshot,
+    },
+}
@@ -0,0 +1,320 @@
+use miniserde::json::{Array, Number, Object, Value};
+
+use crate::error::{TradingViewError, TradingViewResult};
+use crate::json_utilities;
+use crate::quote_field::QuoteField;
+
+/// Every message the client sends, one variant per `TradingViewWriter` method.
+#[derive(Debug, Clone)]
+pub enum TradingViewCommand {
+    SetAuthToken {
+        auth_token: String,
+    },
+    SetLocale {
+        language_code: String,
+        region_code: String,
+    },
+    ChartCreateSession {
+        chart_session_id: String,
+    },
+    SwitchTimezone {
+        chart_session_id: String,
+        timezone: String,
+    },
+    QuoteCreateSession {
+        quote_session_id: String,
+    },
+    QuoteAddSymbols {
+        quote_session_id: String,
+        symbol: String,
+    },
+    ResolveSymbol {
+        chart_session_id: String,
+        symbol_id: String,
+        symbol: String,
+    },
+    CreateSeries {
+        chart_session_id: String,
+        series_id: String,
+        unk1: String,
+        symbol_id: String,
+        timeframe: String,
+        range: usize,
+    },
+    RequestMoreTickmarks {
+        chart_session_id: String,
+        series_id: String,
+        range: usize,
+    },
+    RequestMoreData {
+        chart_session_id: String,
+        series_id: String,
+        amount: usize,
+    },
+    QuoteFastSymbols {
+        quote_session_id: String,
+        symbol: String,
+    },
+    QuoteSetFields {
+        quote_session_id: String,
+        fields: Vec<QuoteField>,
+    },
+    CreateStudy {
+        chart_session_id: String,
+        study_id: String,
+        session_id: String,
+        series_id: String,
+        name: String,
+        value: Value,
+    },
+    Pong {
+        nonce: usize,
+    },
+}
+
+fn string(value: &str) -> Value {
+    Value::String(value.to_string())
+}
+
+fn number(value: usize) -> Value {
+    Value::Number(Number::U64(value as u64))
+}
+
+fn param_string(p: &Array, index: usize) -> TradingViewResult<String> {
+    json_utilities::value_to_string(json_utilities::get_index(p, index)?)
+}
+
+fn param_usize(p: &Array, index: usize) -> TradingViewResult<usize> {
+    let value = json_utilities::value_to_number(json_utilities::get_index(p, index)?)?;
+    match value {
+        Number::U64(value) => Ok(value as usize),
+        _ => Err(TradingViewError::Schema(format!("p[{index}] is not an unsigned integer")))
+    }
+}
+
+impl TradingViewCommand {
+    /// The `m` property of the command.
+    pub fn method(&self) -> &'static str {
+        match self {
+            TradingViewCommand::SetAuthToken { .. } => "set_auth_token",
+            TradingViewCommand::SetLocale { .. } => "set_locale",
+            TradingViewCommand::ChartCreateSession { .. } => "chart_create_session",
+            TradingViewCommand::SwitchTimezone { .. } => "switch_timezone",
+            TradingViewCommand::QuoteCreateSession { .. } => "quote_create_session",
+            TradingViewCommand::QuoteAddSymbols { .. } => "quote_add_symbols",
+            TradingViewCommand::ResolveSymbol { .. } => "resolve_symbol",
+            TradingViewCommand::CreateSeries { .. } => "create_series",
+            TradingViewCommand::RequestMoreTickmarks { .. } => "request_more_tickmarks",
+            TradingViewCommand::RequestMoreData { .. } => "request_more_data",
+            TradingViewCommand::QuoteFastSymbols { .. } => "quote_fast_symbols",
+            TradingViewCommand::QuoteSetFields { .. } => "quote_set_fields",
+            TradingViewCommand::CreateStudy { .. } => "create_study",
+            TradingViewCommand::Pong { .. } => "pong",
+        }
+    }
+
+    fn params(&self) -> Vec<Value> {
+        match self {
+            TradingViewCommand::SetAuthToken { auth_token } => vec![string(auth_token)],
+            TradingViewCommand::SetLocale { language_code, region_code } => vec![string(language_code), string(region_code)],
+            TradingViewCommand::ChartCreateSession { chart_session_id } => vec![string(chart_session_id), string("")],
+            TradingViewCommand::SwitchTimezone { chart_session_id, timezone } => vec![string(chart_session_id), string(timezone)],
+            TradingViewCommand::QuoteCreateSession { quote_session_id } => vec![string(quote_session_id), string("")],
+            TradingViewCommand::QuoteAddSymbols { quote_session_id, symbol } => vec![string(quote_session_id), string(symbol)],
+            TradingViewCommand::ResolveSymbol { chart_session_id, symbol_id, symbol } => vec![string(chart_session_id), string(symbol_id), string(symbol)],
+            TradingViewCommand::CreateSeries { chart_session_id, series_id, unk1, symbol_id, timeframe, range } => {
+                // ~m~81~m~{"m":"create_series","p":["cs_000000000001","sds_1","s1","sds_sym_1","5",300,""]}
+                vec![string(chart_session_id), string(series_id), string(unk1), string(symbol_id), string(timeframe), number(*range), string("")]
+            },
+            TradingViewCommand::RequestMoreTickmarks { chart_session_id, series_id, range } => vec![string(chart_session_id), string(series_id), number(*range)],
+            TradingViewCommand::RequestMoreData { chart_session_id, series_id, amount } => vec![string(chart_session_id), string(series_id), number(*amount)],
+            TradingViewCommand::QuoteFastSymbols { quote_session_id, symbol } => vec![string(quote_session_id), string(symbol)],
+            TradingViewCommand::QuoteSetFields { quote_session_id, fields } => {
+                let mut params = vec![string(quote_session_id)];
+                params.extend(fields.iter().map(|field| string(field.name())));
+                params
+            },
+            TradingViewCommand::CreateStudy { chart_session_id, study_id, session_id, series_id, name, value } => {
+                // ~m~105~m~{"m":"create_study","p":["cs_L2mu7VPJpvcr","st1","sessions_1","sds_1","Sessions@tv-basicstudies-241",{}]}
+                vec![string(chart_session_id), string(study_id), string(session_id), string(series_id), string(name), value.clone()]
+            },
+            TradingViewCommand::Pong { nonce } => vec![number(*nonce)],
+        }
+    }
+
+    /// Encodes the command as the payload that goes inside the `~m~len~m~` wrapper.
+    pub fn to_message(&self) -> String {
+        if let TradingViewCommand::Pong { nonce } = self {
+            // heartbeats are not json
+            return format!("~h~{nonce}");
+        }
+        let mut p = Array::new();
+        p.extend(self.params());
+        let mut message = Object::new();
+        message.insert("m".to_string(), string(self.method()));
+        message.insert("p".to_string(), Value::Array(p));
+        miniserde::json::to_string(&message)
+    }
+
+    /// Decodes a payload produced by `to_message`.
+    pub fn from_message(value: &str) -> TradingViewResult<Self> {
+        if let Some(nonce) = value.strip_prefix("~h~") {
+            let nonce = nonce.parse::<usize>().map_err(|_| TradingViewError::Schema(format!("failed to parse nonce {nonce}")))?;
+            return Ok(TradingViewCommand::Pong { nonce });
+        }
+
+        let message: Object = miniserde::json::from_str(value)?;
+        let method = json_utilities::value_to_string(json_utilities::get_key(&message, "m")?)?;
+        let p = json_utilities::value_to_array(json_utilities::get_key(&message, "p")?)?;
+        let command = match method.as_str() {
+            "set_auth_token" => TradingViewCommand::SetAuthToken {
+                auth_token: param_string(&p, 0)?,
+            },
+            "set_locale" => TradingViewCommand::SetLocale {
+                language_code: param_string(&p, 0)?,
+                region_code: param_string(&p, 1)?,
+            },
+            "chart_create_session" => TradingViewCommand::ChartCreateSession {
+                chart_session_id: param_string(&p, 0)?,
+            },
+            "switch_timezone" => TradingViewCommand::SwitchTimezone {
+                chart_session_id: param_string(&p, 0)?,
+                timezone: param_string(&p, 1)?,
+            },
+            "quote_create_session" => TradingViewCommand::QuoteCreateSession {
+                quote_session_id: param_string(&p, 0)?,
+            },
+            "quote_add_symbols" => TradingViewCommand::QuoteAddSymbols {
+                quote_session_id: param_string(&p, 0)?,
+                symbol: param_string(&p, 1)?,
+            },
+            "resolve_symbol" => TradingViewCommand::ResolveSymbol {
+                chart_session_id: param_string(&p, 0)?,
+                symbol_id: param_string(&p, 1)?,
+                symbol: param_string(&p, 2)?,
+            },
+            "create_series" => TradingViewCommand::CreateSeries {
+                chart_session_id: param_string(&p, 0)?,
+                series_id: param_string(&p, 1)?,
+                unk1: param_string(&p, 2)?,
+                symbol_id: param_string(&p, 3)?,
+                timeframe: param_string(&p, 4)?,
+                range: param_usize(&p, 5)?,
+            },
+            "request_more_tickmarks" => TradingViewCommand::RequestMoreTickmarks {
+                chart_session_id: param_string(&p, 0)?,
+                series_id: param_string(&p, 1)?,
+                range: param_usize(&p, 2)?,
+            },
+            "request_more_data" => TradingViewCommand::RequestMoreData {
+                chart_session_id: param_string(&p, 0)?,
+                series_id: param_string(&p, 1)?,
+                amount: param_usize(&p, 2)?,
+            },
+            "quote_fast_symbols" => TradingViewCommand::QuoteFastSymbols {
+                quote_session_id: param_string(&p, 0)?,
+                symbol: param_string(&p, 1)?,
+            },
+            "quote_set_fields" => TradingViewCommand::QuoteSetFields {
+                quote_session_id: param_string(&p, 0)?,
+                fields: (1..p.len()).map(|index| param_string(&p, index).map(|field| QuoteField::from_name(&field))).collect::<TradingViewResult<Vec<_>>>()?,
+            },
+            "create_study" => TradingViewCommand::CreateStudy {
+                chart_session_id: param_string(&p, 0)?,
+                study_id: param_string(&p, 1)?,
+                session_id: param_string(&p, 2)?,
+                series_id: param_string(&p, 3)?,
+                name: param_string(&p, 4)?,
+                value: json_utilities::get_index(&p, 5)?.clone(),
+            },
+            _ => return Err(TradingViewError::Protocol(format!("unknown command {method}")))
+        };
+        Ok(command)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::symbols::SPY5_REG_SYMBOL;
+
+    /// Encodes `command`, checks the payload byte for byte and that decoding it encodes to the same bytes again.
+    fn assert_wire(command: TradingViewCommand, expected: &str) -> TradingViewCommand {
+        let message = command.to_message();
+        assert_eq!(message, expected);
+        let decoded = TradingViewCommand::from_message(&message).unwrap();
+        assert_eq!(decoded.to_message(), expected);
+        decoded
+    }
+
+    #[test]
+    fn create_series_matches_the_browser() {
+        let command = TradingViewCommand::CreateSeries {
+            chart_session_id: "cs_000000000001".to_string(),
+            series_id: "sds_1".to_string(),
+            unk1: "s1".to_string(),
+            symbol_id: "sds_sym_1".to_string(),
+            timeframe: "5".to_string(),
+            range: 300,
+        };
+        let decoded = assert_wire(command, r#"{"m":"create_series","p":["cs_000000000001","sds_1","s1","sds_sym_1","5",300,""]}"#);
+        assert!(matches!(decoded, TradingViewCommand::CreateSeries { range: 300, .. }));
+    }
+
+    #[test]
+    fn resolve_symbol_escapes_the_symbol_json() {
+        let command = TradingViewCommand::ResolveSymbol {
+            chart_session_id: "cs_000000000001".to_string(),
+            symbol_id: "sds_sym_1".to_string(),
+            symbol: SPY5_REG_SYMBOL.to_string(),
+        };
+        let decoded = assert_wire(command, r#"{"m":"resolve_symbol","p":["cs_000000000001","sds_sym_1","={\"adjustment\":\"splits\",\"currency-id\":\"USD\",\"session\":\"regular\",\"symbol\":\"AMEX:SPY\"}"]}"#);
+        assert!(matches!(decoded, TradingViewCommand::ResolveSymbol { symbol, .. } if symbol == SPY5_REG_SYMBOL));
+    }
+
+    #[test]
+    fn auth_token_with_quote_and_backslash_survives() {
+        let auth_token = r#"ey"J\hb"#;
+        let command = TradingViewCommand::SetAuthToken {
+            auth_token: auth_token.to_string(),
+        };
+        let decoded = assert_wire(command, r#"{"m":"set_auth_token","p":["ey\"J\\hb"]}"#);
+        assert!(matches!(decoded, TradingViewCommand::SetAuthToken { auth_token: decoded_token } if decoded_token == auth_token));
+    }
+
+    #[test]
+    fn create_study_keeps_the_inputs_object() {
+        let mut inputs = Object::new();
+        inputs.insert("text".to_string(), string("bmI9Ks46_5P"));
+        let command = TradingViewCommand::CreateStudy {
+            chart_session_id: "cs_000000000001".to_string(),
+            study_id: "st2".to_string(),
+            session_id: "st1".to_string(),
+            series_id: "sds_1".to_string(),
+            name: "Script@tv-scripting-101!".to_string(),
+            value: Value::Object(inputs),
+        };
+        assert_wire(command, r#"{"m":"create_study","p":["cs_000000000001","st2","st1","sds_1","Script@tv-scripting-101!",{"text":"bmI9Ks46_5P"}]}"#);
+    }
+
+    #[test]
+    fn quote_set_fields_lists_the_field_names() {
+        let command = TradingViewCommand::QuoteSetFields {
+            quote_session_id: "qs_000000000001".to_string(),
+            fields: vec![QuoteField::Lp, QuoteField::LpTime, QuoteField::Ch],
+        };
+        let decoded = assert_wire(command, r#"{"m":"quote_set_fields","p":["qs_000000000001","lp","lp_time","ch"]}"#);
+        assert!(matches!(decoded, TradingViewCommand::QuoteSetFields { fields, .. } if fields == vec![QuoteField::Lp, QuoteField::LpTime, QuoteField::Ch]));
+    }
+
+    #[test]
+    fn pong_is_a_heartbeat() {
+        let decoded = assert_wire(TradingViewCommand::Pong { nonce: 7 }, "~h~7");
+        assert!(matches!(decoded, TradingViewCommand::Pong { nonce: 7 }));
+    }
+
+    #[test]
+    fn unknown_method_is_rejected() {
+        assert!(matches!(TradingViewCommand::from_message(r#"{"m":"remove_series","p":["cs_000000000001","sds_1"]}"#), Err(TradingViewError::Protocol(_))));
+    }
+}
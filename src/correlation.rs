@@ -0,0 +1,137 @@
+use crate::parsed_message::ParsedTradingViewMessage;
+
+/// Identifies which request a server message answers: the message type plus the chart/quote session and the
+/// symbol, series or study id it was sent for.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct TradingViewCorrelationKey {
+    pub message_type: &'static str,
+    pub session_id: Option<String>,
+    pub object_id: Option<String>,
+}
+
+impl TradingViewCorrelationKey {
+    pub fn new(message_type: &'static str) -> Self {
+        Self {
+            message_type,
+            session_id: None,
+            object_id: None,
+        }
+    }
+
+    pub fn for_session(message_type: &'static str, session_id: &str) -> Self {
+        Self {
+            message_type,
+            session_id: Some(session_id.to_string()),
+            object_id: None,
+        }
+    }
+
+    pub fn for_object(message_type: &'static str, session_id: &str, object_id: &str) -> Self {
+        Self {
+            message_type,
+            session_id: Some(session_id.to_string()),
+            object_id: Some(object_id.to_string()),
+        }
+    }
+}
+
+impl ParsedTradingViewMessage {
+    /// The key a waiter has to register under to receive this message.
+    pub fn correlation_key(&self) -> TradingViewCorrelationKey {
+        let message_type = self.message_type();
+        match self {
+            ParsedTradingViewMessage::QuoteSeriesData(message) => TradingViewCorrelationKey::for_object(message_type, &message.quote_session_id, &message.quote_update.symbol),
+            ParsedTradingViewMessage::DataUpdate(message) => TradingViewCorrelationKey::for_object(message_type, &message.chart_session_id, &message.update_key),
+            ParsedTradingViewMessage::QuoteCompleted(message) => TradingViewCorrelationKey::for_object(message_type, &message.quote_session_id, &message.symbol),
+            ParsedTradingViewMessage::TimescaleUpdate(message) => match &message.update_key {
+                Some(update_key) => TradingViewCorrelationKey::for_object(message_type, &message.chart_session_id, update_key),
+                None => TradingViewCorrelationKey::for_session(message_type, &message.chart_session_id),
+            },
+            ParsedTradingViewMessage::SymbolResolved(message) => TradingViewCorrelationKey::for_object(message_type, &message.chart_session_id, &message.symbol_id),
+            ParsedTradingViewMessage::SeriesLoading(message) => TradingViewCorrelationKey::for_object(message_type, &message.chart_session_id, &message.series_id),
+            ParsedTradingViewMessage::SeriesCompleted(message) => TradingViewCorrelationKey::for_object(message_type, &message.chart_session_id, &message.series_id),
+            ParsedTradingViewMessage::StudyLoading(message) => TradingViewCorrelationKey::for_object(message_type, &message.chart_session_id, &message.study_id),
+            ParsedTradingViewMessage::StudyError(message) => TradingViewCorrelationKey::for_object(message_type, &message.chart_session_id, &message.study_id),
+            ParsedTradingViewMessage::StudyCompleted(message) => TradingViewCorrelationKey::for_object(message_type, &message.chart_session_id, &message.study_id),
+            ParsedTradingViewMessage::TickmarkUpdate(message) => TradingViewCorrelationKey::for_session(message_type, &message.chart_session_id),
+            _ => TradingViewCorrelationKey::new(message_type),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures_lite::future::block_on;
+
+    use super::*;
+    use crate::dispatcher::TradingViewMessageDispatcher;
+    use crate::message_wrapper::TradingViewMessageWrapper;
+    use crate::session_registry::TradingViewSessionRegistry;
+
+    fn message(payload: &str) -> TradingViewMessageWrapper {
+        TradingViewMessageWrapper::from_payload(payload.to_string(), &TradingViewSessionRegistry::new()).unwrap()
+    }
+
+    #[test]
+    fn keys_carry_the_session_and_the_object() {
+        let key = |payload: &str| message(payload).parsed_message.correlation_key();
+        assert_eq!(key(r#"{"m":"series_loading","p":["cs_000000000001","sds_1","s1"]}"#), TradingViewCorrelationKey::for_object("series_loading", "cs_000000000001", "sds_1"));
+        assert_eq!(key(r#"{"m":"study_completed","p":["cs_000000000001","st3","st3"]}"#), TradingViewCorrelationKey::for_object("study_completed", "cs_000000000001", "st3"));
+        assert_eq!(key(r#"{"m":"quote_completed","p":["qs_000000000001","AMEX:SPY"]}"#), TradingViewCorrelationKey::for_object("quote_completed", "qs_000000000001", "AMEX:SPY"));
+        assert_eq!(key(r#"{"m":"tickmark_update","p":["cs_000000000002",{"index":299,"zoffset":0,"changes":[],"marks":[]}]}"#), TradingViewCorrelationKey::for_session("tickmark_update", "cs_000000000002"));
+        assert_eq!(key(r#"{"m":"protocol_error","p":["wrong data"]}"#), TradingViewCorrelationKey::new("protocol_error"));
+        assert_ne!(
+            key(r#"{"m":"series_completed","p":["cs_000000000001","sds_1","streaming","s1"]}"#),
+            key(r#"{"m":"series_completed","p":["cs_000000000002","sds_1","streaming","s1"]}"#)
+        );
+    }
+
+    #[test]
+    fn same_series_id_on_two_chart_sessions_gets_separate_replies() {
+        block_on(async {
+            let dispatcher = TradingViewMessageDispatcher::new(8);
+            let first = dispatcher.register(TradingViewCorrelationKey::for_object("series_completed", "cs_000000000001", "sds_1")).await;
+            let second = dispatcher.register(TradingViewCorrelationKey::for_object("series_completed", "cs_000000000002", "sds_1")).await;
+
+            // the second session answers first
+            dispatcher.dispatch(message(r#"{"m":"series_completed","p":["cs_000000000002","sds_1","streaming","s1"]}"#)).await;
+            dispatcher.dispatch(message(r#"{"m":"series_completed","p":["cs_000000000001","sds_1","streaming","s1"]}"#)).await;
+
+            let chart_session_of = |message: TradingViewMessageWrapper| message.parsed_message.as_series_completed().map(|message| message.chart_session_id.clone());
+            assert_eq!(first.try_recv().ok().and_then(chart_session_of), Some("cs_000000000001".to_string()));
+            assert_eq!(second.try_recv().ok().and_then(chart_session_of), Some("cs_000000000002".to_string()));
+            assert_eq!(dispatcher.stats().len, 0);
+        });
+    }
+
+    #[test]
+    fn two_studies_on_one_chart_get_separate_replies() {
+        block_on(async {
+            let dispatcher = TradingViewMessageDispatcher::new(8);
+            let mut receivers = vec![];
+            for study_id in ["st2", "st3"] {
+                for message_type in ["study_loading", "study_completed"] {
+                    receivers.push((study_id, message_type, dispatcher.register(TradingViewCorrelationKey::for_object(message_type, "cs_000000000001", study_id)).await));
+                }
+            }
+
+            // both studies load, then finish in the opposite order, with a reply for another chart mixed in
+            for payload in [
+                r#"{"m":"study_loading","p":["cs_000000000001","st3","st3"]}"#,
+                r#"{"m":"study_loading","p":["cs_000000000001","st2","st2"]}"#,
+                r#"{"m":"study_completed","p":["cs_000000000002","st2","st2"]}"#,
+                r#"{"m":"study_completed","p":["cs_000000000001","st3","st3"]}"#,
+                r#"{"m":"study_completed","p":["cs_000000000001","st2","st2"]}"#,
+            ] {
+                dispatcher.dispatch(message(payload)).await;
+            }
+
+            for (study_id, message_type, receiver) in receivers {
+                let key = receiver.try_recv().expect("reply").parsed_message.correlation_key();
+                assert_eq!(key, TradingViewCorrelationKey::for_object(message_type, "cs_000000000001", study_id));
+            }
+            // the other chart's reply had no waiter
+            assert_eq!(dispatcher.stats().len, 1);
+        });
+    }
+}
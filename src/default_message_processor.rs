@@ -1,7 +1,8 @@
 use async_trait::async_trait;
 
+use crate::client_event::TradingViewClientEvent;
 use crate::message_processor::TradingViewMessageProcessor;
-use crate::parsed_message::ParsedTradingViewMessage;
+use crate::parsed_message::{NotifyUserMessage, ParsedTradingViewMessage};
 
 pub struct DefaultTradingViewMessageProcessor;
 
@@ -35,6 +36,9 @@ impl TradingViewMessageProcessor for DefaultTradingViewMessageProcessor {
             log::info!("[{name}:{chart_session_id}:{update_key}] study_update = {study_update:?}");
           }
         }
+        if let Some(graphics) = data_update_message.graphics.filter(|graphics| !graphics.is_empty()) {
+          log::info!("[{name}:{chart_session_id}:{update_key}] graphics = {graphics:?}");
+        }
       },
       ParsedTradingViewMessage::QuoteCompleted(quote_completed_message) => {
         log::info!("[{name}] quote_completed_message = {quote_completed_message:?}");
@@ -72,6 +76,38 @@ impl TradingViewMessageProcessor for DefaultTradingViewMessageProcessor {
       ParsedTradingViewMessage::NotifyUser(notify_user_message) => {
         log::info!("[{name}] notify_user_message = {notify_user_message:?}");
       },
+      ParsedTradingViewMessage::Unknown { raw } => {
+        log::warn!("[{name}] unknown message = {raw}");
+      },
+    }
+  }
+
+  async fn process_event(&self, name: String, event: TradingViewClientEvent) ->() {
+    match event {
+      TradingViewClientEvent::Reconnected { attempts, downtime } => {
+        log::warn!("[{name}] reconnected after {attempts} attempt(s), downtime = {downtime:?}");
+      },
+      TradingViewClientEvent::MessagesDropped { dropped, total } => {
+        log::warn!("[{name}] processor fell behind, {dropped} message(s) dropped ({total} on this connection)");
+      },
+      TradingViewClientEvent::BarUpdated { chart_session_id, series_id, update } => {
+        log::info!("[{name}:{chart_session_id}:{series_id}] {:?} bar = {:?}", update.change, update.bar);
+      },
+      TradingViewClientEvent::BarClosed { chart_session_id, series_id, bar, by_timer } => {
+        log::info!("[{name}:{chart_session_id}:{series_id}] closed bar = {bar:?} by_timer = {by_timer}");
+      },
+      TradingViewClientEvent::StudyUpdated { chart_session_id, study_id, rows } => {
+        for row in rows {
+          log::info!("[{name}:{chart_session_id}:{study_id}] {} plots = {:?}", row.timestamp().seconds(), row.row.plots);
+        }
+      },
+      TradingViewClientEvent::QuoteChanged { quote_session_id, changed_fields, missing_fields, snapshot } => {
+        log::info!("[{name}:{quote_session_id}] {} changed {changed_fields:?} missing {missing_fields:?}", snapshot.symbol);
+      },
     }
   }
+
+  async fn process_notification(&self, name: String, notification: NotifyUserMessage) -> () {
+    log::warn!("[{name}] {:?} notice from server: {} ({})", notification.kind(), notification.text, notification.notification_type);
+  }
 }
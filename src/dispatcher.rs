@@ -0,0 +1,233 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use async_channel::{Receiver, Sender, TrySendError};
+use async_lock::Mutex;
+
+use crate::correlation::TradingViewCorrelationKey;
+use crate::message_wrapper::TradingViewMessageWrapper;
+
+type WaiterCondition = Box<dyn Fn(&TradingViewMessageWrapper) -> bool + Send + Sync>;
+
+struct Waiter {
+    condition: Option<WaiterCondition>,
+    sender: Sender<TradingViewMessageWrapper>,
+}
+
+#[derive(Debug, Clone)]
+pub struct TradingViewBacklogStats {
+    pub len: usize,
+    pub capacity: usize,
+    pub dropped: usize,
+}
+
+/// Routes messages coming off the reader task either to a waiter registered up front or to a bounded backlog.
+pub struct TradingViewMessageDispatcher {
+    waiters: Mutex<HashMap<TradingViewCorrelationKey, VecDeque<Waiter>>>,
+    backlog_sender: Sender<TradingViewMessageWrapper>,
+    backlog_receiver: Receiver<TradingViewMessageWrapper>,
+    backlog_capacity: usize,
+    dropped: AtomicUsize,
+}
+
+impl TradingViewMessageDispatcher {
+    pub fn new(backlog_capacity: usize) -> Self {
+        let (backlog_sender, backlog_receiver) = async_channel::bounded(backlog_capacity.max(1));
+        Self {
+            waiters: Mutex::new(HashMap::new()),
+            backlog_sender,
+            backlog_receiver,
+            backlog_capacity: backlog_capacity.max(1),
+            dropped: AtomicUsize::new(0),
+        }
+    }
+
+    /// Registers interest in the next message correlated to `key`. Register before sending the request so the reply can't race past.
+    pub async fn register(&self, key: TradingViewCorrelationKey) -> Receiver<TradingViewMessageWrapper> {
+        self.register_waiter(key, None).await
+    }
+
+    /// Same as `register`, but only messages for `key` that also satisfy `condition` are handed over.
+    pub async fn register_with<F>(&self, key: TradingViewCorrelationKey, condition: F) -> Receiver<TradingViewMessageWrapper>
+    where
+        F: Fn(&TradingViewMessageWrapper) -> bool + Send + Sync + 'static,
+    {
+        self.register_waiter(key, Some(Box::new(condition))).await
+    }
+
+    async fn register_waiter(&self, key: TradingViewCorrelationKey, condition: Option<WaiterCondition>) -> Receiver<TradingViewMessageWrapper> {
+        let (sender, receiver) = async_channel::bounded(1);
+        let mut waiters = self.waiters.lock().await;
+        waiters.entry(key).or_default().push_back(Waiter { condition, sender });
+        receiver
+    }
+
+    /// Hands a message to the oldest matching waiter, or queues it on the backlog (dropping the oldest entry when full).
+    pub async fn dispatch(&self, message: TradingViewMessageWrapper) {
+        let mut message = message;
+        let mut waiters = self.waiters.lock().await;
+        if let Some(queue) = waiters.get_mut(&message.parsed_message.correlation_key()) {
+            // waiters that timed out have dropped their receiver
+            queue.retain(|waiter| !waiter.sender.is_closed());
+            while let Some(position) = queue.iter().position(|waiter| waiter.condition.as_ref().map(|condition| condition(&message)).unwrap_or(true)) {
+                let waiter = queue.remove(position).expect("position is in bounds");
+                match waiter.sender.try_send(message) {
+                    Ok(()) => return,
+                    Err(err) => message = err.into_inner(),
+                }
+            }
+        }
+        drop(waiters);
+
+        loop {
+            match self.backlog_sender.try_send(message) {
+                Ok(()) => return,
+                Err(TrySendError::Full(returned)) => {
+                    message = returned;
+                    if let Ok(oldest) = self.backlog_receiver.try_recv() {
+                        let dropped = self.dropped.fetch_add(1, Ordering::Relaxed) + 1;
+                        log::warn!("backlog full, dropped {} message (dropped = {dropped})", oldest.parsed_message.message_type());
+                    }
+                },
+                Err(TrySendError::Closed(_)) => return,
+            }
+        }
+    }
+
+    /// Waits for the next message nobody registered for. Returns `None` once the connection is closed and the backlog is drained.
+    pub async fn next_message(&self) -> Option<TradingViewMessageWrapper> {
+        self.backlog_receiver.recv().await.ok()
+    }
+
+    /// Takes the next unmatched message if one is already queued.
+    pub fn try_next_message(&self) -> Option<TradingViewMessageWrapper> {
+        self.backlog_receiver.try_recv().ok()
+    }
+
+    /// Wakes up every waiter and backlog reader; called when the reader task exits.
+    pub async fn close(&self) {
+        self.backlog_sender.close();
+        self.waiters.lock().await.clear();
+    }
+
+    pub fn stats(&self) -> TradingViewBacklogStats {
+        TradingViewBacklogStats {
+            len: self.backlog_receiver.len(),
+            capacity: self.backlog_capacity,
+            dropped: self.dropped.load(Ordering::Relaxed),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures_lite::future::block_on;
+
+    use super::*;
+    use crate::session_registry::TradingViewSessionRegistry;
+
+    fn message(payload: &str) -> TradingViewMessageWrapper {
+        TradingViewMessageWrapper::from_payload(payload.to_string(), &TradingViewSessionRegistry::new()).unwrap()
+    }
+
+    fn series_loading(series_id: &str) -> TradingViewMessageWrapper {
+        message(&format!(r#"{{"m":"series_loading","p":["cs_000000000001","{series_id}","s1"]}}"#))
+    }
+
+    fn qsd(lp: Option<f64>) -> TradingViewMessageWrapper {
+        let v = lp.map(|lp| format!(r#"{{"lp":{lp}}}"#)).unwrap_or_else(|| r#"{"ch":1.5}"#.to_string());
+        message(&format!(r#"{{"m":"qsd","p":["qs_000000000001",{{"n":"AMEX:SPY","s":"ok","v":{v}}}]}}"#))
+    }
+
+    fn key(series_id: &str) -> TradingViewCorrelationKey {
+        TradingViewCorrelationKey::for_object("series_loading", "cs_000000000001", series_id)
+    }
+
+    #[test]
+    fn waiters_for_one_key_are_served_oldest_first_and_once() {
+        block_on(async {
+            let dispatcher = TradingViewMessageDispatcher::new(8);
+            let first = dispatcher.register(key("sds_1")).await;
+            let second = dispatcher.register(key("sds_1")).await;
+
+            dispatcher.dispatch(series_loading("sds_1")).await;
+            assert!(first.try_recv().is_ok());
+            assert!(second.try_recv().is_err());
+
+            dispatcher.dispatch(series_loading("sds_1")).await;
+            dispatcher.dispatch(series_loading("sds_1")).await;
+            assert!(second.try_recv().is_ok());
+            assert!(first.try_recv().is_err());
+            // both waiters are used up, the third reply has nobody left
+            assert_eq!(dispatcher.stats().len, 1);
+        });
+    }
+
+    #[test]
+    fn conditional_waiter_only_takes_matching_messages() {
+        block_on(async {
+            let dispatcher = TradingViewMessageDispatcher::new(8);
+            let qsd_key = TradingViewCorrelationKey::for_object("qsd", "qs_000000000001", "AMEX:SPY");
+            let last_price = dispatcher.register_with(qsd_key.clone(), |message| {
+                message.parsed_message.as_quote_series_data().map(|message| message.quote_update.lp.is_some()).unwrap_or(false)
+            }).await;
+            let any = dispatcher.register(qsd_key).await;
+
+            // the first qsd fails the condition and falls through to the unconditional waiter behind it
+            dispatcher.dispatch(qsd(None)).await;
+            assert!(last_price.try_recv().is_err());
+            assert!(any.try_recv().is_ok());
+
+            // with nobody else left, a non matching one goes to the backlog
+            dispatcher.dispatch(qsd(None)).await;
+            assert_eq!(dispatcher.try_next_message().and_then(|message| message.parsed_message.as_quote_series_data().map(|message| message.quote_update.lp)), Some(None));
+
+            dispatcher.dispatch(qsd(Some(450.5))).await;
+            assert_eq!(last_price.try_recv().ok().and_then(|message| message.parsed_message.as_quote_series_data().and_then(|message| message.quote_update.lp)), Some(450.5));
+            assert_eq!(dispatcher.stats().len, 0);
+        });
+    }
+
+    #[test]
+    fn closed_waiters_are_skipped_and_cleaned_up() {
+        block_on(async {
+            let dispatcher = TradingViewMessageDispatcher::new(8);
+            // a waiter that timed out and dropped its receiver
+            drop(dispatcher.register(key("sds_1")).await);
+            let live = dispatcher.register(key("sds_1")).await;
+
+            dispatcher.dispatch(series_loading("sds_1")).await;
+            assert!(live.try_recv().is_ok());
+            assert!(dispatcher.waiters.lock().await.get(&key("sds_1")).is_some_and(|queue| queue.is_empty()));
+
+            drop(dispatcher.register(key("sds_1")).await);
+            dispatcher.dispatch(series_loading("sds_1")).await;
+            assert_eq!(dispatcher.stats().len, 1);
+            assert!(dispatcher.waiters.lock().await.get(&key("sds_1")).is_some_and(|queue| queue.is_empty()));
+        });
+    }
+
+    #[test]
+    fn full_backlog_drops_the_oldest_and_counts_it() {
+        block_on(async {
+            let dispatcher = TradingViewMessageDispatcher::new(2);
+            for series_id in ["sds_1", "sds_2", "sds_3", "sds_4"] {
+                dispatcher.dispatch(series_loading(series_id)).await;
+            }
+            let stats = dispatcher.stats();
+            assert_eq!((stats.len, stats.capacity, stats.dropped), (2, 2, 2));
+
+            let kept = std::iter::from_fn(|| dispatcher.try_next_message())
+                .filter_map(|message| message.parsed_message.as_series_loading().map(|message| message.series_id.clone()))
+                .collect::<Vec<_>>();
+            assert_eq!(kept, vec!["sds_3", "sds_4"]);
+
+            // once closed, nothing more is queued or counted
+            dispatcher.close().await;
+            dispatcher.dispatch(series_loading("sds_5")).await;
+            assert_eq!(dispatcher.stats().len, 0);
+            assert_eq!(dispatcher.stats().dropped, 2);
+            assert_eq!(dispatcher.next_message().await.map(|message| message.payload), None);
+        });
+    }
+}
@@ -0,0 +1,78 @@
+use std::fmt;
+
+/// Everything that can go wrong between the socket and a `ParsedTradingViewMessage`.
+#[derive(Debug)]
+pub enum TradingViewError {
+    /// The websocket frame or the `~m~len~m~` wrapper around a message was malformed
+    Framing(String),
+    /// A payload was not valid JSON
+    Json(String),
+    /// Valid JSON that did not have the shape the message type requires
+    Schema(String),
+    /// The server did something the protocol does not allow
+    Protocol(String),
+    /// The underlying websocket connection failed
+    Transport(anyhow::Error),
+    /// The server refused to create a study (`study_error`)
+    StudyFailed {
+        chart_session_id: String,
+        study_id: String,
+        error: String,
+    },
+    /// The server rejected a command (`critical_error`)
+    Rejected {
+        session_id: String,
+        code: String,
+        method: Option<String>,
+    },
+    /// The server rejected the auth token
+    Unauthorized(String),
+    /// The server could not parse something this client sent (`protocol_error`)
+    ServerProtocol(String),
+}
+
+impl TradingViewError {
+    /// Whether the stream can't be trusted anymore after this error (as opposed to a single bad message).
+    pub fn is_fatal(&self) -> bool {
+        matches!(self, TradingViewError::Framing(_) | TradingViewError::Transport(_))
+    }
+
+    /// Whether the server turned down what was asked for, so reconnecting and asking again would fail the same way.
+    pub fn is_rejection(&self) -> bool {
+        matches!(self, TradingViewError::StudyFailed { .. } | TradingViewError::Rejected { .. } | TradingViewError::Unauthorized(_) | TradingViewError::ServerProtocol(_))
+    }
+}
+
+impl fmt::Display for TradingViewError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TradingViewError::Framing(message) => write!(f, "framing error: {message}"),
+            TradingViewError::Json(message) => write!(f, "json error: {message}"),
+            TradingViewError::Schema(message) => write!(f, "schema error: {message}"),
+            TradingViewError::Protocol(message) => write!(f, "protocol error: {message}"),
+            TradingViewError::Transport(err) => write!(f, "transport error: {err}"),
+            TradingViewError::StudyFailed { chart_session_id, study_id, error } => write!(f, "study {study_id} on {chart_session_id} failed: {error}"),
+            TradingViewError::Rejected { session_id, code, method } => write!(f, "server rejected {} on {session_id:?}: {code}", method.as_deref().unwrap_or("command")),
+            TradingViewError::Unauthorized(message) => write!(f, "unauthorized: {message}"),
+            TradingViewError::ServerProtocol(message) => write!(f, "server protocol error: {message}"),
+        }
+    }
+}
+
+impl std::error::Error for TradingViewError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            TradingViewError::Transport(err) => Some(err.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+impl From<miniserde::Error> for TradingViewError {
+    fn from(_: miniserde::Error) -> Self {
+        // miniserde errors carry no detail
+        TradingViewError::Json("failed to parse json".to_string())
+    }
+}
+
+pub type TradingViewResult<T> = Result<T, TradingViewError>;
@@ -0,0 +1,75 @@
+use std::collections::BTreeMap;
+
+use crate::parsed_message::TimescaleUpdate;
+use crate::timestamp::TradingViewTimestamp;
+
+/// How far back `TradingViewClient::fetch_history` should page.
+#[derive(Debug, Clone, Copy)]
+pub enum TradingViewHistoryTarget {
+    /// The most recent N bars
+    Bars(usize),
+    /// Every bar at or after this timestamp
+    Since(TradingViewTimestamp),
+}
+
+/// Bars collected across `request_more_data` pages, de-duplicated and ordered by timestamp.
+#[derive(Debug, Default)]
+pub struct TradingViewHistory {
+    bars: BTreeMap<TradingViewTimestamp, TimescaleUpdate>,
+}
+
+impl TradingViewHistory {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Moves every bar collected so far `offset` indexes up, for a page that prepended `offset` bars.
+    pub fn renumber(&mut self, offset: i64) {
+        for bar in self.bars.values_mut() {
+            bar.index += offset;
+        }
+    }
+
+    /// Merges a page, returns how many bars were not seen before. Later pages win for timestamps seen twice.
+    pub fn merge(&mut self, updates: &[TimescaleUpdate]) -> usize {
+        let mut added = 0;
+        for update in updates {
+            if self.bars.insert(update.timestamp, update.clone()).is_none() {
+                added += 1;
+            }
+        }
+        added
+    }
+
+    pub fn len(&self) -> usize {
+        self.bars.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bars.is_empty()
+    }
+
+    pub fn earliest_timestamp(&self) -> Option<TradingViewTimestamp> {
+        self.bars.keys().next().copied()
+    }
+
+    pub fn is_satisfied(&self, target: TradingViewHistoryTarget) -> bool {
+        match target {
+            TradingViewHistoryTarget::Bars(count) => self.bars.len() >= count,
+            TradingViewHistoryTarget::Since(timestamp) => self.earliest_timestamp().map(|earliest| earliest <= timestamp).unwrap_or(false),
+        }
+    }
+
+    /// Oldest first, trimmed to what `target` asked for.
+    pub fn into_bars(self, target: TradingViewHistoryTarget) -> Vec<TimescaleUpdate> {
+        match target {
+            TradingViewHistoryTarget::Bars(count) => {
+                let skip = self.bars.len().saturating_sub(count);
+                self.bars.into_values().skip(skip).collect()
+            },
+            TradingViewHistoryTarget::Since(timestamp) => {
+                self.bars.into_iter().filter(|(bar_timestamp, _)| *bar_timestamp >= timestamp).map(|(_, bar)| bar).collect()
+            },
+        }
+    }
+}
@@ -1,6 +1,93 @@
+use miniserde::de::{Map, Visitor};
+use miniserde::{make_place, Deserialize};
+
+use crate::study_metadata::TradingViewStudyMetadata;
+
+/// A pine script study to create on every chart session: its inputs and, optionally, its plot layout.
+///
+/// In a JSON config either the object form or, as before plot layouts existed, just the inputs string.
+#[derive(Debug, Clone)]
+pub struct TradingViewIndicator {
+  /// Inputs object passed to `create_study`, e.g. from `TradingViewIndicators`
+  pub value: String,
+  /// Plot names for `StudyRow`, without it the plots are named `plot_<n>`
+  pub metadata: Option<TradingViewStudyMetadata>,
+}
+
+impl TradingViewIndicator {
+  pub fn new(value: String) -> Self {
+    Self { value, metadata: None }
+  }
+
+  pub fn with_metadata(mut self, metadata: TradingViewStudyMetadata) -> Self {
+    self.metadata = Some(metadata);
+    self
+  }
+}
+
+impl From<String> for TradingViewIndicator {
+  fn from(value: String) -> Self {
+    Self::new(value)
+  }
+}
+
+make_place!(Place);
+
+impl Visitor for Place<TradingViewIndicator> {
+  fn string(&mut self, s: &str) -> miniserde::Result<()> {
+    self.out = Some(TradingViewIndicator::new(s.to_string()));
+    Ok(())
+  }
+
+  fn map(&mut self) -> miniserde::Result<Box<dyn Map + '_>> {
+    Ok(Box::new(IndicatorBuilder {
+      value: None,
+      metadata: None,
+      out: &mut self.out,
+    }))
+  }
+}
+
+struct IndicatorBuilder<'a> {
+  value: Option<String>,
+  metadata: Option<Option<TradingViewStudyMetadata>>,
+  out: &'a mut Option<TradingViewIndicator>,
+}
+
+impl Map for IndicatorBuilder<'_> {
+  fn key(&mut self, k: &str) -> miniserde::Result<&mut dyn Visitor> {
+    match k {
+      "value" => Ok(Deserialize::begin(&mut self.value)),
+      "metadata" => Ok(Deserialize::begin(&mut self.metadata)),
+      _ => Ok(<dyn Visitor>::ignore()),
+    }
+  }
+
+  fn finish(&mut self) -> miniserde::Result<()> {
+    let value = self.value.take().ok_or(miniserde::Error)?;
+    *self.out = Some(TradingViewIndicator {
+      value,
+      metadata: self.metadata.take().flatten(),
+    });
+    Ok(())
+  }
+}
+
+impl Deserialize for TradingViewIndicator {
+  fn begin(out: &mut Option<Self>) -> &mut dyn Visitor {
+    Place::new(out)
+  }
+}
+
 pub struct TradingViewIndicators;
 
 impl TradingViewIndicators {
+  /// Plot layout of `generate_vwap_mvwap_ema_crossover`, pass it to `TradingViewIndicator::with_metadata`. MVWAP is the third
+  /// plot, `values[3]` of a row counting the timestamp. Plots after these keep their `plot_<n>` names.
+  pub fn vwap_mvwap_ema_crossover_metadata() -> TradingViewStudyMetadata {
+    TradingViewStudyMetadata::from_titles(&["VWAP", "EMA 1", "MVWAP", "EMA 2"])
+  }
+
   pub fn generate_vwap_mvwap_ema_crossover(vwap_length: usize, ema1_source: String, ema1_length: usize, ema2_source: String, ema2_length: usize, rsi_limit: usize, rsi_minimum: usize, mvwap_length: usize) -> String {
     format!(r#"{{
       "text": "bmI9Ks46_14Oy1AFtjg8Ls9wU0S1rlg==_u70xwiBAuvwE8ScMuj3/xelBeUlPpaP443vgI0LOz0anO3Sz0Nml/Cw66rceMmOX/36sFmV/J8A9ocybTXK65SWNk5Mq5ULJ6IYlXtaoFYYsZRWpEMmaP9eq8c+j6BmHYcbh3XLrcNMUimL3emFm7ualhqyIU9Bit+n31nA898zBRSxB1+Jj5sHZ5cCUltgwmiCmbV6WhQoR6fRTVK5DXvgazVghDGv9ZF18/TpaZAnipKAZ1P59oNNL2e72XZQXWzWZlAbu7CHAtjyLv5RmO9bMBdsr2+Icd5cmGy+inNgtM4++cecagL5owwZhZGA/GRPyZ8UtjuvJesqiGPH+yqQEWtyfCnCjpvTV+tpDCn2SKcSQZyA87pNzAIi6/pspgUb01Sf2+wiJY+HuXAMKZQQ9zgD7oIvjjPaQqTBUgjVc0VMlQYX98yW3jzdOkaRXjKHxqSn0MXodjEBr1wQvH8sUv8Pvrttgdb7LVh/NFH4z8sQMRK7U7HB08M277TrUkz5Lak1OArmJ5vGF36Ty+Cw7nF3T2/t+LHecLwbIAzrtxR85m0fHMsZwwfW8z71w6/PuQnSZnlinambAWGDzUOAcc9CcXj9LRHsi9/wjRecaws1CUt1t4DI3oYsdMBcoGdx79k2a5qJT3aAYgpa1GTY3saW3RK5Lf8DasNK3srIlE6NyomS+pGhpBUpEFbd6iZL5o9G3iPUMHApZF3wXAHq78WxT+dnPUc/x3nnTmUK4IzsJnURj7jdi2Ko3LlC6OIO8o9/6knQPipTK7MMPG+sSJoFrfVaQiH6aXUMiTAspzHVmeoxZRFoi3J95HfXh+bOMbIwP62VmHgH0RhZzHWpUxIJof4iK/SIo3JVAQkt43JGyD8A0CzIgH2MVZmMV+rwe6URDCO63Vrs/6Fvz6QzPWbUmiXW5laTpBXJzM5mBrZD+M9Zso42rATUT6w3i23H2VE5kKbHG5p5kkyGM1c134cike1y5gyZDK3SMmnQyNgxUJKG0UpgXF2dnlQJpHXzya8dXco5QhldBd7TG33vKdKN5Ti/LMP6GJsZt6QC4CZWj0tWC8ow9ETVkiw0GGSLNUq818rG0EnWt9ZPVPu2dyT3gP/ZamMmmrKRWne12psNknznrqiH1ffDxdGGkJgVpda377gPVPYK5XrzyXvQKhNf7/xdAqN5DAiW5xpiUJ6GFcl3sgR35OBsFkFA=",
@@ -49,3 +136,28 @@ impl TradingViewIndicators {
     }}"#)
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::parsed_message::StudyUpdate;
+  use crate::study_metadata::StudyRow;
+
+  #[test]
+  fn indicators_deserialize_from_the_object_or_the_old_string_form() {
+    let indicators: Vec<TradingViewIndicator> = miniserde::json::from_str(r#"["{\"pineId\":\"STD;VWAP\"}",{"value":"{}","metadata":{"plots":[{"id":"plot_0","title":"Close","plot_type":null}]}},{"value":"{}"}]"#).unwrap();
+    assert_eq!(indicators[0].value, r#"{"pineId":"STD;VWAP"}"#);
+    assert!(indicators[0].metadata.is_none());
+    assert_eq!(indicators[1].metadata.as_ref().map(|metadata| metadata.plot_names()), Some(vec!["Close".to_string()]));
+    assert!(indicators[2].metadata.is_none());
+    assert!(miniserde::json::from_str::<TradingViewIndicator>(r#"{"metadata":null}"#).is_err());
+  }
+
+  #[test]
+  fn crossover_row_reads_mvwap_from_values_3() {
+    let update = StudyUpdate { index: 299, values: vec![1_700_000_100.0, 451.2, 450.8, 449.9, 450.1] };
+    let row = StudyRow::from_update(&update, Some(&TradingViewIndicators::vwap_mvwap_ema_crossover_metadata())).unwrap();
+    assert_eq!(row.plot("MVWAP"), Some(update.values[3]));
+    assert_eq!(row.plot("VWAP"), Some(451.2));
+  }
+}
@@ -1,44 +1,121 @@
 use miniserde::json::{Array, Number, Object, Value};
 
-pub fn value_to_string(input: &Value) -> anyhow::Result<String> {
+use crate::error::{TradingViewError, TradingViewResult};
+
+pub fn value_to_string(input: &Value) -> TradingViewResult<String> {
     match input {
         Value::String(value) => Ok(value.clone()),
-        _ => Err(anyhow::anyhow!("parsing failed"))
+        _ => Err(TradingViewError::Schema(format!("expected string, got {input:?}")))
     }
 }
 
-pub fn value_to_array(input: &Value) -> anyhow::Result<Array> {
+pub fn value_to_array(input: &Value) -> TradingViewResult<Array> {
     match input {
         Value::Array(value) => Ok(value.clone()),
-        _ => Err(anyhow::anyhow!("parsing failed"))
+        _ => Err(TradingViewError::Schema(format!("expected array, got {input:?}")))
     }
 }
 
-pub fn value_to_object(input: &Value) -> anyhow::Result<Object> {
+pub fn value_to_object(input: &Value) -> TradingViewResult<Object> {
     match input {
         Value::Object(value) => Ok(value.clone()),
-        _ => Err(anyhow::anyhow!("parsing failed"))
+        _ => Err(TradingViewError::Schema(format!("expected object, got {input:?}")))
     }
 }
 
-pub fn value_to_number(input: &Value) -> anyhow::Result<Number> {
+pub fn value_to_number(input: &Value) -> TradingViewResult<Number> {
     match input {
         Value::Number(value) => Ok(value.clone()),
-        _ => Err(anyhow::anyhow!("parsing failed"))
+        _ => Err(TradingViewError::Schema(format!("expected number, got {input:?}")))
+    }
+}
+
+pub fn value_to_bool(input: &Value) -> TradingViewResult<bool> {
+    match input {
+        Value::Bool(value) => Ok(*value),
+        _ => Err(TradingViewError::Schema(format!("expected bool, got {input:?}")))
+    }
+}
+
+pub fn get_key<'a>(input: &'a Object, key: &str) -> TradingViewResult<&'a Value> {
+    input.get(key).ok_or(TradingViewError::Schema(format!("failed to get {key}")))
+}
+
+pub fn get_index(input: &Array, index: usize) -> TradingViewResult<&Value> {
+    input.get(index).ok_or(TradingViewError::Schema(format!("failed to get index {index}")))
+}
+
+/// A missing key and an explicit null are both `None`.
+pub fn get_optional_number(input: &Object, key: &str) -> TradingViewResult<Option<Number>> {
+    match input.get(key) {
+        Some(Value::Null) | None => Ok(None),
+        Some(value) => Ok(Some(value_to_number(value)?)),
+    }
+}
+
+/// A missing key and an explicit null are both `None`.
+pub fn get_optional_string(input: &Object, key: &str) -> TradingViewResult<Option<String>> {
+    match input.get(key) {
+        Some(Value::Null) | None => Ok(None),
+        Some(value) => Ok(Some(value_to_string(value)?)),
+    }
+}
+
+/// A missing key and an explicit null are both `None`.
+pub fn get_optional_bool(input: &Object, key: &str) -> TradingViewResult<Option<bool>> {
+    match input.get(key) {
+        Some(Value::Null) | None => Ok(None),
+        Some(value) => Ok(Some(value_to_bool(value)?)),
     }
 }
 
-pub fn value_to_bool(input: &Value) -> anyhow::Result<bool> {
+pub fn value_to_u64(input: &Value) -> TradingViewResult<u64> {
     match input {
-        Value::Bool(value) => Ok(value.clone()),
-        _ => Err(anyhow::anyhow!("parsing failed"))
+        Value::Number(Number::U64(value)) => Ok(*value),
+        Value::Number(Number::I64(value)) if *value >= 0 => Ok(*value as u64),
+        Value::Number(Number::F64(value)) if *value >= 0.0 && value.fract() == 0.0 => Ok(*value as u64),
+        _ => Err(TradingViewError::Schema(format!("expected unsigned integer, got {input:?}")))
     }
 }
 
-pub fn is_null(input: &Object, key: &str) -> anyhow::Result<bool> {
-    let value = input.get(key).ok_or(anyhow::anyhow!("failed to get key"))?;
-    match value {
-        Value::Null => Ok(true),
-        _ => Ok(false),
+/// A missing key and an explicit null are both `None`.
+pub fn get_optional_u64(input: &Object, key: &str) -> TradingViewResult<Option<u64>> {
+    match input.get(key) {
+        Some(Value::Null) | None => Ok(None),
+        Some(value) => Ok(Some(value_to_u64(value)?)),
+    }
+}
+
+pub fn number_to_i64(input: &Number) -> i64 {
+    match input {
+        Number::U64(value) => *value as i64,
+        Number::I64(value) => *value,
+        Number::F64(value) => *value as i64,
+    }
+}
+
+pub fn number_to_f64(input: &Number) -> f64 {
+    match input {
+        Number::U64(value) => *value as f64,
+        Number::I64(value) => *value as f64,
+        Number::F64(value) => *value,
     }
 }
+
+pub fn value_to_i64(input: &Value) -> TradingViewResult<i64> {
+    Ok(number_to_i64(&value_to_number(input)?))
+}
+
+pub fn value_to_f64(input: &Value) -> TradingViewResult<f64> {
+    Ok(number_to_f64(&value_to_number(input)?))
+}
+
+/// A missing key and an explicit null are both `None`.
+pub fn get_optional_i64(input: &Object, key: &str) -> TradingViewResult<Option<i64>> {
+    Ok(get_optional_number(input, key)?.map(|number| number_to_i64(&number)))
+}
+
+/// A missing key and an explicit null are both `None`.
+pub fn get_optional_f64(input: &Object, key: &str) -> TradingViewResult<Option<f64>> {
+    Ok(get_optional_number(input, key)?.map(|number| number_to_f64(&number)))
+}
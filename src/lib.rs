@@ -1,25 +1,62 @@
+mod error;
 mod message_wrapper;
 mod reader;
 mod writer;
+mod command;
 mod client;
 mod utilities;
+mod transport;
+mod dispatcher;
+mod correlation;
+mod session_registry;
 mod json_utilities;
 mod parsed_message;
 mod message_processor;
 mod default_message_processor;
 mod client_config;
+mod client_event;
 mod indicators;
 mod symbols;
+mod symbol_info;
 mod scrape_result;
+mod history;
+mod bar_series;
+mod quote_book;
+mod quote_field;
+mod pine_graphics;
+mod study_metadata;
+mod study_join;
+mod timestamp;
+mod price;
+#[cfg(feature = "testing")]
+mod testing;
 
+pub use error::*;
 pub use reader::*;
 pub use writer::*;
+pub use command::*;
 pub use message_wrapper::*;
 pub use client::*;
 pub use parsed_message::*;
 pub use message_processor::*;
 pub use default_message_processor::*;
 pub use client_config::*;
+pub use client_event::*;
+pub use dispatcher::*;
+pub use correlation::*;
+pub use session_registry::*;
 pub use indicators::*;
 pub use symbols::*;
+pub use symbol_info::*;
 pub use scrape_result::*;
+pub use history::*;
+pub use bar_series::*;
+pub use quote_book::*;
+pub use quote_field::*;
+pub use pine_graphics::*;
+pub use study_metadata::*;
+pub use study_join::*;
+pub use timestamp::*;
+pub use price::*;
+#[cfg(feature = "testing")]
+pub use testing::*;
@@ -1,8 +1,14 @@
 use async_trait::async_trait;
 
-use crate::parsed_message::ParsedTradingViewMessage;
+use crate::client_event::TradingViewClientEvent;
+use crate::parsed_message::{NotifyUserMessage, ParsedTradingViewMessage};
 
 #[async_trait]
 pub trait TradingViewMessageProcessor {
     async fn process_message(&self, name: String, message: ParsedTradingViewMessage);
+
+    async fn process_event(&self, _name: String, _event: TradingViewClientEvent) {}
+
+    /// Notices the server shows to the user, raised on top of the `notify_user` message itself so they can be alerted on.
+    async fn process_notification(&self, _name: String, _notification: NotifyUserMessage) {}
 }
@@ -1,3 +1,5 @@
+use std::time::SystemTime;
+
 use nom::{
     bytes::streaming::{tag as tag_streaming, take as take_streaming},
     character::streaming::digit1 as digit1_streaming,
@@ -6,12 +8,16 @@ use nom::{
     IResult,
 };
 
+use crate::error::TradingViewResult;
 use crate::parsed_message::ParsedTradingViewMessage;
+use crate::session_registry::TradingViewSessionRegistry;
 
 #[derive(Debug, Clone)]
 pub struct TradingViewMessageWrapper {
     pub payload: String,
-    pub parsed_message: ParsedTradingViewMessage
+    pub parsed_message: ParsedTradingViewMessage,
+    /// Local time the payload was decoded at
+    pub received_at: SystemTime
 }
 
 impl TradingViewMessageWrapper {
@@ -21,8 +27,29 @@ impl TradingViewMessageWrapper {
         format!("~m~{input_len}~m~{input}")
     }
 
-    /// Parses a TradingView message from the input bytes.
-    pub fn parse(input: &[u8]) -> IResult<&[u8], TradingViewMessageWrapper> {
+    /// Decodes an unwrapped payload, keeping only the first message of a batched frame like `ParsedTradingViewMessage::from_string_with_registry`.
+    pub fn from_payload(payload: String, registry: &TradingViewSessionRegistry) -> TradingViewResult<Self> {
+        let parsed_message = ParsedTradingViewMessage::from_string_with_registry(&payload, registry)?;
+        Ok(TradingViewMessageWrapper {
+            payload,
+            parsed_message,
+            received_at: SystemTime::now()
+        })
+    }
+
+    /// Decodes an unwrapped payload into every message it holds, see `ParsedTradingViewMessage::messages_from_string_with_registry`.
+    pub fn messages_from_payload(payload: String, registry: &TradingViewSessionRegistry) -> TradingViewResult<Vec<Self>> {
+        let parsed_messages = ParsedTradingViewMessage::messages_from_string_with_registry(&payload, registry)?;
+        let received_at = SystemTime::now();
+        Ok(parsed_messages.into_iter().map(|parsed_message| TradingViewMessageWrapper {
+            payload: payload.clone(),
+            parsed_message,
+            received_at
+        }).collect())
+    }
+
+    /// Parses the `~m~len~m~` framing from the input bytes, leaving the payload undecoded.
+    pub fn parse_frame(input: &[u8]) -> IResult<&[u8], String> {
         // Parse the prefix "~m~"
         let (input, _) = tag_streaming("~m~")(input)?;
 
@@ -51,18 +78,19 @@ impl TradingViewMessageWrapper {
                 nom::Err::Failure(nom::error::Error::new(payload, ErrorKind::Fail))
             })?;
 
+        Ok((input, string_payload))
+    }
+
+    /// Parses a TradingView message from the input bytes.
+    pub fn parse(input: &[u8]) -> IResult<&[u8], TradingViewMessageWrapper> {
+        let (remaining, payload) = Self::parse_frame(input)?;
+
         // Try to parse into message
-        let parsed_message = ParsedTradingViewMessage::from_string(&string_payload)
+        let message = Self::from_payload(payload, &TradingViewSessionRegistry::new())
             .map_err(|_| {
-                nom::Err::Failure(nom::error::Error::new(payload, ErrorKind::Fail))
+                nom::Err::Failure(nom::error::Error::new(input, ErrorKind::Fail))
             })?;
 
-        Ok((
-            input,
-            TradingViewMessageWrapper {
-                payload: string_payload,
-                parsed_message
-            },
-        ))
+        Ok((remaining, message))
     }
 }
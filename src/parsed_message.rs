@@ -1,26 +1,43 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
 use enum_as_inner::EnumAsInner;
 use miniserde::Serialize;
-use miniserde::json::{Number, Object};
+use miniserde::ser::Fragment;
+use miniserde::json::{Object, Value};
 
+use crate::error::{TradingViewError, TradingViewResult};
 use crate::json_utilities;
+use crate::symbol_info::SymbolInfo;
+use crate::timestamp::TradingViewTimestamp;
+use crate::pine_graphics::PineGraphics;
+use crate::quote_field::{QuoteField, QuoteFieldMap};
+use crate::study_metadata::StudyRow;
+use crate::session_registry::{TradingViewSessionObjectKind, TradingViewSessionRegistry};
 
 #[derive(Debug, Clone, Serialize)]
 pub struct QuoteSeriesDataUpdate {
     pub symbol: String,
-    pub volume: Option<Number>,
-    pub ch: Option<Number>,
-    pub chp: Option<Number>,
-    pub rch: Option<Number>,
-    pub rchp: Option<Number>,
-    pub rtc: Option<Number>,
-    pub rtc_time: Option<Number>,
-    pub lp: Option<Number>,
-    pub lp_time: Option<Number>,
-    pub ask: Option<Number>,
-    pub ask_size: Option<Number>,
-    pub bid: Option<Number>,
-    pub bid_size: Option<Number>,
+    pub volume: Option<f64>,
+    pub ch: Option<f64>,
+    pub chp: Option<f64>,
+    pub rch: Option<f64>,
+    pub rchp: Option<f64>,
+    pub rtc: Option<f64>,
+    pub rtc_time: Option<TradingViewTimestamp>,
+    pub lp: Option<f64>,
+    pub lp_time: Option<TradingViewTimestamp>,
+    pub ask: Option<f64>,
+    pub ask_size: Option<f64>,
+    pub bid: Option<f64>,
+    pub bid_size: Option<f64>,
     pub trade_loaded: Option<bool>,
+    pub current_session: Option<String>,
+    /// Every field in the update, including the ones above and any this client has no variant for
+    pub fields: QuoteFieldMap,
+    /// Fields `quote_set_fields` asked for that this update doesn't carry, updates after the first only carry what changed
+    pub missing_fields: Vec<QuoteField>,
+    /// Fields in this update that `quote_set_fields` didn't ask for
+    pub unexpected_fields: Vec<QuoteField>,
 }
 
 #[derive(Debug, Clone, Serialize)]
@@ -35,6 +52,10 @@ pub struct DataUpdateMessage {
     pub update_key: String,
     pub series_updates: Option<Vec<SeriesUpdate>>,
     pub study_updates: Option<Vec<StudyUpdate>>,
+    /// `study_updates` with the values named after the study's plots
+    pub study_rows: Option<Vec<StudyRow>>,
+    /// Labels, lines, boxes and tables the study drew, from its `ns` payload
+    pub graphics: Option<PineGraphics>,
 }
 
 #[derive(Debug, Clone, Serialize)]
@@ -45,92 +66,210 @@ pub struct QuoteCompletedMessage {
 
 #[derive(Debug, Clone, Serialize)]
 pub struct TimescaleUpdate {
-    pub index: Number,
-    pub timestamp: Number,
-    pub open: Number,
-    pub high: Number,
-    pub low: Number,
-    pub close: Number,
-    pub volume: Number,
+    pub index: i64,
+    pub timestamp: TradingViewTimestamp,
+    pub open: f64,
+    pub high: f64,
+    pub low: f64,
+    pub close: f64,
+    pub volume: f64,
 }
 
 #[derive(Debug, Clone, Serialize)]
 pub struct SeriesUpdate {
-    pub index: Number,
-    pub timestamp: Number,
-    pub open: Number,
-    pub high: Number,
-    pub low: Number,
-    pub close: Number,
-    pub volume: Number,
+    pub index: i64,
+    pub timestamp: TradingViewTimestamp,
+    pub open: f64,
+    pub high: f64,
+    pub low: f64,
+    pub close: f64,
+    pub volume: f64,
 }
 
 #[derive(Debug, Clone, Serialize)]
 pub struct StudyUpdate {
-    pub index: Number,
-    pub values: Vec<Number>,
+    pub index: i64,
+    /// The bar timestamp followed by one value per plot
+    pub values: Vec<f64>,
+}
+
+/// A labelled point on the time axis, `[weight, index, time]` on the wire.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TimescaleMark {
+    /// Higher for coarser boundaries (year > month > day > ...), decides which labels survive when zoomed out
+    pub weight: i64,
+    pub index: i64,
+    pub timestamp: TradingViewTimestamp,
+}
+
+/// The shared time scale of a chart session after a `timescale_update`. Every series and study of the session
+/// indexes its bars on it.
+#[derive(Debug, Clone, Serialize)]
+pub struct TimescaleChanges {
+    /// Index of the newest point
+    pub index: i64,
+    /// How many points this update prepended, every index assigned before it is now `zoffset` higher
+    pub zoffset: i64,
+    /// Open times of the points added or changed
+    pub changes: Vec<TradingViewTimestamp>,
+    pub marks: Vec<TimescaleMark>,
 }
 
 #[derive(Debug, Clone, Serialize)]
 pub struct TimescaleUpdatedMessage {
     pub chart_session_id: String,
     pub update_key: Option<String>,
-    pub updates: Option<Vec<TimescaleUpdate>>
+    pub updates: Option<Vec<TimescaleUpdate>>,
+    pub timescale: Option<TimescaleChanges>
 }
 
+/// First message on every connection, the only one without an `m`.
 #[derive(Debug, Clone, Serialize)]
 pub struct ServerHelloMessage {
+    pub session_id: Option<String>,
+    /// Server clock when the hello was sent, with millisecond precision when the server included `timestampMs`
+    pub timestamp: Option<TradingViewTimestamp>,
+    /// Image the server runs, e.g. `registry.xtools.tv/tvbs_release/webchart:release_206-21`
+    pub release: Option<String>,
+    pub studies_metadata_hash: Option<String>,
+    /// Wire protocol, `json`
+    pub protocol: Option<String>,
+    pub auth_scheme_version: Option<i64>,
+    /// Address of the front end the connection landed on
+    pub via: Option<String>,
+    /// Versions of the java studies engine, newest last
+    pub javastudies: Vec<String>,
+}
 
+/// How the server keeps a completed series or study up to date.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TradingViewUpdateMode {
+    /// Every change is pushed as it happens
+    Streaming,
+    /// Changes are batched and pushed every `rt_update_period`, as for delayed or throttled data
+    Pulsed,
+    /// Any mode this client doesn't know yet
+    Other(String),
+}
+
+impl TradingViewUpdateMode {
+    pub fn parse(value: &str) -> Self {
+        match value {
+            "streaming" => TradingViewUpdateMode::Streaming,
+            "pulsed" => TradingViewUpdateMode::Pulsed,
+            other => TradingViewUpdateMode::Other(other.to_string()),
+        }
+    }
+
+    pub fn as_str(&self) -> &str {
+        match self {
+            TradingViewUpdateMode::Streaming => "streaming",
+            TradingViewUpdateMode::Pulsed => "pulsed",
+            TradingViewUpdateMode::Other(other) => other,
+        }
+    }
+}
+
+// serialized as the wire string
+impl Serialize for TradingViewUpdateMode {
+    fn begin(&self) -> Fragment<'_> {
+        Fragment::Str(self.as_str().into())
+    }
 }
 
 #[derive(Debug, Clone, Serialize)]
 pub struct SeriesLoadingMessage {
-
+    pub chart_session_id: String,
+    pub series_id: String,
+    /// Turnaround id of the `create_series`/`modify_series` that started the load
+    pub turnaround: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize)]
 pub struct SymbolResolvedMessage {
-
+    pub chart_session_id: String,
+    pub symbol_id: String,
+    pub symbol_info: SymbolInfo,
 }
 
 #[derive(Debug, Clone, Serialize)]
 pub struct SeriesCompletedMessage {
-
+    pub chart_session_id: String,
+    pub series_id: String,
+    pub update_mode: Option<TradingViewUpdateMode>,
+    pub turnaround: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize)]
 pub struct StudyLoadingMessage {
-
+    pub chart_session_id: String,
+    pub study_id: String,
+    pub turnaround: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize)]
 pub struct StudyErrorMessage {
-
+    pub chart_session_id: String,
+    pub study_id: String,
+    /// Why the study failed, e.g. a pine compile error or a study limit
+    pub error: String,
+    /// Raw JSON of the extra details object, when it isn't empty
+    pub details: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize)]
 pub struct StudyCompletedMessage {
-
+    pub chart_session_id: String,
+    pub study_id: String,
+    pub turnaround: Option<String>,
 }
 
+/// Time axis labels the server sent on its own or for a `request_more_tickmarks`.
 #[derive(Debug, Clone, Serialize)]
 pub struct TickmarkUpdateMessage {
-
+    pub chart_session_id: String,
+    /// Index of the newest point on the time scale
+    pub index: i64,
+    pub marks: Vec<TimescaleMark>,
 }
 
+/// The server rejected a command, the session it was sent on is unusable.
 #[derive(Debug, Clone, Serialize)]
 pub struct CriticalErrorMessage {
-
+    /// Empty for commands outside of a session
+    pub session_id: String,
+    /// Machine readable reason, e.g. `invalid_parameters`
+    pub code: String,
+    /// Command that was rejected, e.g. `create_series`
+    pub method: Option<String>,
 }
 
+/// The server could not make sense of a message and is about to drop the connection.
 #[derive(Debug, Clone, Serialize)]
 pub struct ProtocolErrorMessage {
+    pub error: String,
+}
 
+/// What a `notify_user` is about, from the type the server tagged it with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum TradingViewNotificationKind {
+    /// The account has more sessions open than its plan allows, one of them gets closed
+    TooManySessions,
+    /// The data isn't real time, usually because the account has no subscription for the exchange
+    DataDelayed,
+    /// Some other plan limit was hit: studies, symbols, history depth...
+    PlanLimit,
+    Other,
 }
 
+/// A notice the server shows to the user.
 #[derive(Debug, Clone, Serialize)]
 pub struct NotifyUserMessage {
-
+    /// Type the server tagged the notice with, empty when it sent none
+    pub notification_type: String,
+    pub text: String,
+    /// Raw JSON of the params, for the fields this client doesn't decode
+    pub params: String,
 }
 
 #[derive(Debug, Clone, EnumAsInner)]
@@ -151,70 +290,429 @@ pub enum ParsedTradingViewMessage {
     CriticalError(CriticalErrorMessage),
     ProtocolError(ProtocolErrorMessage),
     NotifyUser(NotifyUserMessage),
+    /// A message type this client doesn't know yet, passed through untouched
+    Unknown {
+        raw: String
+    },
+}
+
+/// Plucks the leading `[session_id, object_id, ...]` pair most chart session replies carry in `p`.
+fn parse_session_and_object_ids(parsed_message: &Object) -> TradingViewResult<(String, String)> {
+    let p = json_utilities::value_to_array(json_utilities::get_key(parsed_message, "p")?)?;
+    let session_id = json_utilities::value_to_string(json_utilities::get_index(&p, 0)?)?;
+    let object_id = json_utilities::value_to_string(json_utilities::get_index(&p, 1)?)?;
+    Ok((session_id, object_id))
+}
+
+/// String param at `index` of `p`, `None` when it's missing or not a string.
+fn optional_string_param(parsed_message: &Object, index: usize) -> TradingViewResult<Option<String>> {
+    let p = json_utilities::value_to_array(json_utilities::get_key(parsed_message, "p")?)?;
+    match p.get(index) {
+        Some(Value::String(value)) => Ok(Some(value.clone())),
+        _ => Ok(None),
+    }
+}
+
+/// A string param as is, anything else as its JSON text.
+fn value_to_text(value: &Value) -> String {
+    match value {
+        Value::String(value) => value.clone(),
+        value => miniserde::json::to_string(value),
+    }
+}
+
+impl TimescaleUpdatedMessage {
+    /// How far the indexes assigned before this update moved, 0 when the message didn't say.
+    pub fn zoffset(&self) -> i64 {
+        self.timescale.as_ref().map(|timescale| timescale.zoffset).unwrap_or_default()
+    }
+}
+
+impl TimescaleMark {
+    pub fn from_value(value: &Value) -> TradingViewResult<Self> {
+        let mark = json_utilities::value_to_array(value)?;
+        Ok(TimescaleMark {
+            weight: json_utilities::value_to_i64(json_utilities::get_index(&mark, 0)?)?,
+            index: json_utilities::value_to_i64(json_utilities::get_index(&mark, 1)?)?,
+            timestamp: TradingViewTimestamp::Seconds(json_utilities::value_to_i64(json_utilities::get_index(&mark, 2)?)?),
+        })
+    }
+}
+
+impl TimescaleChanges {
+    /// Decodes the `{"index", "zoffset", "changes", "marks"}` object that ends a `timescale_update`.
+    pub fn from_object(object: &Object) -> TradingViewResult<Self> {
+        let changes = match object.get("changes") {
+            Some(changes) => json_utilities::value_to_array(changes)?
+                .iter()
+                .map(|change| Ok(TradingViewTimestamp::Seconds(json_utilities::value_to_i64(change)?)))
+                .collect::<TradingViewResult<Vec<_>>>()?,
+            None => vec![]
+        };
+        let marks = match object.get("marks") {
+            Some(marks) => json_utilities::value_to_array(marks)?
+                .iter()
+                .map(TimescaleMark::from_value)
+                .collect::<TradingViewResult<Vec<_>>>()?,
+            None => vec![]
+        };
+        Ok(TimescaleChanges {
+            index: json_utilities::get_optional_i64(object, "index")?.unwrap_or_default(),
+            zoffset: json_utilities::get_optional_i64(object, "zoffset")?.unwrap_or_default(),
+            changes,
+            marks,
+        })
+    }
+}
+
+/// `{"i": index, "v": [timestamp, open, high, low, close, volume]}` as found in series and timescale updates
+fn parse_bar(element: &Value) -> TradingViewResult<(i64, TradingViewTimestamp, [f64; 5])> {
+    // value -> object
+    let element = json_utilities::value_to_object(element)?;
+
+    // pluck i (index)
+    let i = json_utilities::value_to_i64(json_utilities::get_key(&element, "i")?)?;
+
+    // pluck v (values)
+    let v = json_utilities::value_to_array(json_utilities::get_key(&element, "v")?)?;
+    if v.len() < 6 {
+        return Err(TradingViewError::Schema(format!("expected 6 bar values, got {}", v.len())));
+    }
+
+    // pluck out of values
+    let timestamp = TradingViewTimestamp::Seconds(json_utilities::value_to_i64(&v[0])?);
+    let open = json_utilities::value_to_f64(&v[1])?;
+    let high = json_utilities::value_to_f64(&v[2])?;
+    let low = json_utilities::value_to_f64(&v[3])?;
+    let close = json_utilities::value_to_f64(&v[4])?;
+    let volume = json_utilities::value_to_f64(&v[5])?;
+    Ok((i, timestamp, [open, high, low, close, volume]))
+}
+
+impl ServerHelloMessage {
+    pub fn from_object(object: &Object) -> TradingViewResult<Self> {
+        let timestamp = match json_utilities::get_optional_i64(object, "timestampMs")? {
+            Some(milliseconds) => Some(TradingViewTimestamp::Milliseconds(milliseconds)),
+            None => json_utilities::get_optional_i64(object, "timestamp")?.map(TradingViewTimestamp::Seconds),
+        };
+        let javastudies = match object.get("javastudies") {
+            Some(Value::Array(javastudies)) => javastudies.iter().map(json_utilities::value_to_string).collect::<TradingViewResult<Vec<_>>>()?,
+            Some(Value::String(javastudies)) => vec![javastudies.clone()],
+            _ => vec![],
+        };
+        Ok(ServerHelloMessage {
+            session_id: json_utilities::get_optional_string(object, "session_id")?,
+            timestamp,
+            release: json_utilities::get_optional_string(object, "release")?,
+            studies_metadata_hash: json_utilities::get_optional_string(object, "studies_metadata_hash")?,
+            protocol: json_utilities::get_optional_string(object, "protocol")?,
+            auth_scheme_version: json_utilities::get_optional_i64(object, "auth_scheme_vsn")?,
+            via: json_utilities::get_optional_string(object, "via")?,
+            javastudies,
+        })
+    }
+
+    /// Build part of `release`, the tag after the image name (`release_206-21`).
+    pub fn build(&self) -> Option<&str> {
+        let release = self.release.as_deref()?;
+        Some(release.rsplit_once(':').map(|(_, build)| build).unwrap_or(release))
+    }
+
+    /// Newest java studies engine version.
+    pub fn javastudies_version(&self) -> Option<&str> {
+        self.javastudies.last().map(|version| version.as_str())
+    }
+
+    /// Milliseconds the server clock is ahead of the local one (negative when behind), given the local time the hello arrived at.
+    /// Includes the one way latency of the hello, so it overstates the offset by that much.
+    pub fn clock_offset_ms(&self, received_at: SystemTime) -> Option<i64> {
+        let server_ms = self.timestamp?.milliseconds();
+        let local_ms = match received_at.duration_since(UNIX_EPOCH) {
+            Ok(since_epoch) => since_epoch.as_millis() as i64,
+            Err(before_epoch) => -(before_epoch.duration().as_millis() as i64),
+        };
+        Some(server_ms - local_ms)
+    }
+}
+
+/// Notification types the server tags session limit notices with.
+const TOO_MANY_SESSIONS_TYPES: &[&str] = &["session_limit", "too_many_sessions"];
+
+/// Notification types for data that isn't real time.
+const DATA_DELAYED_TYPES: &[&str] = &["data_delayed", "delayed_data"];
+
+/// Notification types for the other plan limits.
+const PLAN_LIMIT_TYPES: &[&str] = &["plan_limit", "limit_exceeded", "upgrade_required"];
+
+impl NotifyUserMessage {
+    /// Maps the server's notification type, the text is never looked at. Unknown or missing types are `Other`.
+    pub fn kind(&self) -> TradingViewNotificationKind {
+        let notification_type = self.notification_type.as_str();
+        if TOO_MANY_SESSIONS_TYPES.contains(&notification_type) {
+            TradingViewNotificationKind::TooManySessions
+        } else if DATA_DELAYED_TYPES.contains(&notification_type) {
+            TradingViewNotificationKind::DataDelayed
+        } else if PLAN_LIMIT_TYPES.contains(&notification_type) {
+            TradingViewNotificationKind::PlanLimit
+        } else {
+            TradingViewNotificationKind::Other
+        }
+    }
+}
+
+/// Command whose rejection means the auth token was refused.
+const AUTH_METHOD: &str = "set_auth_token";
+
+/// Error codes TradingView sends for a bad or expired auth token.
+const AUTH_ERROR_CODES: &[&str] = &["invalid_auth_token", "auth_token_expired", "unauthorized_access"];
+
+/// Whether an error code is one of the known auth token rejections.
+fn is_auth_error_code(code: &str) -> bool {
+    AUTH_ERROR_CODES.contains(&code)
 }
 
 impl ParsedTradingViewMessage {
-    pub fn from_string(value: &str) -> anyhow::Result<Self> {
+    /// The error a `study_error`, `critical_error` or `protocol_error` stands for, `None` for every other message.
+    pub fn to_error(&self) -> Option<TradingViewError> {
+        match self {
+            ParsedTradingViewMessage::StudyError(message) => Some(TradingViewError::StudyFailed {
+                chart_session_id: message.chart_session_id.clone(),
+                study_id: message.study_id.clone(),
+                error: message.error.clone(),
+            }),
+            ParsedTradingViewMessage::CriticalError(message) => {
+                if is_auth_error_code(&message.code) || message.method.as_deref() == Some(AUTH_METHOD) {
+                    Some(TradingViewError::Unauthorized(message.code.clone()))
+                } else {
+                    Some(TradingViewError::Rejected {
+                        session_id: message.session_id.clone(),
+                        code: message.code.clone(),
+                        method: message.method.clone(),
+                    })
+                }
+            },
+            ParsedTradingViewMessage::ProtocolError(message) => {
+                if is_auth_error_code(&message.error) {
+                    Some(TradingViewError::Unauthorized(message.error.clone()))
+                } else {
+                    Some(TradingViewError::ServerProtocol(message.error.clone()))
+                }
+            },
+            _ => None
+        }
+    }
+
+    /// The protocol-level message type, used to route replies to whoever is waiting on them.
+    pub fn message_type(&self) -> &'static str {
+        match self {
+            ParsedTradingViewMessage::ServerHello(_) => "server_hello",
+            ParsedTradingViewMessage::Ping(_) => "ping",
+            ParsedTradingViewMessage::QuoteSeriesData(_) => "qsd",
+            ParsedTradingViewMessage::DataUpdate(_) => "du",
+            ParsedTradingViewMessage::QuoteCompleted(_) => "quote_completed",
+            ParsedTradingViewMessage::TimescaleUpdate(_) => "timescale_update",
+            ParsedTradingViewMessage::SeriesLoading(_) => "series_loading",
+            ParsedTradingViewMessage::SymbolResolved(_) => "symbol_resolved",
+            ParsedTradingViewMessage::SeriesCompleted(_) => "series_completed",
+            ParsedTradingViewMessage::StudyLoading(_) => "study_loading",
+            ParsedTradingViewMessage::StudyError(_) => "study_error",
+            ParsedTradingViewMessage::StudyCompleted(_) => "study_completed",
+            ParsedTradingViewMessage::TickmarkUpdate(_) => "tickmark_update",
+            ParsedTradingViewMessage::CriticalError(_) => "critical_error",
+            ParsedTradingViewMessage::ProtocolError(_) => "protocol_error",
+            ParsedTradingViewMessage::NotifyUser(_) => "notify_user",
+            ParsedTradingViewMessage::Unknown { .. } => "unknown",
+        }
+    }
+
+    /// Decodes the update of one object of a `du`, `raw` is the whole frame.
+    fn from_data_update(chart_session_id: &str, update_key: &str, update_value: &Object, registry: &TradingViewSessionRegistry, raw: &str) -> TradingViewResult<Self> {
+        // the registry knows what every id is, fall back to the payload shape for objects it never saw created
+        let kind = registry.lookup(chart_session_id, update_key).or_else(|| {
+            // series updates carry an (empty) ns too, so only a lone ns means a study
+            if update_value.contains_key("st") {
+                Some(TradingViewSessionObjectKind::Study)
+            } else if update_value.contains_key("s") {
+                Some(TradingViewSessionObjectKind::Series)
+            } else if update_value.contains_key("ns") {
+                Some(TradingViewSessionObjectKind::Study)
+            } else {
+                None
+            }
+        });
+        match kind {
+            Some(TradingViewSessionObjectKind::Series) => {
+                let series_updates = match update_value.get("s") {
+                    Some(s) => {
+                        let s = json_utilities::value_to_array(s)?;
+                        let series_updates = s.iter().map(|element| {
+                            let (index, timestamp, [open, high, low, close, volume]) = parse_bar(element)?;
+                            Ok(SeriesUpdate {
+                                index,
+                                timestamp,
+                                open,
+                                high,
+                                low,
+                                close,
+                                volume,
+                            })
+                        }).collect::<TradingViewResult<Vec<_>>>()?;
+                        Some(series_updates)
+                    },
+                    None => None
+                };
+                Ok(ParsedTradingViewMessage::DataUpdate(DataUpdateMessage {
+                    chart_session_id: chart_session_id.to_string(),
+                    update_key: update_key.to_string(),
+                    series_updates,
+                    study_updates: None,
+                    study_rows: None,
+                    graphics: None
+                }))
+            },
+            Some(TradingViewSessionObjectKind::Study) => {
+                let study_updates = match update_value.get("st") {
+                    Some(st) => {
+                        let st = json_utilities::value_to_array(st)?;
+                        let study_updates = st.iter().map(|element| {
+                            // value -> object
+                            let element = json_utilities::value_to_object(element)?;
+
+                            // pluck i (index)
+                            let i = json_utilities::value_to_i64(json_utilities::get_key(&element, "i")?)?;
+
+                            // pluck v (values)
+                            let v = json_utilities::value_to_array(json_utilities::get_key(&element, "v")?)?;
+                            let v = v.iter().map(json_utilities::value_to_f64).collect::<TradingViewResult<Vec<_>>>()?;
+
+                            Ok(StudyUpdate {
+                                index: i,
+                                values: v
+                            })
+                        }).collect::<TradingViewResult<Vec<_>>>()?;
+                        Some(study_updates)
+                    },
+                    None => None
+                };
+                let study_rows = match &study_updates {
+                    Some(study_updates) => {
+                        let metadata = registry.study_metadata(chart_session_id, update_key);
+                        Some(study_updates.iter().map(|study_update| StudyRow::from_update(study_update, metadata.as_ref())).collect::<TradingViewResult<Vec<_>>>()?)
+                    },
+                    None => None
+                };
+                // non-series output (labels, lines, boxes, tables) rides along in ns
+                let graphics = match update_value.get("ns") {
+                    Some(ns) => Some(PineGraphics::from_ns(&json_utilities::value_to_object(ns)?)?),
+                    None => None
+                };
+                Ok(ParsedTradingViewMessage::DataUpdate(DataUpdateMessage {
+                    chart_session_id: chart_session_id.to_string(),
+                    update_key: update_key.to_string(),
+                    series_updates: None,
+                    study_updates,
+                    study_rows,
+                    graphics
+                }))
+            },
+            None => {
+                log::warn!("du with unknown update_key = {update_key}");
+                Ok(ParsedTradingViewMessage::Unknown { raw: raw.to_string() })
+            }
+        }
+    }
+
+    /// Parses a message without knowing which series and studies exist, `du` updates are told apart by their shape. Like
+    /// `from_string_with_registry`, only the first message of a batched frame is returned.
+    pub fn from_string(value: &str) -> TradingViewResult<Self> {
+        Self::from_string_with_registry(value, &TradingViewSessionRegistry::new())
+    }
+
+    /// Parses a frame, using `registry` to tell which `du` update keys are series and which are studies. A `du` or
+    /// `timescale_update` batching several objects comes back as its first object only, in frame order, the rest are dropped;
+    /// use `messages_from_string_with_registry` to get all of them.
+    pub fn from_string_with_registry(value: &str, registry: &TradingViewSessionRegistry) -> TradingViewResult<Self> {
+        let mut messages = Self::messages_from_string_with_registry(value, registry)?.into_iter();
+        messages.next().ok_or(TradingViewError::Schema(format!("frame without messages: {value}")))
+    }
+
+    /// Same as `messages_from_string_with_registry`, without knowing which series and studies exist.
+    pub fn messages_from_string(value: &str) -> TradingViewResult<Vec<Self>> {
+        Self::messages_from_string_with_registry(value, &TradingViewSessionRegistry::new())
+    }
+
+    /// Parses a frame into its messages. `du` and `timescale_update` batch the updates of several series and studies of a chart
+    /// session in one frame, they come out as one message per object, in frame order. Every other frame is a single message.
+    pub fn messages_from_string_with_registry(value: &str, registry: &TradingViewSessionRegistry) -> TradingViewResult<Vec<Self>> {
         log::trace!("value = {value}");
 
         // ping messages are not json
-        if value.starts_with("~h~") {
-            let nonce_str = &value[3..];
-            let nonce = nonce_str.parse::<usize>().map_err(|_| anyhow::anyhow!("failed to parse nonce"))?;
-            return Ok(ParsedTradingViewMessage::Ping(nonce));
+        if let Some(nonce_str) = value.strip_prefix("~h~") {
+            let nonce = nonce_str.parse::<usize>().map_err(|_| TradingViewError::Schema(format!("failed to parse nonce {nonce_str}")))?;
+            return Ok(vec![ParsedTradingViewMessage::Ping(nonce)]);
         }
 
         // all other messages are json
-        let parsed_message: Object = miniserde::json::from_str(&value)?;
+        let parsed_message: Object = miniserde::json::from_str(value)?;
 
         // check for server hello message
-        if parsed_message.contains_key("javastudies") {
-            return Ok(ParsedTradingViewMessage::ServerHello(ServerHelloMessage {
-
-            }));
+        if !parsed_message.contains_key("m") && (parsed_message.contains_key("javastudies") || parsed_message.contains_key("session_id")) {
+            return Ok(vec![ParsedTradingViewMessage::ServerHello(ServerHelloMessage::from_object(&parsed_message)?)]);
         }
         
         // all other messages have m property
-        let message_type = parsed_message.get("m").ok_or(anyhow::anyhow!("failed to get message_type"))?;
-        let message_type = json_utilities::value_to_string(message_type)?;
-        if message_type == "qsd" {
+        let message_type = match parsed_message.get("m") {
+            Some(message_type) => json_utilities::value_to_string(message_type)?,
+            None => {
+                log::warn!("message without message_type = {value}");
+                return Ok(vec![ParsedTradingViewMessage::Unknown { raw: value.to_string() }]);
+            }
+        };
+        let message = if message_type == "qsd" {
             //log::info!("qsd = {parsed_message:?}");
-            let p = parsed_message.get("p").ok_or(anyhow::anyhow!("failed to get p"))?;
-            let p = json_utilities::value_to_array(p)?;
-            let quote_session_id = &p[0];
-            let quote_session_id = json_utilities::value_to_string(&quote_session_id)?;
-            let update = &p[1];
-            let update = json_utilities::value_to_object(&update)?;
-            let symbol = json_utilities::value_to_string(update.get("n").ok_or(anyhow::anyhow!("failed to get n"))?)?;
-            let v = json_utilities::value_to_object(update.get("v").ok_or(anyhow::anyhow!("failed to get v"))?)?;
-            //let v_keys = v.keys().collect::<Vec<&String>>();
+            let p = json_utilities::value_to_array(json_utilities::get_key(&parsed_message, "p")?)?;
+            let quote_session_id = json_utilities::value_to_string(json_utilities::get_index(&p, 0)?)?;
+            let update = json_utilities::value_to_object(json_utilities::get_index(&p, 1)?)?;
+            let symbol = json_utilities::value_to_string(json_utilities::get_key(&update, "n")?)?;
+            let v = json_utilities::value_to_object(json_utilities::get_key(&update, "v")?)?;
+            let fields = QuoteFieldMap::from_object(&v);
+
+            // the registry knows what quote_set_fields asked for on this session, the fields are kept either way
+            let (missing_fields, unexpected_fields) = match registry.quote_fields(&quote_session_id) {
+                Some(requested_fields) => (fields.missing(&requested_fields), fields.unexpected(&requested_fields)),
+                None => (vec![], vec![])
+            };
             // TODO: check more combinations
             let quote_series_data_update = QuoteSeriesDataUpdate {
                 symbol,
 
-                volume: if v.contains_key("volume") { Some(json_utilities::value_to_number(v.get("volume").ok_or(anyhow::anyhow!("failed to get v"))?)?) } else { None },
+                volume: json_utilities::get_optional_f64(&v, "volume")?,
+
+                ch: json_utilities::get_optional_f64(&v, "ch")?,
+                chp: json_utilities::get_optional_f64(&v, "chp")?,
 
-                ch: if v.contains_key("ch") { Some(json_utilities::value_to_number(v.get("ch").ok_or(anyhow::anyhow!("failed to get ch"))?)?) } else { None },
-                chp: if v.contains_key("chp") { Some(json_utilities::value_to_number(v.get("chp").ok_or(anyhow::anyhow!("failed to get chp"))?)?) } else { None },
+                rch: json_utilities::get_optional_f64(&v, "rch")?,
+                rchp: json_utilities::get_optional_f64(&v, "rchp")?,
 
-                rch: if v.contains_key("rch") && !json_utilities::is_null(&v, "rch")? { Some(json_utilities::value_to_number(v.get("rch").ok_or(anyhow::anyhow!("failed to get rch"))?)?) } else { None },
-                rchp: if v.contains_key("rchp") && !json_utilities::is_null(&v, "rchp")? { Some(json_utilities::value_to_number(v.get("rchp").ok_or(anyhow::anyhow!("failed to get rchp"))?)?) } else { None },
+                lp: json_utilities::get_optional_f64(&v, "lp")?,
+                lp_time: json_utilities::get_optional_i64(&v, "lp_time")?.map(TradingViewTimestamp::Seconds),
 
-                lp: if v.contains_key("lp") { Some(json_utilities::value_to_number(v.get("lp").ok_or(anyhow::anyhow!("failed to get lp"))?)?) } else { None },
-                lp_time: if v.contains_key("lp_time") { Some(json_utilities::value_to_number(v.get("lp_time").ok_or(anyhow::anyhow!("failed to get lp_time"))?)?) } else { None },
+                rtc: json_utilities::get_optional_f64(&v, "rtc")?,
+                rtc_time: json_utilities::get_optional_i64(&v, "rtc_time")?.map(TradingViewTimestamp::Seconds),
 
-                rtc: if v.contains_key("rtc") && !json_utilities::is_null(&v, "rtc")? { Some(json_utilities::value_to_number(v.get("rtc").ok_or(anyhow::anyhow!("failed to get rtc"))?)?) } else { None },
-                rtc_time: if v.contains_key("rtc_time") && !json_utilities::is_null(&v, "rtc_time")? { Some(json_utilities::value_to_number(v.get("rtc_time").ok_or(anyhow::anyhow!("failed to get rtc_time"))?)?) } else { None },
+                ask: json_utilities::get_optional_f64(&v, "ask")?,
+                ask_size: json_utilities::get_optional_f64(&v, "ask_size")?,
 
-                ask: if v.contains_key("ask") { Some(json_utilities::value_to_number(v.get("ask").ok_or(anyhow::anyhow!("failed to get ask"))?)?) } else { None },
-                ask_size: if v.contains_key("ask_size") { Some(json_utilities::value_to_number(v.get("ask_size").ok_or(anyhow::anyhow!("failed to get ask_size"))?)?) } else { None },
+                bid: json_utilities::get_optional_f64(&v, "bid")?,
+                bid_size: json_utilities::get_optional_f64(&v, "bid_size")?,
 
-                bid: if v.contains_key("bid") { Some(json_utilities::value_to_number(v.get("bid").ok_or(anyhow::anyhow!("failed to get bid"))?)?) } else { None },
-                bid_size: if v.contains_key("bid_size") { Some(json_utilities::value_to_number(v.get("bid_size").ok_or(anyhow::anyhow!("failed to get bid_size"))?)?) } else { None },
+                trade_loaded: json_utilities::get_optional_bool(&v, "trade_loaded")?,
 
-                trade_loaded: if v.contains_key("trade_loaded") { Some(json_utilities::value_to_bool(v.get("trade_loaded").ok_or(anyhow::anyhow!("failed to get trade_loaded"))?)?) } else { None },
+                current_session: json_utilities::get_optional_string(&v, "current_session")?,
 
-                // TODO: more fields?
+                fields,
+                missing_fields,
+                unexpected_fields,
             };
             Ok(ParsedTradingViewMessage::QuoteSeriesData(QuoteSeriesDataMessage {
                 quote_session_id,
@@ -222,221 +720,325 @@ impl ParsedTradingViewMessage {
             }))
         } else if message_type == "du" {
             //log::info!("du = {parsed_message:?}");
-            let p = parsed_message.get("p").ok_or(anyhow::anyhow!("failed to get p"))?;
-            let p = json_utilities::value_to_array(p)?;
-            let chart_session_id = &p[0];
-            let chart_session_id = json_utilities::value_to_string(&chart_session_id)?;
-            let update = &p[1];
-            let update = json_utilities::value_to_object(&update)?;
-            let update_keys = update.keys().collect::<Vec<&String>>();
-            assert!(update_keys.len() == 1);
-            let update_key = update_keys[0];
-            if update_key == "sds_1" { // series
-                let update_value = json_utilities::value_to_object(update.get(update_key).ok_or(anyhow::anyhow!("failed to get update_key"))?)?;
-                if update_value.contains_key("s") {
-                    let s = update_value.get("s").ok_or(anyhow::anyhow!("failed to get s"))?;
-                    let s = json_utilities::value_to_array(s)?;
-                    let series_updates = s.iter().map(|element| {
-                        // value -> object
-                        let element = json_utilities::value_to_object(&element).expect("failed to cast");
-                        
-                        // pluck i (index)
-                        let i = element.get("i").expect("failed to get i");
-                        let i = json_utilities::value_to_number(i).expect("failed to cast");
-    
-                        // pluck v (values)
-                        let v = element.get("v").expect("failed to get v");
-                        let v = json_utilities::value_to_array(v).expect("failed to cast");
-    
-                        // pluck out of values
-                        let timestamp = json_utilities::value_to_number(&v[0]).expect("failed to cast");
-                        let open = json_utilities::value_to_number(&v[1]).expect("failed to cast");
-                        let high = json_utilities::value_to_number(&v[2]).expect("failed to cast");
-                        let low = json_utilities::value_to_number(&v[3]).expect("failed to cast");
-                        let close = json_utilities::value_to_number(&v[4]).expect("failed to cast");
-                        let volume = json_utilities::value_to_number(&v[5]).expect("failed to cast");
-    
-                        // return
-                        SeriesUpdate {
-                            index: i,
-                            timestamp,
-                            open,
-                            high,
-                            low,
-                            close,
-                            volume,
-                        }
-                    }).collect::<Vec<_>>();
-                    Ok(ParsedTradingViewMessage::DataUpdate(DataUpdateMessage {
-                        chart_session_id,
-                        update_key: update_key.to_string(),
-                        series_updates: Some(series_updates),
-                        study_updates: None
-                    }))
-                } else {
-                    // watch out for weird du message with no updates on it? ns property
-                    Ok(ParsedTradingViewMessage::DataUpdate(DataUpdateMessage {
-                        chart_session_id,
-                        update_key: update_key.to_string(),
-                        series_updates: None,
-                        study_updates: None
-                    }))
-                }
-            } else if update_key == "st1" || update_key == "st2" { // study
-                let update_value = json_utilities::value_to_object(update.get(update_key).ok_or(anyhow::anyhow!("failed to get update_key"))?)?;
-                let st = update_value.get("st").ok_or(anyhow::anyhow!("failed to get st"))?;
-                let st = json_utilities::value_to_array(st)?;
-                let study_updates = st.iter().map(|element| {
-                    // value -> object
-                    let element = json_utilities::value_to_object(&element).expect("failed to cast");
-
-                    // pluck i (index)
-                    let i = element.get("i").expect("failed to get i");
-                    let i = json_utilities::value_to_number(i).expect("failed to cast");
-
-                    // pluck v (values)
-                    let v = element.get("v").expect("failed to get v");
-                    let v = json_utilities::value_to_array(v).expect("failed to cast");
-                    let v = v.iter().map(|value| json_utilities::value_to_number(value).expect("failed to cast")).collect::<Vec<_>>();
-
-                    StudyUpdate {
-                        index: i,
-                        values: v
-                    }
-                }).collect::<Vec<_>>();
-                Ok(ParsedTradingViewMessage::DataUpdate(DataUpdateMessage {
-                    chart_session_id,
-                    update_key: update_key.to_string(),
-                    series_updates: None,
-                    study_updates: Some(study_updates)
-                }))
-            } else {
-                todo!("update_key = {update_key}");
-            }
+            // p = [chart_session_id, {update_key: update, ...}], one frame can carry a series and all of its studies
+            let p = json_utilities::value_to_array(json_utilities::get_key(&parsed_message, "p")?)?;
+            let chart_session_id = json_utilities::value_to_string(json_utilities::get_index(&p, 0)?)?;
+            let update = json_utilities::value_to_object(json_utilities::get_index(&p, 1)?)?;
+            return update.iter()
+                .map(|(update_key, update_value)| Self::from_data_update(&chart_session_id, update_key, &json_utilities::value_to_object(update_value)?, registry, value))
+                .collect();
         } else if message_type == "quote_completed" {
             //log::info!("quote_completed = {parsed_message:?}");
-            let p = parsed_message.get("p").ok_or(anyhow::anyhow!("failed to get p"))?;
-            let p = json_utilities::value_to_array(p)?;
-            let quote_session_id = &p[0];
-            let quote_session_id = json_utilities::value_to_string(&quote_session_id)?;
-            let symbol = &p[1];
-            let symbol = json_utilities::value_to_string(&symbol)?;
+            let (quote_session_id, symbol) = parse_session_and_object_ids(&parsed_message)?;
             Ok(ParsedTradingViewMessage::QuoteCompleted(QuoteCompletedMessage {
                 quote_session_id,
                 symbol
             }))
         } else if message_type == "timescale_update" {
             //log::info!("timescale_update parsed_message = {parsed_message:?}");
-            let p = parsed_message.get("p").ok_or(anyhow::anyhow!("failed to get p"))?;
-            let p = json_utilities::value_to_array(p)?;
-            let chart_session_id = &p[0];
-            let chart_session_id = json_utilities::value_to_string(&chart_session_id)?;
-            let update = &p[1];
-            let update = json_utilities::value_to_object(&update)?;
-            let update_keys = update.keys().collect::<Vec<&String>>();
-            if update_keys.len() == 0 {
-                // weird timescale_update with index/zoffset/changes/marks but nothing of any interest/importance
-                Ok(ParsedTradingViewMessage::TimescaleUpdate(TimescaleUpdatedMessage {
+            // p = [chart_session_id, {series_id: {"s": bars, ...}}, {"index", "zoffset", "changes", "marks"}]
+            let p = json_utilities::value_to_array(json_utilities::get_key(&parsed_message, "p")?)?;
+            let chart_session_id = json_utilities::value_to_string(json_utilities::get_index(&p, 0)?)?;
+            let update = json_utilities::value_to_object(json_utilities::get_index(&p, 1)?)?;
+            let timescale = match p.get(2) {
+                Some(Value::Object(timescale)) => Some(TimescaleChanges::from_object(timescale)?),
+                _ => None
+            };
+            if update.is_empty() {
+                // only the time scale moved, e.g. history prepended for another series of the session
+                return Ok(vec![ParsedTradingViewMessage::TimescaleUpdate(TimescaleUpdatedMessage {
                     chart_session_id,
                     update_key: None,
-                    updates: None
-                }))
-            } else if update_keys.len() == 1 {
-                let update_key = update_keys[0];
-                let update_value = json_utilities::value_to_object(update.get(update_key).ok_or(anyhow::anyhow!("failed to get update_key"))?)?;
-                let s = update_value.get("s").ok_or(anyhow::anyhow!("failed to get s"))?;
-                let s = json_utilities::value_to_array(s)?;
-                let timescale_updates = s.iter().map(|element| {
-                    // value -> object
-                    let element = json_utilities::value_to_object(&element).expect("failed to cast");
-                    
-                    // pluck i (index)
-                    let i = element.get("i").expect("failed to get i");
-                    let i = json_utilities::value_to_number(i).expect("failed to cast");
-
-                    // pluck v (values)
-                    let v = element.get("v").expect("failed to get v");
-                    let v = json_utilities::value_to_array(v).expect("failed to cast");
-
-                    // pluck out of values
-                    let timestamp = json_utilities::value_to_number(&v[0]).expect("failed to cast");
-                    let open = json_utilities::value_to_number(&v[1]).expect("failed to cast");
-                    let high = json_utilities::value_to_number(&v[2]).expect("failed to cast");
-                    let low = json_utilities::value_to_number(&v[3]).expect("failed to cast");
-                    let close = json_utilities::value_to_number(&v[4]).expect("failed to cast");
-                    let volume = json_utilities::value_to_number(&v[5]).expect("failed to cast");
-
-                    // return
-                    TimescaleUpdate {
-                        index: i,
-                        timestamp,
-                        open,
-                        high,
-                        low,
-                        close,
-                        volume,
-                    }
-                }).collect::<Vec<_>>();
+                    updates: None,
+                    timescale
+                })]);
+            }
+            // the time scale moves once per frame, so only the first series carries the changes
+            let mut timescale = timescale;
+            return update.iter().map(|(update_key, update_value)| {
+                let update_value = json_utilities::value_to_object(update_value)?;
+                let updates = match update_value.get("s") {
+                    Some(s) => Some(json_utilities::value_to_array(s)?.iter().map(|element| {
+                        let (index, timestamp, [open, high, low, close, volume]) = parse_bar(element)?;
+                        Ok(TimescaleUpdate {
+                            index,
+                            timestamp,
+                            open,
+                            high,
+                            low,
+                            close,
+                            volume,
+                        })
+                    }).collect::<TradingViewResult<Vec<_>>>()?),
+                    None => None
+                };
                 Ok(ParsedTradingViewMessage::TimescaleUpdate(TimescaleUpdatedMessage {
-                    chart_session_id,
+                    chart_session_id: chart_session_id.clone(),
                     update_key: Some(update_key.to_string()),
-                    updates: Some(timescale_updates)
+                    updates,
+                    timescale: timescale.take()
                 }))
-            } else {
-                unimplemented!()
-            }
+            }).collect();
         } else if message_type == "series_loading" {
             log::info!("series_loading = {parsed_message:?}");
+            // p = [chart_session_id, series_id, turnaround]
+            let (chart_session_id, series_id) = parse_session_and_object_ids(&parsed_message)?;
             Ok(ParsedTradingViewMessage::SeriesLoading(SeriesLoadingMessage {
-                
+                chart_session_id,
+                series_id,
+                turnaround: optional_string_param(&parsed_message, 2)?
             }))
         } else if message_type == "symbol_resolved" {
-            log::info!("symbol_resolved = {parsed_message:?}");
+            let (chart_session_id, symbol_id) = parse_session_and_object_ids(&parsed_message)?;
+            let p = json_utilities::value_to_array(json_utilities::get_key(&parsed_message, "p")?)?;
+            let symbol_info = SymbolInfo::from_object(&json_utilities::value_to_object(json_utilities::get_index(&p, 2)?)?)?;
             Ok(ParsedTradingViewMessage::SymbolResolved(SymbolResolvedMessage {
-                
+                chart_session_id,
+                symbol_id,
+                symbol_info
             }))
         } else if message_type == "series_completed" {
             log::info!("series_completed = {parsed_message:?}");
+            // p = [chart_session_id, series_id, update_mode, turnaround, {"rt_update_period": ...}]
+            let (chart_session_id, series_id) = parse_session_and_object_ids(&parsed_message)?;
             Ok(ParsedTradingViewMessage::SeriesCompleted(SeriesCompletedMessage {
-                
+                chart_session_id,
+                series_id,
+                update_mode: optional_string_param(&parsed_message, 2)?.map(|update_mode| TradingViewUpdateMode::parse(&update_mode)),
+                turnaround: optional_string_param(&parsed_message, 3)?
             }))
         } else if message_type == "study_loading" {
             log::info!("study_loading = {parsed_message:?}");
+            // p = [chart_session_id, study_id, turnaround]
+            let (chart_session_id, study_id) = parse_session_and_object_ids(&parsed_message)?;
             Ok(ParsedTradingViewMessage::StudyLoading(StudyLoadingMessage {
-                
+                chart_session_id,
+                study_id,
+                turnaround: optional_string_param(&parsed_message, 2)?
             }))
         } else if message_type == "study_error" {
             log::info!("study_error = {parsed_message:?}");
+            // p = [chart_session_id, study_id, turnaround, error, details]
+            let (chart_session_id, study_id) = parse_session_and_object_ids(&parsed_message)?;
+            let p = json_utilities::value_to_array(json_utilities::get_key(&parsed_message, "p")?)?;
+            let error = p.get(3).map(value_to_text).unwrap_or_default();
+            let details = match p.get(4) {
+                Some(Value::Object(details)) if details.is_empty() => None,
+                Some(Value::Null) | None => None,
+                Some(details) => Some(value_to_text(details)),
+            };
             Ok(ParsedTradingViewMessage::StudyError(StudyErrorMessage {
-                
+                chart_session_id,
+                study_id,
+                error,
+                details
             }))
         } else if message_type == "study_completed" {
             log::info!("study_completed = {parsed_message:?}");
+            // p = [chart_session_id, study_id, turnaround]
+            let (chart_session_id, study_id) = parse_session_and_object_ids(&parsed_message)?;
             Ok(ParsedTradingViewMessage::StudyCompleted(StudyCompletedMessage {
-                
+                chart_session_id,
+                study_id,
+                turnaround: optional_string_param(&parsed_message, 2)?
             }))
         } else if message_type == "tickmark_update" {
             log::info!("tickmark_update = {parsed_message:?}");
+            // p = [chart_session_id, {"index", "zoffset", "changes", "marks"}], the same shape that ends a timescale_update
+            let p = json_utilities::value_to_array(json_utilities::get_key(&parsed_message, "p")?)?;
+            let chart_session_id = json_utilities::value_to_string(json_utilities::get_index(&p, 0)?)?;
+            let timescale = TimescaleChanges::from_object(&json_utilities::value_to_object(json_utilities::get_index(&p, 1)?)?)?;
             Ok(ParsedTradingViewMessage::TickmarkUpdate(TickmarkUpdateMessage {
-                
+                chart_session_id,
+                index: timescale.index,
+                marks: timescale.marks
             }))
         } else if message_type == "critical_error" {
             log::info!("critical_error = {parsed_message:?}");
+            // p = [session_id, code, method]
+            let p = json_utilities::value_to_array(json_utilities::get_key(&parsed_message, "p")?)?;
             Ok(ParsedTradingViewMessage::CriticalError(CriticalErrorMessage {
-                
+                session_id: p.first().map(value_to_text).unwrap_or_default(),
+                code: p.get(1).map(value_to_text).unwrap_or_default(),
+                method: p.get(2).map(value_to_text)
             }))
-        } else if message_type == "protcol_error" {
-            log::info!("protcol_error = {parsed_message:?}");
+        } else if message_type == "protocol_error" {
+            log::info!("protocol_error = {parsed_message:?}");
+            // p = [error]
+            let p = json_utilities::value_to_array(json_utilities::get_key(&parsed_message, "p")?)?;
             Ok(ParsedTradingViewMessage::ProtocolError(ProtocolErrorMessage {
-                
+                error: p.iter().map(value_to_text).collect::<Vec<_>>().join(", ")
             }))
         } else if message_type == "notify_user" {
             log::info!("notify_user = {parsed_message:?}");
+            // p = [{"type", "message", ...}] or [type, message, ...]
+            let p = json_utilities::value_to_array(json_utilities::get_key(&parsed_message, "p")?)?;
+            let (notification_type, text) = match p.first() {
+                Some(Value::Object(notice)) => {
+                    let notification_type = ["type", "code"].iter().find_map(|key| notice.get(*key)).map(value_to_text);
+                    let text = ["message", "text", "msg"].iter().find_map(|key| notice.get(*key)).map(value_to_text);
+                    (notification_type, text)
+                },
+                Some(notification_type) => (Some(value_to_text(notification_type)), p.get(1).map(value_to_text)),
+                None => (None, None)
+            };
             Ok(ParsedTradingViewMessage::NotifyUser(NotifyUserMessage {
-                
+                notification_type: notification_type.unwrap_or_default(),
+                text: text.unwrap_or_default(),
+                params: miniserde::json::to_string(&p)
             }))
         } else {
-            unimplemented!("message_type = {message_type}")
-        }
+            log::warn!("unknown message_type = {message_type}");
+            Ok(ParsedTradingViewMessage::Unknown { raw: value.to_string() })
+        };
+        message.map(|message| vec![message])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a series and two of its studies batched in one frame
+    const MULTI_KEY_DU: &str = r#"{"m":"du","p":["cs_000000000001",{"sds_1":{"s":[{"i":299,"v":[1700000100,100.0,100.5,99.5,100.25,1200.0]}],"ns":{"d":"","indexes":"nochange"},"t":"s1"},"st2":{"st":[{"i":299,"v":[1700000100,100.1]}],"ns":{"d":"","indexes":"nochange"}},"st3":{"st":[{"i":299,"v":[1700000100,99.9,100.3]}],"ns":{"d":"","indexes":"nochange"}}}]}"#;
+
+    #[test]
+    fn multi_key_du_fans_out_per_object() {
+        let registry = TradingViewSessionRegistry::new();
+        registry.register_series("cs_000000000001", "sds_1");
+        registry.register_study("cs_000000000001", "st2");
+        registry.register_study("cs_000000000001", "st3");
+
+        let messages = ParsedTradingViewMessage::messages_from_string_with_registry(MULTI_KEY_DU, &registry).unwrap();
+        let updates = messages.iter().map(|message| message.as_data_update().expect("du")).collect::<Vec<_>>();
+        assert_eq!(updates.iter().map(|update| update.update_key.as_str()).collect::<Vec<_>>(), vec!["sds_1", "st2", "st3"]);
+        assert!(updates.iter().all(|update| update.chart_session_id == "cs_000000000001"));
+
+        let bars = updates[0].series_updates.as_ref().expect("series updates");
+        assert_eq!(bars[0].index, 299);
+        assert_eq!(bars[0].close, 100.25);
+        assert!(updates[0].study_updates.is_none());
+
+        assert_eq!(updates[1].study_updates.as_ref().expect("study updates")[0].values, vec![1700000100.0, 100.1]);
+        assert_eq!(updates[2].study_updates.as_ref().expect("study updates")[0].values, vec![1700000100.0, 99.9, 100.3]);
+        assert!(updates[1].series_updates.is_none());
+    }
+
+    #[test]
+    fn multi_key_du_is_told_apart_by_shape_without_registry() {
+        let messages = ParsedTradingViewMessage::messages_from_string(MULTI_KEY_DU).unwrap();
+        assert_eq!(messages.len(), 3);
+        assert!(messages[0].as_data_update().unwrap().series_updates.is_some());
+        assert!(messages[1].as_data_update().unwrap().study_updates.is_some());
+        assert!(messages[2].as_data_update().unwrap().study_updates.is_some());
+    }
+
+    #[test]
+    fn single_message_parse_returns_the_first_message_of_a_batched_frame() {
+        let message = ParsedTradingViewMessage::from_string(MULTI_KEY_DU).unwrap();
+        let update = message.as_data_update().expect("du");
+        assert_eq!(update.update_key, "sds_1");
+        assert_eq!(update.series_updates.as_ref().expect("series updates")[0].close, 100.25);
+    }
+
+    #[test]
+    fn multi_key_timescale_update_moves_the_time_scale_once() {
+        let frame = r#"{"m":"timescale_update","p":["cs_000000000001",{"sds_1":{"s":[{"i":0,"v":[1699999800,1,2,0.5,1.5,10]}],"t":"s1"},"sds_2":{"s":[{"i":0,"v":[1699999800,3,4,2.5,3.5,20]}],"t":"s2"}},{"index":300,"zoffset":1,"changes":[1699999800],"marks":[]}]}"#;
+        let messages = ParsedTradingViewMessage::messages_from_string(frame).unwrap();
+        let updates = messages.iter().map(|message| message.as_timescale_update().expect("timescale_update")).collect::<Vec<_>>();
+        assert_eq!(updates.iter().map(|update| update.update_key.as_deref()).collect::<Vec<_>>(), vec![Some("sds_1"), Some("sds_2")]);
+        assert_eq!(updates[0].zoffset(), 1);
+        assert_eq!(updates[1].zoffset(), 0);
+        assert_eq!(updates[1].updates.as_ref().expect("bars")[0].close, 3.5);
+    }
+
+    #[test]
+    fn qsd_keeps_every_field_and_reports_missing_and_unexpected_ones() {
+        let registry = TradingViewSessionRegistry::new();
+        registry.register_quote_fields("qs_000000000001", &[QuoteField::Lp, QuoteField::Ch, QuoteField::Volume]);
+
+        let qsd = r#"{"m":"qsd","p":["qs_000000000001",{"n":"AMEX:SPY","s":"ok","v":{"lp":450.5,"ch":1.25,"description":"SPDR S&P 500 ETF","bid":450.4,"some_new_field":7}}]}"#;
+        let message = ParsedTradingViewMessage::from_string_with_registry(qsd, &registry).unwrap();
+        let quote_update = &message.as_quote_series_data().expect("qsd").quote_update;
+        assert_eq!(quote_update.fields.len(), 5);
+        assert_eq!(quote_update.fields.bid(), Some(450.4));
+        assert_eq!(quote_update.bid, Some(450.4));
+        assert!(quote_update.fields.get(&QuoteField::Other("some_new_field".to_string())).is_some());
+        assert_eq!(quote_update.missing_fields, vec![QuoteField::Volume]);
+        assert_eq!(quote_update.unexpected_fields, vec![QuoteField::Description, QuoteField::Bid, QuoteField::Other("some_new_field".to_string())]);
+
+        // a session the registry doesn't know about reports nothing either way
+        let unknown = qsd.replace("qs_000000000001", "qs_000000000002");
+        let message = ParsedTradingViewMessage::from_string_with_registry(&unknown, &registry).unwrap();
+        let quote_update = &message.as_quote_series_data().expect("qsd").quote_update;
+        assert_eq!(quote_update.fields.len(), 5);
+        assert!(quote_update.missing_fields.is_empty() && quote_update.unexpected_fields.is_empty());
+    }
+
+    #[test]
+    fn only_known_auth_rejections_are_unauthorized() {
+        let to_error = |payload: &str| ParsedTradingViewMessage::from_string(payload).unwrap().to_error().expect("error message");
+        assert!(matches!(to_error(r#"{"m":"critical_error","p":["","invalid_auth_token","set_auth_token"]}"#), TradingViewError::Unauthorized(_)));
+        assert!(matches!(to_error(r#"{"m":"critical_error","p":["","invalid_parameters","set_auth_token"]}"#), TradingViewError::Unauthorized(_)));
+        assert!(matches!(to_error(r#"{"m":"critical_error","p":["cs_000000000001","invalid_auth_token"]}"#), TradingViewError::Unauthorized(_)));
+        // mentioning a token or auth somewhere in the text isn't enough
+        assert!(matches!(to_error(r#"{"m":"protocol_error","p":["unexpected token at position 12"]}"#), TradingViewError::ServerProtocol(_)));
+        assert!(matches!(to_error(r#"{"m":"critical_error","p":["cs_000000000001","author_not_found","create_study"]}"#), TradingViewError::Rejected { .. }));
+    }
+
+    /// A series and two studies in one `du`, only `st2` drew anything.
+    const GRAPHICS_FRAME: &str = include_str!("../tests/fixtures/du_study_graphics.json");
+
+    #[test]
+    fn du_graphics_belong_to_the_study_that_drew_them() {
+        let registry = TradingViewSessionRegistry::new();
+        registry.register_series("cs_000000000001", "sds_1");
+        registry.register_study("cs_000000000001", "st2");
+        registry.register_study("cs_000000000001", "st3");
+
+        let messages = ParsedTradingViewMessage::messages_from_string_with_registry(GRAPHICS_FRAME.trim(), &registry).unwrap();
+        let updates = messages.iter().map(|message| message.as_data_update().expect("du")).collect::<Vec<_>>();
+        assert_eq!(updates.iter().map(|update| update.update_key.as_str()).collect::<Vec<_>>(), vec!["sds_1", "st2", "st3"]);
+
+        // the series' own ns isn't decoded as graphics
+        assert!(updates[0].graphics.is_none());
+        let graphics = updates[1].graphics.as_ref().expect("st2 graphics");
+        assert_eq!(graphics.labels.iter().map(|label| label.id).collect::<Vec<_>>(), vec![11]);
+        assert_eq!(graphics.tables.len(), 1);
+        assert!(updates[2].graphics.as_ref().expect("st3 graphics").is_empty());
+    }
+
+    #[test]
+    fn notify_user_kind_comes_from_the_type_not_the_text() {
+        let kind = |payload: &str| ParsedTradingViewMessage::from_string(payload).unwrap().as_notify_user().expect("notify_user").kind();
+        assert_eq!(kind(r#"{"m":"notify_user","p":[{"type":"session_limit","message":"Too many sessions open for this account"}]}"#), TradingViewNotificationKind::TooManySessions);
+        assert_eq!(kind(r#"{"m":"notify_user","p":[{"code":"data_delayed","text":"Data for NASDAQ is delayed by 15 minutes"}]}"#), TradingViewNotificationKind::DataDelayed);
+        assert_eq!(kind(r#"{"m":"notify_user","p":["plan_limit","Study limit of your plan reached"]}"#), TradingViewNotificationKind::PlanLimit);
+        // a text full of keywords under an unknown or missing type stays Other
+        assert_eq!(kind(r#"{"m":"notify_user","p":[{"type":"promo","message":"Session limit reached? Upgrade your plan to lift the delay"}]}"#), TradingViewNotificationKind::Other);
+        assert_eq!(kind(r#"{"m":"notify_user","p":[{"message":"Too many sessions"}]}"#), TradingViewNotificationKind::Other);
+        assert_eq!(kind(r#"{"m":"notify_user","p":[]}"#), TradingViewNotificationKind::Other);
+    }
+
+    /// A `request_more_data` page as the server lays it out: three older bars for a chart that had 300, with the extra keys
+    /// (`lbs`, `index_diff`) the parser has to tolerate.
+    const PREPEND_FRAME: &str = include_str!("../tests/fixtures/timescale_update_prepend.json");
+
+    #[test]
+    fn prepended_page_shifts_every_earlier_index_by_zoffset() {
+        let messages = ParsedTradingViewMessage::messages_from_string(PREPEND_FRAME.trim()).unwrap();
+        let update = messages[0].as_timescale_update().expect("timescale_update");
+        let timescale = update.timescale.as_ref().expect("timescale changes");
+
+        // the page takes indexes 0..zoffset, the 300 bars loaded before it (0..=299) are now 3..=302
+        assert_eq!(update.zoffset(), 3);
+        let bars = update.updates.as_ref().expect("bars");
+        assert_eq!(bars.iter().map(|bar| bar.index).collect::<Vec<_>>(), vec![0, 1, 2]);
+        assert_eq!(timescale.index, 299 + update.zoffset());
+        assert_eq!(timescale.changes, bars.iter().map(|bar| bar.timestamp).collect::<Vec<_>>());
+
+        // marks already use the new numbering: the hour that was bar 8 is labelled at 11
+        assert_eq!(timescale.marks[0].index, 8 + update.zoffset());
+        assert_eq!(timescale.marks[0].timestamp.seconds(), 1_699_910_400 + 8 * 300);
     }
 }
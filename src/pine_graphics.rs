@@ -0,0 +1,414 @@
+use miniserde::Serialize;
+use miniserde::json::{Object, Value};
+
+use crate::error::TradingViewResult;
+use crate::json_utilities;
+
+/// A `label.new` drawn by the script.
+#[derive(Debug, Clone, Serialize)]
+pub struct PineLabel {
+    pub id: i64,
+    /// Position into the `indexes` of the graphics payload, see `PineGraphics::bar_index`
+    pub x: Option<i64>,
+    pub y: Option<f64>,
+    pub y_location: Option<String>,
+    pub text: Option<String>,
+    pub tooltip: Option<String>,
+    pub style: Option<String>,
+    pub size: Option<String>,
+    pub text_align: Option<String>,
+    /// Colors are packed ARGB integers
+    pub color: Option<i64>,
+    pub text_color: Option<i64>,
+}
+
+/// A `line.new` drawn by the script.
+#[derive(Debug, Clone, Serialize)]
+pub struct PineLine {
+    pub id: i64,
+    pub x1: Option<i64>,
+    pub y1: Option<f64>,
+    pub x2: Option<i64>,
+    pub y2: Option<f64>,
+    pub x_location: Option<String>,
+    pub extend: Option<String>,
+    pub style: Option<String>,
+    pub width: Option<i64>,
+    pub color: Option<i64>,
+}
+
+/// A `box.new` drawn by the script, `x1`/`y1` is the top left corner and `x2`/`y2` the bottom right one.
+#[derive(Debug, Clone, Serialize)]
+pub struct PineBox {
+    pub id: i64,
+    pub x1: Option<i64>,
+    pub y1: Option<f64>,
+    pub x2: Option<i64>,
+    pub y2: Option<f64>,
+    pub extend: Option<String>,
+    pub style: Option<String>,
+    pub width: Option<i64>,
+    pub border_color: Option<i64>,
+    pub background_color: Option<i64>,
+    pub text: Option<String>,
+    pub text_color: Option<i64>,
+}
+
+/// A `table.new` drawn by the script, its cells come separately as `PineTableCell`.
+#[derive(Debug, Clone, Serialize)]
+pub struct PineTable {
+    pub id: i64,
+    /// Anchor on the chart, e.g. `top_right`
+    pub position: Option<String>,
+    pub rows: Option<i64>,
+    pub columns: Option<i64>,
+    pub background_color: Option<i64>,
+    pub frame_color: Option<i64>,
+    pub frame_width: Option<i64>,
+    pub border_color: Option<i64>,
+    pub border_width: Option<i64>,
+}
+
+/// A `table.cell` of the table with id `table_id`.
+#[derive(Debug, Clone, Serialize)]
+pub struct PineTableCell {
+    pub id: i64,
+    pub table_id: i64,
+    pub row: i64,
+    pub column: i64,
+    pub text: Option<String>,
+    pub text_color: Option<i64>,
+    pub text_size: Option<String>,
+    pub background_color: Option<i64>,
+}
+
+/// Tells the consumer to drop graphics it got from earlier updates: everything (`action` is `all`) or the object `id` of `kind` (`action` is `one`).
+#[derive(Debug, Clone, Serialize)]
+pub struct PineGraphicsErase {
+    pub action: String,
+    /// Graphics collection the id belongs to, e.g. `dwglabels`
+    pub kind: Option<String>,
+    pub id: Option<i64>,
+}
+
+/// Non-series output of a study, decoded from the `ns` payload of its `du`.
+#[derive(Debug, Clone, Serialize)]
+pub struct PineGraphics {
+    /// Bar indexes the `x` coordinates point into, `None` when the server sent `nochange`
+    pub indexes: Option<Vec<i64>>,
+    pub erase: Vec<PineGraphicsErase>,
+    pub labels: Vec<PineLabel>,
+    pub lines: Vec<PineLine>,
+    pub boxes: Vec<PineBox>,
+    pub tables: Vec<PineTable>,
+    pub table_cells: Vec<PineTableCell>,
+}
+
+/// Flattens `create.<kind>`, a list of `{ "data": [...] }` groups, into the objects of every group.
+fn created_objects(create: &Object, kind: &str) -> TradingViewResult<Vec<Object>> {
+    let mut objects = vec![];
+    if let Some(groups) = create.get(kind) {
+        for group in json_utilities::value_to_array(groups)? {
+            let group = json_utilities::value_to_object(&group)?;
+            if let Some(data) = group.get("data") {
+                for object in json_utilities::value_to_array(data)? {
+                    objects.push(json_utilities::value_to_object(&object)?);
+                }
+            }
+        }
+    }
+    Ok(objects)
+}
+
+impl PineLabel {
+    pub fn from_object(object: &Object) -> TradingViewResult<Self> {
+        Ok(PineLabel {
+            id: json_utilities::value_to_i64(json_utilities::get_key(object, "id")?)?,
+            x: json_utilities::get_optional_i64(object, "x")?,
+            y: json_utilities::get_optional_f64(object, "y")?,
+            y_location: json_utilities::get_optional_string(object, "yl")?,
+            text: json_utilities::get_optional_string(object, "t")?,
+            tooltip: json_utilities::get_optional_string(object, "tt")?,
+            style: json_utilities::get_optional_string(object, "st")?,
+            size: json_utilities::get_optional_string(object, "sz")?,
+            text_align: json_utilities::get_optional_string(object, "ta")?,
+            color: json_utilities::get_optional_i64(object, "ci")?,
+            text_color: json_utilities::get_optional_i64(object, "tci")?,
+        })
+    }
+}
+
+impl PineLine {
+    pub fn from_object(object: &Object) -> TradingViewResult<Self> {
+        Ok(PineLine {
+            id: json_utilities::value_to_i64(json_utilities::get_key(object, "id")?)?,
+            x1: json_utilities::get_optional_i64(object, "x1")?,
+            y1: json_utilities::get_optional_f64(object, "y1")?,
+            x2: json_utilities::get_optional_i64(object, "x2")?,
+            y2: json_utilities::get_optional_f64(object, "y2")?,
+            x_location: json_utilities::get_optional_string(object, "xl")?,
+            extend: json_utilities::get_optional_string(object, "ex")?,
+            style: json_utilities::get_optional_string(object, "st")?,
+            width: json_utilities::get_optional_i64(object, "w")?,
+            color: json_utilities::get_optional_i64(object, "ci")?,
+        })
+    }
+}
+
+impl PineBox {
+    pub fn from_object(object: &Object) -> TradingViewResult<Self> {
+        Ok(PineBox {
+            id: json_utilities::value_to_i64(json_utilities::get_key(object, "id")?)?,
+            x1: json_utilities::get_optional_i64(object, "x1")?,
+            y1: json_utilities::get_optional_f64(object, "y1")?,
+            x2: json_utilities::get_optional_i64(object, "x2")?,
+            y2: json_utilities::get_optional_f64(object, "y2")?,
+            extend: json_utilities::get_optional_string(object, "ex")?,
+            style: json_utilities::get_optional_string(object, "st")?,
+            width: json_utilities::get_optional_i64(object, "w")?,
+            border_color: json_utilities::get_optional_i64(object, "c")?,
+            background_color: json_utilities::get_optional_i64(object, "bc")?,
+            text: json_utilities::get_optional_string(object, "t")?,
+            text_color: json_utilities::get_optional_i64(object, "tc")?,
+        })
+    }
+}
+
+impl PineTable {
+    pub fn from_object(object: &Object) -> TradingViewResult<Self> {
+        Ok(PineTable {
+            id: json_utilities::value_to_i64(json_utilities::get_key(object, "id")?)?,
+            position: json_utilities::get_optional_string(object, "pos")?,
+            rows: json_utilities::get_optional_i64(object, "rows")?,
+            columns: json_utilities::get_optional_i64(object, "columns")?,
+            background_color: json_utilities::get_optional_i64(object, "bgc")?,
+            frame_color: json_utilities::get_optional_i64(object, "frmc")?,
+            frame_width: json_utilities::get_optional_i64(object, "frmw")?,
+            border_color: json_utilities::get_optional_i64(object, "brc")?,
+            border_width: json_utilities::get_optional_i64(object, "brw")?,
+        })
+    }
+}
+
+impl PineTableCell {
+    pub fn from_object(object: &Object) -> TradingViewResult<Self> {
+        Ok(PineTableCell {
+            id: json_utilities::value_to_i64(json_utilities::get_key(object, "id")?)?,
+            table_id: json_utilities::value_to_i64(json_utilities::get_key(object, "tid")?)?,
+            row: json_utilities::value_to_i64(json_utilities::get_key(object, "row")?)?,
+            column: json_utilities::value_to_i64(json_utilities::get_key(object, "col")?)?,
+            text: json_utilities::get_optional_string(object, "t")?,
+            text_color: json_utilities::get_optional_i64(object, "tc")?,
+            text_size: json_utilities::get_optional_string(object, "ts")?,
+            background_color: json_utilities::get_optional_i64(object, "bgc")?,
+        })
+    }
+}
+
+impl PineGraphicsErase {
+    pub fn from_object(object: &Object) -> TradingViewResult<Self> {
+        Ok(PineGraphicsErase {
+            action: json_utilities::value_to_string(json_utilities::get_key(object, "action")?)?,
+            kind: json_utilities::get_optional_string(object, "type")?,
+            id: json_utilities::get_optional_i64(object, "id")?,
+        })
+    }
+}
+
+impl PineGraphics {
+    /// Decodes the `ns` object of a study update, `{ "d": "<json>", "indexes": [...] | "nochange" }`.
+    pub fn from_ns(ns: &Object) -> TradingViewResult<Self> {
+        let indexes = match ns.get("indexes") {
+            Some(Value::Array(indexes)) => Some(indexes.iter().map(json_utilities::value_to_i64).collect::<TradingViewResult<Vec<_>>>()?),
+            // "nochange" keeps the indexes of the previous update
+            _ => None
+        };
+        let mut graphics = PineGraphics {
+            indexes,
+            erase: vec![],
+            labels: vec![],
+            lines: vec![],
+            boxes: vec![],
+            tables: vec![],
+            table_cells: vec![],
+        };
+
+        // the graphics themselves are a json document inside a string, empty when the script drew nothing
+        let d = json_utilities::get_optional_string(ns, "d")?.unwrap_or_default();
+        if d.is_empty() {
+            return Ok(graphics);
+        }
+        let d: Object = miniserde::json::from_str(&d)?;
+        let graphics_cmds = match d.get("graphicsCmds") {
+            Some(graphics_cmds) => json_utilities::value_to_object(graphics_cmds)?,
+            None => return Ok(graphics)
+        };
+
+        if let Some(erase) = graphics_cmds.get("erase") {
+            graphics.erase = json_utilities::value_to_array(erase)?
+                .iter()
+                .map(|erase| PineGraphicsErase::from_object(&json_utilities::value_to_object(erase)?))
+                .collect::<TradingViewResult<Vec<_>>>()?;
+        }
+        if let Some(create) = graphics_cmds.get("create") {
+            let create = json_utilities::value_to_object(create)?;
+            graphics.labels = created_objects(&create, "dwglabels")?.iter().map(PineLabel::from_object).collect::<TradingViewResult<Vec<_>>>()?;
+            graphics.lines = created_objects(&create, "dwglines")?.iter().map(PineLine::from_object).collect::<TradingViewResult<Vec<_>>>()?;
+            graphics.boxes = created_objects(&create, "dwgboxes")?.iter().map(PineBox::from_object).collect::<TradingViewResult<Vec<_>>>()?;
+            graphics.tables = created_objects(&create, "dwgtables")?.iter().map(PineTable::from_object).collect::<TradingViewResult<Vec<_>>>()?;
+            graphics.table_cells = created_objects(&create, "dwgtablecells")?.iter().map(PineTableCell::from_object).collect::<TradingViewResult<Vec<_>>>()?;
+        }
+        Ok(graphics)
+    }
+
+    /// Bar index an `x` coordinate points at, through `indexes`.
+    pub fn bar_index(&self, x: i64) -> Option<i64> {
+        let indexes = self.indexes.as_ref()?;
+        usize::try_from(x).ok().and_then(|x| indexes.get(x).copied())
+    }
+
+    /// Whether the update neither drew nor erased anything.
+    pub fn is_empty(&self) -> bool {
+        self.erase.is_empty() && self.labels.is_empty() && self.lines.is_empty() && self.boxes.is_empty() && self.tables.is_empty() && self.table_cells.is_empty()
+    }
+
+    /// Cells of the table with id `table_id`, in row then column order.
+    pub fn cells_of(&self, table_id: i64) -> Vec<&PineTableCell> {
+        let mut cells = self.table_cells.iter().filter(|cell| cell.table_id == table_id).collect::<Vec<_>>();
+        cells.sort_by_key(|cell| (cell.row, cell.column));
+        cells
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::TradingViewError;
+
+    /// A `du` with a series and two studies, `st2` drew one of every graphics type and erased a label from an earlier update.
+    const GRAPHICS_FRAME: &str = include_str!("../tests/fixtures/du_study_graphics.json");
+
+    fn ns_of(update_key: &str) -> Object {
+        let frame: Object = miniserde::json::from_str(GRAPHICS_FRAME.trim()).unwrap();
+        let p = json_utilities::value_to_array(json_utilities::get_key(&frame, "p").unwrap()).unwrap();
+        let updates = json_utilities::value_to_object(json_utilities::get_index(&p, 1).unwrap()).unwrap();
+        let update = json_utilities::value_to_object(json_utilities::get_key(&updates, update_key).unwrap()).unwrap();
+        json_utilities::value_to_object(json_utilities::get_key(&update, "ns").unwrap()).unwrap()
+    }
+
+    fn graphics() -> PineGraphics {
+        PineGraphics::from_ns(&ns_of("st2")).unwrap()
+    }
+
+    #[test]
+    fn labels_are_decoded_and_placed_through_indexes() {
+        let graphics = graphics();
+        assert_eq!(graphics.indexes, Some(vec![297, 298, 299]));
+        assert_eq!(graphics.labels.len(), 1);
+        let label = &graphics.labels[0];
+        assert_eq!(label.id, 11);
+        assert_eq!(label.x.and_then(|x| graphics.bar_index(x)), Some(299));
+        assert_eq!(label.y, Some(451.25));
+        assert_eq!(label.y_location.as_deref(), Some("pr"));
+        assert_eq!(label.text.as_deref(), Some("BUY"));
+        assert_eq!(label.tooltip.as_deref(), Some("ema crossed up"));
+        assert_eq!(label.style.as_deref(), Some("label_up"));
+        assert_eq!(label.size.as_deref(), Some("normal"));
+        assert_eq!(label.text_align.as_deref(), Some("center"));
+        assert_eq!(label.color, Some(4278255360));
+        assert_eq!(label.text_color, Some(4294967295));
+    }
+
+    #[test]
+    fn lines_are_decoded() {
+        let graphics = graphics();
+        assert_eq!(graphics.lines.len(), 1);
+        let line = &graphics.lines[0];
+        assert_eq!(line.id, 21);
+        assert_eq!((line.x1, line.y1, line.x2, line.y2), (Some(0), Some(449.5), Some(2), Some(451.0)));
+        assert_eq!(line.x_location.as_deref(), Some("bi"));
+        assert_eq!(line.extend.as_deref(), Some("r"));
+        assert_eq!(line.style.as_deref(), Some("solid"));
+        assert_eq!(line.width, Some(2));
+        assert_eq!(line.color, Some(4280391411));
+    }
+
+    #[test]
+    fn boxes_are_decoded() {
+        let graphics = graphics();
+        assert_eq!(graphics.boxes.len(), 1);
+        let pine_box = &graphics.boxes[0];
+        assert_eq!(pine_box.id, 31);
+        assert_eq!((pine_box.x1, pine_box.y1, pine_box.x2, pine_box.y2), (Some(0), Some(452.0), Some(1), Some(449.0)));
+        assert_eq!(pine_box.extend.as_deref(), Some("n"));
+        assert_eq!(pine_box.style.as_deref(), Some("dashed"));
+        assert_eq!(pine_box.width, Some(1));
+        assert_eq!(pine_box.border_color, Some(4294901760));
+        assert_eq!(pine_box.background_color, Some(855638016));
+        assert_eq!(pine_box.text.as_deref(), Some("range"));
+        assert_eq!(pine_box.text_color, Some(4278190080));
+    }
+
+    #[test]
+    fn tables_are_decoded() {
+        let graphics = graphics();
+        assert_eq!(graphics.tables.len(), 1);
+        let table = &graphics.tables[0];
+        assert_eq!(table.id, 41);
+        assert_eq!(table.position.as_deref(), Some("top_right"));
+        assert_eq!((table.rows, table.columns), (Some(2), Some(2)));
+        assert_eq!(table.background_color, Some(4294967295));
+        assert_eq!((table.frame_color, table.frame_width), (Some(4278190080), Some(1)));
+        assert_eq!((table.border_color, table.border_width), (Some(4286611584), Some(1)));
+    }
+
+    #[test]
+    fn table_cells_are_decoded_and_sorted_by_position() {
+        let graphics = graphics();
+        assert_eq!(graphics.table_cells.len(), 3);
+        let cells = graphics.cells_of(41);
+        assert_eq!(cells.iter().map(|cell| (cell.row, cell.column)).collect::<Vec<_>>(), vec![(0, 0), (0, 1), (1, 0)]);
+        assert_eq!(cells.iter().map(|cell| cell.text.as_deref()).collect::<Vec<_>>(), vec![Some("VWAP"), Some("MVWAP"), Some("450.10")]);
+        assert_eq!(cells[1].text_size.as_deref(), Some("small"));
+        assert_eq!(cells[2].background_color, Some(4294967295));
+        assert_eq!(cells[0].text_color, None);
+        assert!(graphics.cells_of(42).is_empty());
+    }
+
+    #[test]
+    fn erase_commands_are_decoded() {
+        let erase = &graphics().erase;
+        assert_eq!(erase.len(), 1);
+        assert_eq!(erase[0].action, "one");
+        assert_eq!(erase[0].kind.as_deref(), Some("dwglabels"));
+        assert_eq!(erase[0].id, Some(3));
+
+        let ns: Object = miniserde::json::from_str(r#"{"d":"{\"graphicsCmds\":{\"erase\":[{\"action\":\"all\"}]}}","indexes":"nochange"}"#).unwrap();
+        let graphics = PineGraphics::from_ns(&ns).unwrap();
+        assert_eq!(graphics.indexes, None);
+        assert_eq!(graphics.erase[0].action, "all");
+        assert_eq!((graphics.erase[0].kind.as_deref(), graphics.erase[0].id), (None, None));
+        assert!(graphics.labels.is_empty() && !graphics.is_empty());
+    }
+
+    #[test]
+    fn nothing_drawn_is_empty() {
+        let graphics = PineGraphics::from_ns(&ns_of("st3")).unwrap();
+        assert!(graphics.is_empty());
+        assert_eq!(graphics.indexes, None);
+        assert_eq!(graphics.bar_index(0), None);
+    }
+
+    #[test]
+    fn malformed_graphics_are_errors() {
+        let from_ns = |ns: &str| PineGraphics::from_ns(&miniserde::json::from_str::<Object>(ns).unwrap());
+        // d is not json
+        assert!(matches!(from_ns(r#"{"d":"{graphicsCmds","indexes":[]}"#), Err(TradingViewError::Json(_))));
+        // a label without its id
+        assert!(from_ns(r#"{"d":"{\"graphicsCmds\":{\"create\":{\"dwglabels\":[{\"data\":[{\"x\":1}]}]}}","indexes":[1]}"#).is_err());
+        // create groups have to be a list
+        assert!(from_ns(r#"{"d":"{\"graphicsCmds\":{\"create\":{\"dwglines\":{\"data\":[]}}}","indexes":[1]}"#).is_err());
+        // indexes have to be numbers
+        assert!(from_ns(r#"{"d":"","indexes":["a"]}"#).is_err());
+    }
+}
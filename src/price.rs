@@ -0,0 +1,153 @@
+use std::fmt;
+use std::hash::{Hash, Hasher};
+
+use miniserde::Serialize;
+use miniserde::ser::Fragment;
+
+/// An exact decimal price, `mantissa / 10^scale`, so prices print and compare without float noise.
+///
+/// Built from a float and the symbol's `pricescale`/`minmov` with `TradingViewPrice::from_f64` or `SymbolInfo::price`.
+///
+/// Comparisons and hashing ignore trailing zeros, so `1.50` (scale 2) equals `1.5` (scale 1).
+#[derive(Debug, Clone, Copy)]
+pub struct TradingViewPrice {
+    mantissa: i64,
+    scale: u32,
+}
+
+impl TradingViewPrice {
+    pub fn new(mantissa: i64, scale: u32) -> Self {
+        Self { mantissa, scale }
+    }
+
+    /// Rounds `value` to the nearest multiple of `minmov / pricescale`. `None` for prices that aren't decimal (a `pricescale`
+    /// that isn't a power of ten, as with fractional bond prices) or that can't be represented.
+    pub fn from_f64(value: f64, pricescale: u64, minmov: u64) -> Option<Self> {
+        if !value.is_finite() || pricescale == 0 || minmov == 0 {
+            return None;
+        }
+        let scale = pricescale.ilog10();
+        if 10u64.checked_pow(scale)? != pricescale {
+            return None;
+        }
+        let ticks = (value * pricescale as f64 / minmov as f64).round() * minmov as f64;
+        if ticks.abs() >= i64::MAX as f64 {
+            return None;
+        }
+        Some(Self {
+            mantissa: ticks as i64,
+            scale,
+        })
+    }
+
+    pub fn mantissa(&self) -> i64 {
+        self.mantissa
+    }
+
+    /// Number of decimal places.
+    pub fn scale(&self) -> u32 {
+        self.scale
+    }
+
+    pub fn to_f64(&self) -> f64 {
+        self.mantissa as f64 / 10f64.powi(self.scale as i32)
+    }
+
+    /// Mantissa and scale with the trailing zeros stripped.
+    fn normalized(&self) -> (i64, u32) {
+        let (mut mantissa, mut scale) = (self.mantissa, self.scale);
+        while scale > 0 && mantissa % 10 == 0 {
+            mantissa /= 10;
+            scale -= 1;
+        }
+        (mantissa, scale)
+    }
+}
+
+impl PartialEq for TradingViewPrice {
+    fn eq(&self, other: &Self) -> bool {
+        self.normalized() == other.normalized()
+    }
+}
+
+impl Eq for TradingViewPrice {}
+
+impl Hash for TradingViewPrice {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.normalized().hash(state);
+    }
+}
+
+impl fmt::Display for TradingViewPrice {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let sign = if self.mantissa < 0 { "-" } else { "" };
+        let digits = self.mantissa.unsigned_abs().to_string();
+        let scale = self.scale as usize;
+        if scale == 0 {
+            return write!(f, "{sign}{digits}");
+        }
+        let digits = format!("{digits:0>width$}", width = scale + 1);
+        let (integer, fraction) = digits.split_at(digits.len() - scale);
+        write!(f, "{sign}{integer}.{fraction}")
+    }
+}
+
+// serialized as a string, a json number would bring the float noise back
+impl Serialize for TradingViewPrice {
+    fn begin(&self) -> Fragment<'_> {
+        Fragment::Str(self.to_string().into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+
+    #[test]
+    fn same_price_at_different_scales_is_equal() {
+        assert_eq!(TradingViewPrice::new(150, 2), TradingViewPrice::new(15, 1));
+        assert_eq!(TradingViewPrice::new(0, 4), TradingViewPrice::new(0, 0));
+        assert_ne!(TradingViewPrice::new(151, 2), TradingViewPrice::new(15, 1));
+        assert_ne!(TradingViewPrice::new(15, 2), TradingViewPrice::new(15, 1));
+        let prices = HashSet::from([TradingViewPrice::new(150, 2), TradingViewPrice::new(15, 1), TradingViewPrice::new(1500, 3)]);
+        assert_eq!(prices.len(), 1);
+    }
+
+    #[test]
+    fn from_f64_rounds_to_minmov_ticks() {
+        // cents
+        let price = TradingViewPrice::from_f64(450.123, 100, 1).unwrap();
+        assert_eq!((price.mantissa(), price.scale()), (45012, 2));
+        assert_eq!(price.to_string(), "450.12");
+        // quarter points, e.g. index futures with pricescale 100 and minmov 25
+        assert_eq!(TradingViewPrice::from_f64(4512.13, 100, 25).unwrap().to_string(), "4512.25");
+        assert_eq!(TradingViewPrice::from_f64(4512.12, 100, 25).unwrap().to_string(), "4512.00");
+        // whole units and negative prices
+        assert_eq!(TradingViewPrice::from_f64(1234.6, 1, 1).unwrap().to_string(), "1235");
+        assert_eq!(TradingViewPrice::from_f64(-0.055, 1000, 1).unwrap().to_string(), "-0.055");
+        assert_eq!(TradingViewPrice::from_f64(0.00012, 100000, 1).unwrap().to_string(), "0.00012");
+    }
+
+    #[test]
+    fn from_f64_rejects_what_it_cannot_represent() {
+        // fractional bond prices, a pricescale that isn't a power of ten
+        assert_eq!(TradingViewPrice::from_f64(101.5, 32, 1), None);
+        assert_eq!(TradingViewPrice::from_f64(101.5, 0, 1), None);
+        assert_eq!(TradingViewPrice::from_f64(101.5, 100, 0), None);
+        assert_eq!(TradingViewPrice::from_f64(f64::NAN, 100, 1), None);
+        assert_eq!(TradingViewPrice::from_f64(f64::INFINITY, 100, 1), None);
+        assert_eq!(TradingViewPrice::from_f64(1e18, 100, 1), None);
+    }
+
+    #[test]
+    fn round_trips_through_f64() {
+        for (value, pricescale) in [(450.12, 100), (0.00012, 100000), (-3.5, 10), (1.0, 1), (67812.37, 100)] {
+            let price = TradingViewPrice::from_f64(value, pricescale, 1).unwrap();
+            assert_eq!(price.to_f64(), value);
+            assert_eq!(TradingViewPrice::from_f64(price.to_f64(), pricescale, 1), Some(price));
+        }
+        assert_eq!(miniserde::json::to_string(&TradingViewPrice::new(45012, 2)), r#""450.12""#);
+    }
+}
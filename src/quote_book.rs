@@ -0,0 +1,155 @@
+use std::collections::HashMap;
+
+use miniserde::Serialize;
+
+use crate::parsed_message::{QuoteSeriesDataMessage, QuoteSeriesDataUpdate};
+use crate::quote_field::{QuoteField, QuoteFieldMap};
+use crate::timestamp::TradingViewTimestamp;
+
+/// Everything known about a symbol's quote so far, the sum of every `qsd` received for it.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct QuoteSnapshot {
+    pub symbol: String,
+    /// Latest value of every field received, a field the server nulled out holds `null`
+    pub fields: QuoteFieldMap,
+}
+
+impl QuoteSnapshot {
+    pub fn new(symbol: &str) -> Self {
+        Self {
+            symbol: symbol.to_string(),
+            ..Default::default()
+        }
+    }
+
+    /// Overwrites the fields present in `update` and returns the ones whose value changed.
+    pub fn merge(&mut self, update: &QuoteSeriesDataUpdate) -> Vec<QuoteField> {
+        let mut changed = vec![];
+        for (field, value) in update.fields.iter() {
+            if self.fields.insert(field.clone(), value.clone()) {
+                changed.push(field.clone());
+            }
+        }
+        changed
+    }
+
+    pub fn volume(&self) -> Option<f64> {
+        self.fields.volume()
+    }
+
+    pub fn ch(&self) -> Option<f64> {
+        self.fields.ch()
+    }
+
+    pub fn chp(&self) -> Option<f64> {
+        self.fields.chp()
+    }
+
+    pub fn rch(&self) -> Option<f64> {
+        self.fields.rch()
+    }
+
+    pub fn rchp(&self) -> Option<f64> {
+        self.fields.rchp()
+    }
+
+    pub fn rtc(&self) -> Option<f64> {
+        self.fields.rtc()
+    }
+
+    pub fn rtc_time(&self) -> Option<TradingViewTimestamp> {
+        self.fields.rtc_time()
+    }
+
+    pub fn lp(&self) -> Option<f64> {
+        self.fields.lp()
+    }
+
+    pub fn lp_time(&self) -> Option<TradingViewTimestamp> {
+        self.fields.lp_time()
+    }
+
+    pub fn ask(&self) -> Option<f64> {
+        self.fields.ask()
+    }
+
+    pub fn ask_size(&self) -> Option<f64> {
+        self.fields.ask_size()
+    }
+
+    pub fn bid(&self) -> Option<f64> {
+        self.fields.bid()
+    }
+
+    pub fn bid_size(&self) -> Option<f64> {
+        self.fields.bid_size()
+    }
+
+    pub fn trade_loaded(&self) -> Option<bool> {
+        self.fields.trade_loaded()
+    }
+
+    pub fn current_session(&self) -> Option<&str> {
+        self.fields.current_session()
+    }
+}
+
+/// Latest full quote per symbol, built up from partial `qsd` updates.
+#[derive(Debug, Clone, Default)]
+pub struct TradingViewQuoteBook {
+    quotes: HashMap<String, QuoteSnapshot>,
+}
+
+impl TradingViewQuoteBook {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Merges a `qsd` into the symbol's snapshot, returning the names of the fields that changed.
+    pub fn apply(&mut self, message: &QuoteSeriesDataMessage) -> Vec<QuoteField> {
+        let symbol = &message.quote_update.symbol;
+        self.quotes.entry(symbol.clone())
+            .or_insert_with(|| QuoteSnapshot::new(symbol))
+            .merge(&message.quote_update)
+    }
+
+    pub fn get(&self, symbol: &str) -> Option<&QuoteSnapshot> {
+        self.quotes.get(symbol)
+    }
+
+    pub fn symbols(&self) -> impl Iterator<Item = &String> {
+        self.quotes.keys()
+    }
+
+    pub fn snapshots(&self) -> impl Iterator<Item = &QuoteSnapshot> {
+        self.quotes.values()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parsed_message::ParsedTradingViewMessage;
+
+    fn qsd(payload: &str) -> QuoteSeriesDataMessage {
+        ParsedTradingViewMessage::from_string(payload).unwrap().as_quote_series_data().expect("qsd").clone()
+    }
+
+    #[test]
+    fn null_update_clears_the_field() {
+        let mut quote_book = TradingViewQuoteBook::new();
+        quote_book.apply(&qsd(r#"{"m":"qsd","p":["qs_000000000001",{"n":"AMEX:SPY","s":"ok","v":{"lp":450.5,"bid":450.25,"lp_time":1700000100}}]}"#));
+        let snapshot = quote_book.get("AMEX:SPY").unwrap();
+        assert_eq!(snapshot.lp(), Some(450.5));
+        assert_eq!(snapshot.bid(), Some(450.25));
+        assert_eq!(snapshot.lp_time().map(|lp_time| lp_time.seconds()), Some(1_700_000_100));
+
+        // the bid went away, the last price didn't change
+        let changed = quote_book.apply(&qsd(r#"{"m":"qsd","p":["qs_000000000001",{"n":"AMEX:SPY","s":"ok","v":{"lp":450.5,"bid":null}}]}"#));
+        assert_eq!(changed, vec![QuoteField::Bid]);
+        let snapshot = quote_book.get("AMEX:SPY").unwrap();
+        assert_eq!(snapshot.bid(), None);
+        assert!(matches!(snapshot.fields.get(&QuoteField::Bid), Some(miniserde::json::Value::Null)));
+        assert_eq!(snapshot.lp(), Some(450.5));
+    }
+}
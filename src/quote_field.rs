@@ -0,0 +1,465 @@
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::fmt;
+
+use miniserde::de::Visitor;
+use miniserde::ser::Fragment;
+use miniserde::{make_place, Deserialize, Serialize};
+use miniserde::json::{Number, Object, Value};
+
+use crate::json_utilities;
+use crate::timestamp::TradingViewTimestamp;
+
+/// A key of the `v` object in `qsd`, one variant per field `quote_set_fields` can ask for.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum QuoteField {
+    BaseCurrencyLogoid,
+    Ch,
+    Chp,
+    CurrencyLogoid,
+    CurrencyCode,
+    CurrencyId,
+    BaseCurrencyId,
+    CurrentSession,
+    Description,
+    Exchange,
+    Format,
+    Fractional,
+    IsTradable,
+    Language,
+    LocalDescription,
+    ListedExchange,
+    Logoid,
+    Lp,
+    LpTime,
+    Minmov,
+    Minmove2,
+    OriginalName,
+    Pricescale,
+    ProName,
+    ShortName,
+    Type,
+    Typespecs,
+    UpdateMode,
+    Volume,
+    VariableTickSize,
+    ValueUnitId,
+    UnitId,
+    Measure,
+    Rch,
+    Rchp,
+    Rtc,
+    RtcTime,
+    Ask,
+    AskSize,
+    Bid,
+    BidSize,
+    TradeLoaded,
+    OpenPrice,
+    HighPrice,
+    LowPrice,
+    PrevClosePrice,
+    MarketCapBasic,
+    PriceEarningsTtm,
+    EarningsPerShareBasicTtm,
+    DividendsYield,
+    Beta1Year,
+    Sector,
+    Industry,
+    /// A field this client doesn't know yet, kept under its raw name
+    Other(String),
+}
+
+impl QuoteField {
+    /// The name used on the wire.
+    pub fn name(&self) -> &str {
+        match self {
+            QuoteField::BaseCurrencyLogoid => "base-currency-logoid",
+            QuoteField::Ch => "ch",
+            QuoteField::Chp => "chp",
+            QuoteField::CurrencyLogoid => "currency-logoid",
+            QuoteField::CurrencyCode => "currency_code",
+            QuoteField::CurrencyId => "currency_id",
+            QuoteField::BaseCurrencyId => "base_currency_id",
+            QuoteField::CurrentSession => "current_session",
+            QuoteField::Description => "description",
+            QuoteField::Exchange => "exchange",
+            QuoteField::Format => "format",
+            QuoteField::Fractional => "fractional",
+            QuoteField::IsTradable => "is_tradable",
+            QuoteField::Language => "language",
+            QuoteField::LocalDescription => "local_description",
+            QuoteField::ListedExchange => "listed_exchange",
+            QuoteField::Logoid => "logoid",
+            QuoteField::Lp => "lp",
+            QuoteField::LpTime => "lp_time",
+            QuoteField::Minmov => "minmov",
+            QuoteField::Minmove2 => "minmove2",
+            QuoteField::OriginalName => "original_name",
+            QuoteField::Pricescale => "pricescale",
+            QuoteField::ProName => "pro_name",
+            QuoteField::ShortName => "short_name",
+            QuoteField::Type => "type",
+            QuoteField::Typespecs => "typespecs",
+            QuoteField::UpdateMode => "update_mode",
+            QuoteField::Volume => "volume",
+            QuoteField::VariableTickSize => "variable_tick_size",
+            QuoteField::ValueUnitId => "value_unit_id",
+            QuoteField::UnitId => "unit_id",
+            QuoteField::Measure => "measure",
+            QuoteField::Rch => "rch",
+            QuoteField::Rchp => "rchp",
+            QuoteField::Rtc => "rtc",
+            QuoteField::RtcTime => "rtc_time",
+            QuoteField::Ask => "ask",
+            QuoteField::AskSize => "ask_size",
+            QuoteField::Bid => "bid",
+            QuoteField::BidSize => "bid_size",
+            QuoteField::TradeLoaded => "trade_loaded",
+            QuoteField::OpenPrice => "open_price",
+            QuoteField::HighPrice => "high_price",
+            QuoteField::LowPrice => "low_price",
+            QuoteField::PrevClosePrice => "prev_close_price",
+            QuoteField::MarketCapBasic => "market_cap_basic",
+            QuoteField::PriceEarningsTtm => "price_earnings_ttm",
+            QuoteField::EarningsPerShareBasicTtm => "earnings_per_share_basic_ttm",
+            QuoteField::DividendsYield => "dividends_yield",
+            QuoteField::Beta1Year => "beta_1_year",
+            QuoteField::Sector => "sector",
+            QuoteField::Industry => "industry",
+            QuoteField::Other(name) => name,
+        }
+    }
+
+    pub fn from_name(name: &str) -> Self {
+        match name {
+            "base-currency-logoid" => QuoteField::BaseCurrencyLogoid,
+            "ch" => QuoteField::Ch,
+            "chp" => QuoteField::Chp,
+            "currency-logoid" => QuoteField::CurrencyLogoid,
+            "currency_code" => QuoteField::CurrencyCode,
+            "currency_id" => QuoteField::CurrencyId,
+            "base_currency_id" => QuoteField::BaseCurrencyId,
+            "current_session" => QuoteField::CurrentSession,
+            "description" => QuoteField::Description,
+            "exchange" => QuoteField::Exchange,
+            "format" => QuoteField::Format,
+            "fractional" => QuoteField::Fractional,
+            "is_tradable" => QuoteField::IsTradable,
+            "language" => QuoteField::Language,
+            "local_description" => QuoteField::LocalDescription,
+            "listed_exchange" => QuoteField::ListedExchange,
+            "logoid" => QuoteField::Logoid,
+            "lp" => QuoteField::Lp,
+            "lp_time" => QuoteField::LpTime,
+            "minmov" => QuoteField::Minmov,
+            "minmove2" => QuoteField::Minmove2,
+            "original_name" => QuoteField::OriginalName,
+            "pricescale" => QuoteField::Pricescale,
+            "pro_name" => QuoteField::ProName,
+            "short_name" => QuoteField::ShortName,
+            "type" => QuoteField::Type,
+            "typespecs" => QuoteField::Typespecs,
+            "update_mode" => QuoteField::UpdateMode,
+            "volume" => QuoteField::Volume,
+            "variable_tick_size" => QuoteField::VariableTickSize,
+            "value_unit_id" => QuoteField::ValueUnitId,
+            "unit_id" => QuoteField::UnitId,
+            "measure" => QuoteField::Measure,
+            "rch" => QuoteField::Rch,
+            "rchp" => QuoteField::Rchp,
+            "rtc" => QuoteField::Rtc,
+            "rtc_time" => QuoteField::RtcTime,
+            "ask" => QuoteField::Ask,
+            "ask_size" => QuoteField::AskSize,
+            "bid" => QuoteField::Bid,
+            "bid_size" => QuoteField::BidSize,
+            "trade_loaded" => QuoteField::TradeLoaded,
+            "open_price" => QuoteField::OpenPrice,
+            "high_price" => QuoteField::HighPrice,
+            "low_price" => QuoteField::LowPrice,
+            "prev_close_price" => QuoteField::PrevClosePrice,
+            "market_cap_basic" => QuoteField::MarketCapBasic,
+            "price_earnings_ttm" => QuoteField::PriceEarningsTtm,
+            "earnings_per_share_basic_ttm" => QuoteField::EarningsPerShareBasicTtm,
+            "dividends_yield" => QuoteField::DividendsYield,
+            "beta_1_year" => QuoteField::Beta1Year,
+            "sector" => QuoteField::Sector,
+            "industry" => QuoteField::Industry,
+            _ => QuoteField::Other(name.to_string()),
+        }
+    }
+}
+
+impl QuoteField {
+    /// What the browser chart asks for, used unless the config says otherwise.
+    pub fn default_fields() -> Vec<QuoteField> {
+        vec![
+            QuoteField::BaseCurrencyLogoid, QuoteField::Ch, QuoteField::Chp, QuoteField::CurrencyLogoid, QuoteField::CurrencyCode,
+            QuoteField::CurrencyId, QuoteField::BaseCurrencyId, QuoteField::CurrentSession, QuoteField::Description, QuoteField::Exchange,
+            QuoteField::Format, QuoteField::Fractional, QuoteField::IsTradable, QuoteField::Language, QuoteField::LocalDescription,
+            QuoteField::ListedExchange, QuoteField::Logoid, QuoteField::Lp, QuoteField::LpTime, QuoteField::Minmov, QuoteField::Minmove2,
+            QuoteField::OriginalName, QuoteField::Pricescale, QuoteField::ProName, QuoteField::ShortName, QuoteField::Type,
+            QuoteField::Typespecs, QuoteField::UpdateMode, QuoteField::Volume, QuoteField::VariableTickSize, QuoteField::ValueUnitId,
+            QuoteField::UnitId, QuoteField::Measure,
+        ]
+    }
+
+    /// Just enough to follow the last price.
+    pub fn last_price_fields() -> Vec<QuoteField> {
+        vec![
+            QuoteField::Lp, QuoteField::LpTime, QuoteField::Ch, QuoteField::Chp, QuoteField::Volume, QuoteField::CurrentSession,
+            QuoteField::UpdateMode,
+        ]
+    }
+
+    /// Last price plus top of book, session OHLC and the extended hours (`rtc`) price.
+    pub fn level1_fields() -> Vec<QuoteField> {
+        let mut fields = Self::last_price_fields();
+        fields.extend([
+            QuoteField::Bid, QuoteField::BidSize, QuoteField::Ask, QuoteField::AskSize, QuoteField::OpenPrice, QuoteField::HighPrice,
+            QuoteField::LowPrice, QuoteField::PrevClosePrice, QuoteField::Rch, QuoteField::Rchp, QuoteField::Rtc, QuoteField::RtcTime,
+            QuoteField::TradeLoaded,
+        ]);
+        fields
+    }
+
+    /// Descriptive and fundamental data, which rarely changes during a session.
+    pub fn fundamentals_fields() -> Vec<QuoteField> {
+        vec![
+            QuoteField::Description, QuoteField::Exchange, QuoteField::ListedExchange, QuoteField::Type, QuoteField::Typespecs,
+            QuoteField::CurrencyCode, QuoteField::Pricescale, QuoteField::Minmov, QuoteField::Sector, QuoteField::Industry,
+            QuoteField::MarketCapBasic, QuoteField::PriceEarningsTtm, QuoteField::EarningsPerShareBasicTtm, QuoteField::DividendsYield,
+            QuoteField::Beta1Year,
+        ]
+    }
+}
+
+impl fmt::Display for QuoteField {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl Serialize for QuoteField {
+    fn begin(&self) -> Fragment<'_> {
+        Fragment::Str(Cow::Borrowed(self.name()))
+    }
+}
+
+make_place!(Place);
+
+impl Visitor for Place<QuoteField> {
+    fn string(&mut self, s: &str) -> miniserde::Result<()> {
+        self.out = Some(QuoteField::from_name(s));
+        Ok(())
+    }
+}
+
+impl Deserialize for QuoteField {
+    fn begin(out: &mut Option<Self>) -> &mut dyn Visitor {
+        Place::new(out)
+    }
+}
+
+/// Every field of a quote as received, with typed accessors for the common ones.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct QuoteFieldMap {
+    pub fields: BTreeMap<QuoteField, Value>,
+}
+
+impl QuoteFieldMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Keeps every key of a `qsd` `v` object, known or not.
+    pub fn from_object(object: &Object) -> Self {
+        Self {
+            fields: object.iter().map(|(name, value)| (QuoteField::from_name(name), value.clone())).collect(),
+        }
+    }
+
+    pub fn get(&self, field: &QuoteField) -> Option<&Value> {
+        self.fields.get(field)
+    }
+
+    /// Sets `field`, returning whether the value differs from the one already held.
+    pub fn insert(&mut self, field: QuoteField, value: Value) -> bool {
+        let changed = self.fields.get(&field).map(|existing| miniserde::json::to_string(existing) != miniserde::json::to_string(&value)).unwrap_or(true);
+        self.fields.insert(field, value);
+        changed
+    }
+
+    /// Requested fields that haven't been received.
+    pub fn missing(&self, requested: &[QuoteField]) -> Vec<QuoteField> {
+        requested.iter().filter(|field| !self.fields.contains_key(field)).cloned().collect()
+    }
+
+    /// Received fields that weren't requested.
+    pub fn unexpected(&self, requested: &[QuoteField]) -> Vec<QuoteField> {
+        self.fields.keys().filter(|field| !requested.contains(field)).cloned().collect()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&QuoteField, &Value)> {
+        self.fields.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.fields.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.fields.is_empty()
+    }
+
+    pub fn number(&self, field: &QuoteField) -> Option<Number> {
+        match self.fields.get(field) {
+            Some(Value::Number(number)) => Some(number.clone()),
+            _ => None,
+        }
+    }
+
+    pub fn f64(&self, field: &QuoteField) -> Option<f64> {
+        self.number(field).map(|number| json_utilities::number_to_f64(&number))
+    }
+
+    pub fn string(&self, field: &QuoteField) -> Option<&str> {
+        match self.fields.get(field) {
+            Some(Value::String(value)) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn bool(&self, field: &QuoteField) -> Option<bool> {
+        match self.fields.get(field) {
+            Some(Value::Bool(value)) => Some(*value),
+            _ => None,
+        }
+    }
+
+    /// `lp_time` and `rtc_time` are unix seconds.
+    pub fn timestamp(&self, field: &QuoteField) -> Option<TradingViewTimestamp> {
+        self.fields.get(field).and_then(|value| json_utilities::value_to_i64(value).ok()).map(TradingViewTimestamp::Seconds)
+    }
+
+    pub fn lp(&self) -> Option<f64> {
+        self.f64(&QuoteField::Lp)
+    }
+
+    pub fn ch(&self) -> Option<f64> {
+        self.f64(&QuoteField::Ch)
+    }
+
+    pub fn chp(&self) -> Option<f64> {
+        self.f64(&QuoteField::Chp)
+    }
+
+    pub fn lp_time(&self) -> Option<TradingViewTimestamp> {
+        self.timestamp(&QuoteField::LpTime)
+    }
+
+    pub fn rch(&self) -> Option<f64> {
+        self.f64(&QuoteField::Rch)
+    }
+
+    pub fn rchp(&self) -> Option<f64> {
+        self.f64(&QuoteField::Rchp)
+    }
+
+    pub fn rtc(&self) -> Option<f64> {
+        self.f64(&QuoteField::Rtc)
+    }
+
+    pub fn rtc_time(&self) -> Option<TradingViewTimestamp> {
+        self.timestamp(&QuoteField::RtcTime)
+    }
+
+    pub fn volume(&self) -> Option<f64> {
+        self.f64(&QuoteField::Volume)
+    }
+
+    pub fn bid(&self) -> Option<f64> {
+        self.f64(&QuoteField::Bid)
+    }
+
+    pub fn bid_size(&self) -> Option<f64> {
+        self.f64(&QuoteField::BidSize)
+    }
+
+    pub fn ask(&self) -> Option<f64> {
+        self.f64(&QuoteField::Ask)
+    }
+
+    pub fn ask_size(&self) -> Option<f64> {
+        self.f64(&QuoteField::AskSize)
+    }
+
+    pub fn trade_loaded(&self) -> Option<bool> {
+        self.bool(&QuoteField::TradeLoaded)
+    }
+
+    pub fn description(&self) -> Option<&str> {
+        self.string(&QuoteField::Description)
+    }
+
+    pub fn exchange(&self) -> Option<&str> {
+        self.string(&QuoteField::Exchange)
+    }
+
+    pub fn listed_exchange(&self) -> Option<&str> {
+        self.string(&QuoteField::ListedExchange)
+    }
+
+    pub fn pro_name(&self) -> Option<&str> {
+        self.string(&QuoteField::ProName)
+    }
+
+    pub fn short_name(&self) -> Option<&str> {
+        self.string(&QuoteField::ShortName)
+    }
+
+    pub fn symbol_type(&self) -> Option<&str> {
+        self.string(&QuoteField::Type)
+    }
+
+    pub fn currency_code(&self) -> Option<&str> {
+        self.string(&QuoteField::CurrencyCode)
+    }
+
+    pub fn current_session(&self) -> Option<&str> {
+        self.string(&QuoteField::CurrentSession)
+    }
+
+    pub fn update_mode(&self) -> Option<&str> {
+        self.string(&QuoteField::UpdateMode)
+    }
+
+    pub fn logoid(&self) -> Option<&str> {
+        self.string(&QuoteField::Logoid)
+    }
+
+    pub fn pricescale(&self) -> Option<u64> {
+        self.fields.get(&QuoteField::Pricescale).and_then(|value| json_utilities::value_to_u64(value).ok())
+    }
+
+    pub fn minmov(&self) -> Option<u64> {
+        self.fields.get(&QuoteField::Minmov).and_then(|value| json_utilities::value_to_u64(value).ok())
+    }
+
+    pub fn fractional(&self) -> Option<bool> {
+        self.bool(&QuoteField::Fractional)
+    }
+
+    pub fn is_tradable(&self) -> Option<bool> {
+        self.bool(&QuoteField::IsTradable)
+    }
+
+    /// `typespecs` is a list of tags such as `common` or `etf`.
+    pub fn typespecs(&self) -> Option<Vec<String>> {
+        match self.fields.get(&QuoteField::Typespecs) {
+            Some(Value::Array(typespecs)) => typespecs.iter().map(|typespec| json_utilities::value_to_string(typespec).ok()).collect(),
+            _ => None,
+        }
+    }
+}
@@ -1,8 +1,12 @@
+use std::collections::VecDeque;
+
 use websocket_client::WebSocketReader;
 use bytes::{Buf, BytesMut};
 use futures_lite::io::AsyncRead;
 
+use crate::error::{TradingViewError, TradingViewResult};
 use crate::message_wrapper::TradingViewMessageWrapper;
+use crate::session_registry::TradingViewSessionRegistry;
 
 pub struct TradingViewReader<R>
 where
@@ -10,30 +14,45 @@ where
 {
     ws_reader: WebSocketReader<R>,
     buffer: BytesMut,
+    registry: TradingViewSessionRegistry,
+    /// Messages of a frame that batched several, handed out one per call
+    pending: VecDeque<TradingViewMessageWrapper>,
 }
 
 impl<R> TradingViewReader<R>
 where
     R: AsyncRead + Unpin,
 {
-    /// Creates a new `TradingViewReader` with the given `WebSocketReader`.
-    pub fn new(ws_reader: WebSocketReader<R>) -> Self {
+    /// Creates a new `TradingViewReader` with the given `WebSocketReader`, decoding updates for the objects in `registry`.
+    pub fn new(ws_reader: WebSocketReader<R>, registry: TradingViewSessionRegistry) -> Self {
         Self {
             ws_reader,
             buffer: BytesMut::with_capacity(1024 * 1024),
+            registry,
+            pending: VecDeque::new(),
         }
     }
 
     /// Reads the next TradingView message, handling partial messages and buffering.
-    pub async fn read_message(&mut self) -> anyhow::Result<Option<TradingViewMessageWrapper>> {
+    pub async fn read_message(&mut self) -> TradingViewResult<Option<TradingViewMessageWrapper>> {
         loop {
-            // Try to parse a TradingView message from the tv_buffer
-            if let Some(message) = self.parse_message()? {
+            if let Some(message) = self.pending.pop_front() {
                 return Ok(Some(message));
             }
 
+            // Try to parse a TradingView message from the tv_buffer
+            if let Some(payload) = self.parse_frame()? {
+                let messages = TradingViewMessageWrapper::messages_from_payload(payload, &self.registry)?;
+                // status has to be current before the message is dispatched to whoever waits on it
+                for message in &messages {
+                    self.registry.record_status(&message.parsed_message);
+                }
+                self.pending.extend(messages);
+                continue;
+            }
+
             // Need more data; read the next WebSocket message
-            match self.ws_reader.read_message().await? {
+            match self.ws_reader.read_message().await.map_err(TradingViewError::Transport)? {
                 Some(ws_message) => {
                     match ws_message.opcode {
                         Some(opcode) => {
@@ -45,10 +64,17 @@ where
                                 websocket_client::WebSocketOpcode::Text => {
                                     self.buffer.extend_from_slice(&ws_message.payload);
                                 },
-                                _ => todo!()
+                                websocket_client::WebSocketOpcode::Ping | websocket_client::WebSocketOpcode::Pong => {
+                                    log::debug!("ignoring websocket control frame {opcode:?}");
+                                },
+                                _ => {
+                                    return Err(TradingViewError::Protocol(format!("unexpected websocket opcode {opcode:?}")));
+                                }
                             }
                         },
-                        None => todo!(),
+                        None => {
+                            return Err(TradingViewError::Framing("websocket frame without opcode".to_string()));
+                        },
                     }
                 }
                 None => {
@@ -56,31 +82,27 @@ where
                     if self.buffer.is_empty() {
                         return Ok(None);
                     } else {
-                        return Err(std::io::Error::new(
-                            std::io::ErrorKind::UnexpectedEof,
-                            "Stream closed with incomplete TradingView message",
-                        )
-                        .into());
+                        return Err(TradingViewError::Framing("stream closed with incomplete TradingView message".to_string()));
                     }
                 }
             }
         }
     }
 
-    /// Parses a TradingView message from the buffer.
-    fn parse_message(&mut self) -> anyhow::Result<Option<TradingViewMessageWrapper>> {
+    /// Parses a TradingView frame from the buffer.
+    fn parse_frame(&mut self) -> TradingViewResult<Option<String>> {
         if self.buffer.is_empty() {
             return Ok(None);
         }
 
         let input = &self.buffer[..];
 
-        match TradingViewMessageWrapper::parse(input) {
-            Ok((remaining, message)) => {
+        match TradingViewMessageWrapper::parse_frame(input) {
+            Ok((remaining, payload)) => {
                 let parsed_len = input.len() - remaining.len();
                 self.buffer.advance(parsed_len);
 
-                Ok(Some(message))
+                Ok(Some(payload))
             }
             Err(nom::Err::Incomplete(_)) => {
                 // Not enough data, need to read more
@@ -88,12 +110,8 @@ where
             }
             Err(e) => {
                 // Parsing error
-                Err(std::io::Error::new(
-                    std::io::ErrorKind::InvalidData,
-                    format!("Parsing error: {:?}", e),
-                )
-                .into())
+                Err(TradingViewError::Framing(format!("{e:?}")))
             }
         }
     }
-}
\ No newline at end of file
+}
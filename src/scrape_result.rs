@@ -1,5 +1,7 @@
 use miniserde::Serialize;
 
+use crate::quote_field::QuoteField;
+
 use crate::parsed_message::{
     ServerHelloMessage,
     QuoteSeriesDataMessage,
@@ -13,6 +15,14 @@ use crate::parsed_message::{
     StudyCompletedMessage,
 };
 
+/// Requested quote fields a symbol hadn't sent by the end of setup.
+#[derive(Debug, Clone, Serialize)]
+pub struct QuoteMissingFields {
+    pub quote_session_id: String,
+    pub symbol: String,
+    pub fields: Vec<QuoteField>,
+}
+
 #[derive(Debug, Serialize)]
 pub struct TradingViewScrapeResult {
     pub server_hello_messages: Vec<ServerHelloMessage>,
@@ -24,6 +34,7 @@ pub struct TradingViewScrapeResult {
     pub study_completed_messages: Vec<StudyCompletedMessage>,
     pub quote_completed_messages: Vec<QuoteCompletedMessage>,
     pub quote_last_price_messages: Vec<QuoteSeriesDataMessage>,
+    pub quote_missing_fields: Vec<QuoteMissingFields>,
     pub series_data_update_messages: Vec<DataUpdateMessage>, // TODO: split series and study?    
     pub study_data_update_messages: Vec<DataUpdateMessage>, // TODO: split series and study?
 }
@@ -0,0 +1,138 @@
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+
+use crate::parsed_message::{ParsedTradingViewMessage, TradingViewUpdateMode};
+use crate::quote_field::QuoteField;
+use crate::study_metadata::TradingViewStudyMetadata;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TradingViewSessionObjectKind {
+    Series,
+    Study,
+}
+
+/// Where a series or study is in its `*_loading` -> `*_completed` cycle.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TradingViewObjectStatus {
+    /// Created or asked for more data, the server hasn't started loading yet
+    Requested,
+    Loading {
+        turnaround: Option<String>,
+    },
+    Completed {
+        turnaround: Option<String>,
+        /// Only series report one
+        update_mode: Option<TradingViewUpdateMode>,
+    },
+    /// Only studies fail this way, with a `study_error`
+    Failed {
+        error: String,
+    },
+}
+
+/// Every series and study created on a connection, keyed by chart session id + object id, plus the fields each quote
+/// session asked for, the plot layout of the studies that came with one and where each object is in loading.
+///
+/// Shared between the writer, which records objects as they are created, and the reader, which needs to know
+/// whether a `du` update key is a series or a study and records the loading status messages.
+#[derive(Debug, Clone, Default)]
+pub struct TradingViewSessionRegistry {
+    objects: Arc<RwLock<HashMap<(String, String), TradingViewSessionObjectKind>>>,
+    quote_fields: Arc<RwLock<HashMap<String, Vec<QuoteField>>>>,
+    study_metadata: Arc<RwLock<HashMap<(String, String), TradingViewStudyMetadata>>>,
+    statuses: Arc<RwLock<HashMap<(String, String), TradingViewObjectStatus>>>,
+}
+
+impl TradingViewSessionRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn register_series(&self, chart_session_id: &str, series_id: &str) {
+        self.register(chart_session_id, series_id, TradingViewSessionObjectKind::Series);
+    }
+
+    pub fn register_study(&self, chart_session_id: &str, study_id: &str) {
+        self.register(chart_session_id, study_id, TradingViewSessionObjectKind::Study);
+    }
+
+    fn register(&self, chart_session_id: &str, object_id: &str, kind: TradingViewSessionObjectKind) {
+        let mut objects = self.objects.write().expect("registry lock poisoned");
+        objects.insert((chart_session_id.to_string(), object_id.to_string()), kind);
+    }
+
+    pub fn register_quote_fields(&self, quote_session_id: &str, fields: &[QuoteField]) {
+        let mut quote_fields = self.quote_fields.write().expect("registry lock poisoned");
+        quote_fields.insert(quote_session_id.to_string(), fields.to_vec());
+    }
+
+    /// Fields set with `quote_set_fields` on `quote_session_id`, `None` if it never set any.
+    pub fn quote_fields(&self, quote_session_id: &str) -> Option<Vec<QuoteField>> {
+        let quote_fields = self.quote_fields.read().expect("registry lock poisoned");
+        quote_fields.get(quote_session_id).cloned()
+    }
+
+    pub fn register_study_metadata(&self, chart_session_id: &str, study_id: &str, metadata: &TradingViewStudyMetadata) {
+        let mut study_metadata = self.study_metadata.write().expect("registry lock poisoned");
+        study_metadata.insert((chart_session_id.to_string(), study_id.to_string()), metadata.clone());
+    }
+
+    /// Plot layout registered for `study_id`, `None` if the study was created without one.
+    pub fn study_metadata(&self, chart_session_id: &str, study_id: &str) -> Option<TradingViewStudyMetadata> {
+        let study_metadata = self.study_metadata.read().expect("registry lock poisoned");
+        study_metadata.get(&(chart_session_id.to_string(), study_id.to_string())).cloned()
+    }
+
+    pub fn set_status(&self, chart_session_id: &str, object_id: &str, status: TradingViewObjectStatus) {
+        let mut statuses = self.statuses.write().expect("registry lock poisoned");
+        statuses.insert((chart_session_id.to_string(), object_id.to_string()), status);
+    }
+
+    /// Loading status of a series or study, `None` if it was never created.
+    pub fn status(&self, chart_session_id: &str, object_id: &str) -> Option<TradingViewObjectStatus> {
+        let statuses = self.statuses.read().expect("registry lock poisoned");
+        statuses.get(&(chart_session_id.to_string(), object_id.to_string())).cloned()
+    }
+
+    /// Moves the object a loading, completed or study error message is about to its new status, ignores every other message.
+    pub fn record_status(&self, message: &ParsedTradingViewMessage) {
+        let (chart_session_id, object_id, status) = match message {
+            ParsedTradingViewMessage::SeriesLoading(message) => (&message.chart_session_id, &message.series_id, TradingViewObjectStatus::Loading {
+                turnaround: message.turnaround.clone(),
+            }),
+            ParsedTradingViewMessage::SeriesCompleted(message) => (&message.chart_session_id, &message.series_id, TradingViewObjectStatus::Completed {
+                turnaround: message.turnaround.clone(),
+                update_mode: message.update_mode.clone(),
+            }),
+            ParsedTradingViewMessage::StudyLoading(message) => (&message.chart_session_id, &message.study_id, TradingViewObjectStatus::Loading {
+                turnaround: message.turnaround.clone(),
+            }),
+            ParsedTradingViewMessage::StudyCompleted(message) => (&message.chart_session_id, &message.study_id, TradingViewObjectStatus::Completed {
+                turnaround: message.turnaround.clone(),
+                update_mode: None,
+            }),
+            ParsedTradingViewMessage::StudyError(message) => (&message.chart_session_id, &message.study_id, TradingViewObjectStatus::Failed {
+                error: message.error.clone(),
+            }),
+            _ => return
+        };
+        self.set_status(chart_session_id, object_id, status);
+    }
+
+    pub fn lookup(&self, chart_session_id: &str, object_id: &str) -> Option<TradingViewSessionObjectKind> {
+        let objects = self.objects.read().expect("registry lock poisoned");
+        objects.get(&(chart_session_id.to_string(), object_id.to_string())).copied()
+    }
+
+    /// Ids of every object of `kind` created in `chart_session_id`.
+    pub fn object_ids(&self, chart_session_id: &str, kind: TradingViewSessionObjectKind) -> Vec<String> {
+        let objects = self.objects.read().expect("registry lock poisoned");
+        let mut object_ids = objects
+            .iter()
+            .filter(|((session_id, _), object_kind)| session_id == chart_session_id && **object_kind == kind)
+            .map(|((_, object_id), _)| object_id.clone())
+            .collect::<Vec<_>>();
+        object_ids.sort();
+        object_ids
+    }
+}
@@ -0,0 +1,199 @@
+use std::collections::BTreeMap;
+
+use miniserde::Serialize;
+
+use crate::bar_series::{Bar, BarSeries};
+use crate::study_metadata::StudyRow;
+use crate::timestamp::TradingViewTimestamp;
+
+/// Rows held back waiting for their bar, the oldest are dropped beyond this.
+const MAX_PENDING_ROWS: usize = 10_000;
+
+/// A study row together with the bar it was computed on.
+#[derive(Debug, Clone, Serialize)]
+pub struct TimestampedStudyRow {
+    pub row: StudyRow,
+    /// The bar with the row's index, as it stood when the row was joined
+    pub bar: Bar,
+}
+
+/// Pairs the rows of one study with the bars of the series it runs on, by bar index.
+///
+/// Either side may arrive first: a row whose bar isn't known yet is held until a series update brings it.
+#[derive(Debug, Clone)]
+pub struct StudyBarJoin {
+    pub chart_session_id: String,
+    pub study_id: String,
+    pub series_id: String,
+    pending: BTreeMap<i64, StudyRow>,
+}
+
+impl TimestampedStudyRow {
+    /// Open time of the bar.
+    pub fn timestamp(&self) -> TradingViewTimestamp {
+        self.bar.timestamp
+    }
+
+    pub fn plot(&self, name: &str) -> Option<f64> {
+        self.row.plot(name)
+    }
+}
+
+impl StudyBarJoin {
+    pub fn new(chart_session_id: &str, study_id: &str, series_id: &str) -> Self {
+        Self {
+            chart_session_id: chart_session_id.to_string(),
+            study_id: study_id.to_string(),
+            series_id: series_id.to_string(),
+            pending: BTreeMap::new(),
+        }
+    }
+
+    /// Joins `rows` against `series`, holding back the ones whose bar hasn't arrived yet. A newer row for an index that is still
+    /// pending replaces it.
+    pub fn apply_rows(&mut self, rows: &[StudyRow], series: &BarSeries) -> Vec<TimestampedStudyRow> {
+        let mut joined = vec![];
+        for row in rows {
+            match series.get(row.index) {
+                Some(bar) => {
+                    self.pending.remove(&row.index);
+                    joined.push(TimestampedStudyRow {
+                        row: row.clone(),
+                        bar: bar.clone(),
+                    });
+                },
+                None => {
+                    self.pending.insert(row.index, row.clone());
+                }
+            }
+        }
+        while self.pending.len() > MAX_PENDING_ROWS {
+            if let Some((index, _)) = self.pending.pop_first() {
+                log::warn!("[{}:{}] no bar for study row {index}, dropped", self.chart_session_id, self.study_id);
+            }
+        }
+        joined
+    }
+
+    /// Releases the held back rows `series` now has bars for, call after applying series updates.
+    pub fn apply_bars(&mut self, series: &BarSeries) -> Vec<TimestampedStudyRow> {
+        let ready = self.pending.keys().copied().filter(|index| series.get(*index).is_some()).collect::<Vec<_>>();
+        ready.into_iter().filter_map(|index| {
+            let row = self.pending.remove(&index)?;
+            let bar = series.get(index)?.clone();
+            Some(TimestampedStudyRow { row, bar })
+        }).collect()
+    }
+
+    /// Moves the held back rows `offset` indexes up, along with the bars of the series when history is prepended.
+    pub fn renumber(&mut self, offset: i64) {
+        if offset == 0 {
+            return;
+        }
+        self.pending = std::mem::take(&mut self.pending)
+            .into_iter()
+            .map(|(index, mut row)| {
+                row.index += offset;
+                (index + offset, row)
+            })
+            .collect();
+    }
+
+    /// Number of rows still waiting for their bar.
+    pub fn pending_len(&self) -> usize {
+        self.pending.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bar(index: i64) -> Bar {
+        Bar {
+            index,
+            timestamp: TradingViewTimestamp::Seconds(1_700_000_100 + index * 300),
+            open: 100.0,
+            high: 101.0,
+            low: 99.0,
+            close: 100.5,
+            volume: 1000.0,
+        }
+    }
+
+    fn row(index: i64, value: f64) -> StudyRow {
+        StudyRow {
+            index,
+            timestamp: TradingViewTimestamp::Seconds(1_700_000_100 + index * 300),
+            plots: BTreeMap::from([("Close".to_string(), value)]),
+        }
+    }
+
+    fn series(indexes: std::ops::Range<i64>) -> BarSeries {
+        let mut series = BarSeries::new("cs_000000000001", "sds_1");
+        for index in indexes {
+            series.apply_bar(bar(index));
+        }
+        series
+    }
+
+    #[test]
+    fn row_ahead_of_its_bar_is_released_when_the_bar_arrives() {
+        let mut join = StudyBarJoin::new("cs_000000000001", "st2", "sds_1");
+        let mut series = series(0..2);
+
+        let joined = join.apply_rows(&[row(1, 1.0), row(2, 2.0)], &series);
+        assert_eq!(joined.iter().map(|row| row.row.index).collect::<Vec<_>>(), vec![1]);
+        assert_eq!(join.pending_len(), 1);
+        assert!(join.apply_bars(&series).is_empty());
+
+        // a newer row for the same index replaces the held one
+        assert!(join.apply_rows(&[row(2, 2.5)], &series).is_empty());
+        assert_eq!(join.pending_len(), 1);
+
+        series.apply_bar(bar(2));
+        let released = join.apply_bars(&series);
+        assert_eq!(released.len(), 1);
+        assert_eq!(released[0].plot("Close"), Some(2.5));
+        assert_eq!(released[0].bar, bar(2));
+        assert_eq!(released[0].timestamp(), bar(2).timestamp);
+        assert_eq!(join.pending_len(), 0);
+        assert!(join.apply_bars(&series).is_empty());
+    }
+
+    #[test]
+    fn oldest_pending_rows_are_evicted_beyond_the_limit() {
+        let mut join = StudyBarJoin::new("cs_000000000001", "st2", "sds_1");
+        let mut series = series(0..1);
+
+        let rows = (1..=MAX_PENDING_ROWS as i64 + 5).map(|index| row(index, index as f64)).collect::<Vec<_>>();
+        assert!(join.apply_rows(&rows, &series).is_empty());
+        assert_eq!(join.pending_len(), MAX_PENDING_ROWS);
+
+        // rows 1 to 5 were dropped, 6 is the oldest still held
+        for index in 1..=6 {
+            series.apply_bar(bar(index));
+        }
+        let released = join.apply_bars(&series);
+        assert_eq!(released.iter().map(|row| row.row.index).collect::<Vec<_>>(), vec![6]);
+    }
+
+    #[test]
+    fn renumber_moves_pending_rows_with_the_bars() {
+        let mut join = StudyBarJoin::new("cs_000000000001", "st2", "sds_1");
+        let mut series = series(0..2);
+        assert!(join.apply_rows(&[row(2, 2.0), row(3, 3.0)], &series).is_empty());
+
+        // 10 bars of history were prepended: the bars move up and so do the rows waiting for them
+        series.renumber(10);
+        join.renumber(10);
+        assert!(join.apply_bars(&series).is_empty());
+        series.apply_bar(bar(12));
+        let released = join.apply_bars(&series);
+        assert_eq!(released.len(), 1);
+        assert_eq!(released[0].row.index, 12);
+        assert_eq!(released[0].bar.index, 12);
+        assert_eq!(released[0].plot("Close"), Some(2.0));
+        assert_eq!(join.pending_len(), 1);
+    }
+}
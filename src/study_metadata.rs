@@ -0,0 +1,134 @@
+use std::collections::BTreeMap;
+
+use miniserde::{Deserialize, Serialize};
+
+use crate::error::{TradingViewError, TradingViewResult};
+use crate::parsed_message::StudyUpdate;
+use crate::timestamp::TradingViewTimestamp;
+
+/// One output column of a study.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StudyPlot {
+    /// Id the script assigns, e.g. `plot_0`
+    pub id: String,
+    /// Title from the script's `plot(title=...)`
+    pub title: Option<String>,
+    /// `line`, `shapes`, `colorer`, ...
+    pub plot_type: Option<String>,
+}
+
+/// Plot layout of a study, so the values of a `du` row can be read by name instead of by position.
+///
+/// The websocket never sends the layout, `study_loading` and `study_completed` carry no plot information, so it is supplied
+/// with the indicator (`TradingViewIndicator::with_metadata`), e.g. from the script's `plot(title=...)` calls.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TradingViewStudyMetadata {
+    /// In the order the values appear in a row, after the timestamp
+    pub plots: Vec<StudyPlot>,
+}
+
+/// A study row with its values keyed by plot name.
+#[derive(Debug, Clone, Serialize)]
+pub struct StudyRow {
+    pub index: i64,
+    pub timestamp: TradingViewTimestamp,
+    pub plots: BTreeMap<String, f64>,
+}
+
+impl TradingViewStudyMetadata {
+    pub fn new(plots: Vec<StudyPlot>) -> Self {
+        Self { plots }
+    }
+
+    /// Plots titled `titles` in order, with ids `plot_0`, `plot_1`, ...
+    pub fn from_titles(titles: &[&str]) -> Self {
+        let plots = titles.iter().enumerate().map(|(position, title)| StudyPlot {
+            id: format!("plot_{position}"),
+            title: Some(title.to_string()),
+            plot_type: None,
+        }).collect();
+        Self { plots }
+    }
+
+    /// Names for the plots in order: the title, or the id when the title is missing or already taken by an earlier plot.
+    pub fn plot_names(&self) -> Vec<String> {
+        let mut names: Vec<String> = vec![];
+        for plot in &self.plots {
+            let name = match &plot.title {
+                Some(title) if !names.contains(title) => title.clone(),
+                _ => plot.id.clone()
+            };
+            names.push(name);
+        }
+        names
+    }
+
+    /// Position of the plot named `name` among the values of a row, after the timestamp.
+    pub fn plot_position(&self, name: &str) -> Option<usize> {
+        self.plot_names().iter().position(|plot_name| plot_name == name)
+    }
+}
+
+impl StudyRow {
+    /// Names the values of `update`, the first value is the bar timestamp and the rest are the plots. Without metadata, or for values
+    /// past the known plots, the names are `plot_<n>`.
+    pub fn from_update(update: &StudyUpdate, metadata: Option<&TradingViewStudyMetadata>) -> TradingViewResult<Self> {
+        let (timestamp, values) = update.values.split_first().ok_or(TradingViewError::Schema(format!("study row {:?} without timestamp", update.index)))?;
+        let names = metadata.map(|metadata| metadata.plot_names()).unwrap_or_default();
+        let plots = values.iter().enumerate().map(|(position, value)| {
+            let name = names.get(position).cloned().unwrap_or_else(|| format!("plot_{position}"));
+            (name, *value)
+        }).collect();
+        Ok(StudyRow {
+            index: update.index,
+            timestamp: TradingViewTimestamp::Seconds(*timestamp as i64),
+            plots,
+        })
+    }
+
+    pub fn plot(&self, name: &str) -> Option<f64> {
+        self.plots.get(name).copied()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn update(values: Vec<f64>) -> StudyUpdate {
+        StudyUpdate { index: 299, values }
+    }
+
+    #[test]
+    fn plot_names_fall_back_to_the_id_for_missing_and_repeated_titles() {
+        let metadata = TradingViewStudyMetadata::new(vec![
+            StudyPlot { id: "plot_0".to_string(), title: Some("Basis".to_string()), plot_type: Some("line".to_string()) },
+            StudyPlot { id: "plot_1".to_string(), title: None, plot_type: Some("line".to_string()) },
+            StudyPlot { id: "plot_2".to_string(), title: Some("Basis".to_string()), plot_type: Some("line".to_string()) },
+        ]);
+        assert_eq!(metadata.plot_names(), vec!["Basis", "plot_1", "plot_2"]);
+        assert_eq!(metadata.plot_position("Basis"), Some(0));
+        assert_eq!(metadata.plot_position("plot_2"), Some(2));
+        assert_eq!(metadata.plot_position("Upper"), None);
+    }
+
+    #[test]
+    fn rows_are_named_after_the_metadata_and_numbered_past_it() {
+        let metadata = TradingViewStudyMetadata::from_titles(&["Basis", "Upper"]);
+        let row = StudyRow::from_update(&update(vec![1_700_000_100.0, 100.5, 102.0, 7.0]), Some(&metadata)).unwrap();
+        assert_eq!(row.index, 299);
+        assert_eq!(row.timestamp, TradingViewTimestamp::Seconds(1_700_000_100));
+        assert_eq!(row.plot("Basis"), Some(100.5));
+        assert_eq!(row.plot("Upper"), Some(102.0));
+        assert_eq!(row.plot("plot_2"), Some(7.0));
+        assert_eq!(row.plot("Lower"), None);
+
+        let row = StudyRow::from_update(&update(vec![1_700_000_100.0, 100.5]), None).unwrap();
+        assert_eq!(row.plot("plot_0"), Some(100.5));
+    }
+
+    #[test]
+    fn row_without_timestamp_is_a_schema_error() {
+        assert!(matches!(StudyRow::from_update(&update(vec![]), None), Err(TradingViewError::Schema(_))));
+    }
+}
@@ -0,0 +1,94 @@
+use miniserde::Serialize;
+use miniserde::json::Object;
+
+use crate::error::TradingViewResult;
+use crate::json_utilities;
+use crate::price::TradingViewPrice;
+
+/// One of the trading sessions a symbol offers (regular, extended, ...).
+#[derive(Debug, Clone, Serialize)]
+pub struct Subsession {
+    pub id: String,
+    pub description: Option<String>,
+    /// Session string in TradingView's `HHMM-HHMM[:days]` notation, e.g. `0930-1600`
+    pub session: Option<String>,
+    pub session_display: Option<String>,
+    pub private: Option<bool>,
+}
+
+/// Metadata from `symbol_resolved`, needed to interpret prices and session times for the symbol.
+#[derive(Debug, Clone, Serialize)]
+pub struct SymbolInfo {
+    pub name: Option<String>,
+    pub full_name: Option<String>,
+    pub pro_name: Option<String>,
+    pub description: Option<String>,
+    pub exchange: Option<String>,
+    pub listed_exchange: Option<String>,
+    pub symbol_type: Option<String>,
+    pub currency_code: Option<String>,
+    /// Prices are quoted in ticks of `minmov / pricescale`
+    pub pricescale: u64,
+    pub minmov: u64,
+    pub minmove2: Option<u64>,
+    pub fractional: Option<bool>,
+    pub session: Option<String>,
+    pub subsession_id: Option<String>,
+    pub subsessions: Vec<Subsession>,
+    pub timezone: Option<String>,
+    pub has_intraday: Option<bool>,
+}
+
+impl Subsession {
+    pub fn from_object(object: &Object) -> TradingViewResult<Self> {
+        Ok(Subsession {
+            id: json_utilities::value_to_string(json_utilities::get_key(object, "id")?)?,
+            description: json_utilities::get_optional_string(object, "description")?,
+            session: json_utilities::get_optional_string(object, "session")?,
+            session_display: json_utilities::get_optional_string(object, "session-display")?,
+            private: json_utilities::get_optional_bool(object, "private")?,
+        })
+    }
+}
+
+impl SymbolInfo {
+    /// Decodes the symbol object at `p[2]` of a `symbol_resolved` message.
+    pub fn from_object(object: &Object) -> TradingViewResult<Self> {
+        let subsessions = match object.get("subsessions") {
+            Some(subsessions) => json_utilities::value_to_array(subsessions)?
+                .iter()
+                .map(|subsession| Subsession::from_object(&json_utilities::value_to_object(subsession)?))
+                .collect::<TradingViewResult<Vec<_>>>()?,
+            None => vec![],
+        };
+        Ok(SymbolInfo {
+            name: json_utilities::get_optional_string(object, "name")?,
+            full_name: json_utilities::get_optional_string(object, "full_name")?,
+            pro_name: json_utilities::get_optional_string(object, "pro_name")?,
+            description: json_utilities::get_optional_string(object, "description")?,
+            exchange: json_utilities::get_optional_string(object, "exchange")?,
+            listed_exchange: json_utilities::get_optional_string(object, "listed_exchange")?,
+            symbol_type: json_utilities::get_optional_string(object, "type")?,
+            currency_code: json_utilities::get_optional_string(object, "currency_code")?,
+            pricescale: json_utilities::value_to_u64(json_utilities::get_key(object, "pricescale")?)?,
+            minmov: json_utilities::value_to_u64(json_utilities::get_key(object, "minmov")?)?,
+            minmove2: json_utilities::get_optional_u64(object, "minmove2")?,
+            fractional: json_utilities::get_optional_bool(object, "fractional")?,
+            session: json_utilities::get_optional_string(object, "session")?,
+            subsession_id: json_utilities::get_optional_string(object, "subsession_id")?,
+            subsessions,
+            timezone: json_utilities::get_optional_string(object, "timezone")?,
+            has_intraday: json_utilities::get_optional_bool(object, "has_intraday")?,
+        })
+    }
+
+    /// Smallest price increment, `minmov / pricescale`.
+    pub fn tick_size(&self) -> f64 {
+        self.minmov as f64 / self.pricescale as f64
+    }
+
+    /// `value` as an exact decimal on this symbol's tick grid, `None` for symbols with fractional prices.
+    pub fn price(&self, value: f64) -> Option<TradingViewPrice> {
+        TradingViewPrice::from_f64(value, self.pricescale, self.minmov)
+    }
+}
@@ -1,3 +1,3 @@
-// super awkward wrapper of stringified JSON wrapped around AMEX:SPY
-pub static SPY5_REG_SYMBOL: &'static str = r#"={\"adjustment\":\"splits\",\"currency-id\":\"USD\",\"session\":\"regular\",\"symbol\":\"AMEX:SPY\"}"#;
-pub static SPY5_EXT_SYMBOL: &'static str = r#"={\"adjustment\":\"splits\",\"currency-id\":\"USD\",\"session\":\"extended\",\"symbol\":\"AMEX:SPY\"}"#;
+// stringified JSON wrapped around AMEX:SPY, the writer takes care of escaping it into the frame
+pub static SPY5_REG_SYMBOL: &'static str = r#"={"adjustment":"splits","currency-id":"USD","session":"regular","symbol":"AMEX:SPY"}"#;
+pub static SPY5_EXT_SYMBOL: &'static str = r#"={"adjustment":"splits","currency-id":"USD","session":"extended","symbol":"AMEX:SPY"}"#;
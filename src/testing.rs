@@ -0,0 +1,787 @@
+use std::collections::HashMap;
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+use async_channel::Sender;
+use async_executor::{Executor, Task};
+use async_io::{Async, Timer};
+use async_lock::Mutex;
+use bytes::{Buf, BytesMut};
+use futures_lite::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader};
+use futures_lite::StreamExt;
+use http::{HeaderMap, HeaderName, HeaderValue};
+use miniserde::json::{Array, Number, Object, Value};
+
+use crate::command::TradingViewCommand;
+use crate::message_wrapper::TradingViewMessageWrapper;
+use crate::transport;
+use crate::utilities;
+
+/// Close time of the newest bar the mock serves, every series ends here.
+const MOCK_LAST_BAR_TIMESTAMP: u64 = 1_700_000_100;
+
+const OPCODE_CONTINUATION: u8 = 0x0;
+const OPCODE_TEXT: u8 = 0x1;
+const OPCODE_CLOSE: u8 = 0x8;
+const OPCODE_PING: u8 = 0x9;
+const OPCODE_PONG: u8 = 0xA;
+
+/// Something the mock server sends back in response to a command or a timer.
+#[derive(Debug, Clone)]
+pub enum MockTradingViewReply {
+    /// A payload that gets wrapped in `~m~len~m~`
+    Message(String),
+    /// A `~h~nonce` heartbeat
+    Ping(usize),
+    /// Closes the websocket once the replies before it are written
+    Close,
+}
+
+impl MockTradingViewReply {
+    /// Builds a `{"m": method, "p": params}` message.
+    pub fn message(method: &str, params: Vec<Value>) -> Self {
+        let mut p = Array::new();
+        p.extend(params);
+        let mut message = Object::new();
+        message.insert("m".to_string(), string(method));
+        message.insert("p".to_string(), Value::Array(p));
+        MockTradingViewReply::Message(miniserde::json::to_string(&message))
+    }
+
+    pub fn study_error(chart_session_id: &str, study_id: &str, error: &str) -> Self {
+        Self::message("study_error", vec![string(chart_session_id), string(study_id), string("st1"), string(error), Value::Object(Object::new())])
+    }
+
+    pub fn critical_error(chart_session_id: &str, error: &str, method: &str) -> Self {
+        Self::message("critical_error", vec![string(chart_session_id), string(error), string(method)])
+    }
+
+    pub fn protocol_error(error: &str) -> Self {
+        Self::message("protocol_error", vec![string(error)])
+    }
+
+    pub fn notify_user(notification_type: &str, text: &str) -> Self {
+        let mut notice = Object::new();
+        notice.insert("type".to_string(), string(notification_type));
+        notice.insert("message".to_string(), string(text));
+        Self::message("notify_user", vec![Value::Object(notice)])
+    }
+}
+
+pub type MockTradingViewHandler = Arc<dyn Fn(&TradingViewCommand) -> Vec<MockTradingViewReply> + Send + Sync>;
+
+/// How the mock server behaves: the server hello, which commands get canned replies and which are overridden.
+#[derive(Clone)]
+pub struct MockTradingViewScript {
+    hello: Option<String>,
+    canned: bool,
+    handlers: HashMap<String, MockTradingViewHandler>,
+    ping_interval: Option<Duration>,
+    tick_interval: Option<Duration>,
+    ticks_per_bar: Option<usize>,
+    history_depth: usize,
+}
+
+impl Default for MockTradingViewScript {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MockTradingViewScript {
+    /// Replies to chart, study and quote commands the way TradingView does, with deterministic bars and quotes.
+    pub fn new() -> Self {
+        Self {
+            hello: Some(hello_at(MOCK_LAST_BAR_TIMESTAMP)),
+            canned: true,
+            handlers: HashMap::new(),
+            ping_interval: None,
+            tick_interval: None,
+            ticks_per_bar: None,
+            history_depth: 10_000,
+        }
+    }
+
+    /// Sends the server hello and nothing else unless a handler is registered.
+    pub fn empty() -> Self {
+        Self {
+            canned: false,
+            ..Self::new()
+        }
+    }
+
+    /// Replaces the canned reply for `method` (e.g. `create_study`) with `handler`.
+    pub fn on<F>(mut self, method: &str, handler: F) -> Self
+    where
+        F: Fn(&TradingViewCommand) -> Vec<MockTradingViewReply> + Send + Sync + 'static,
+    {
+        self.handlers.insert(method.to_string(), Arc::new(handler));
+        self
+    }
+
+    /// Payload sent right after the upgrade, `None` to send nothing.
+    pub fn with_hello(mut self, hello: Option<String>) -> Self {
+        self.hello = hello;
+        self
+    }
+
+    /// Sets the server clock of the hello to this many seconds after the open of the last bar (the default is right at it).
+    pub fn with_clock_offset(mut self, seconds: u64) -> Self {
+        self.hello = Some(hello_at(MOCK_LAST_BAR_TIMESTAMP + seconds));
+        self
+    }
+
+    /// Sends a `~h~` heartbeat at this interval.
+    pub fn with_ping_interval(mut self, interval: Duration) -> Self {
+        self.ping_interval = Some(interval);
+        self
+    }
+
+    /// Updates the forming bar of every series and the last price of every quote symbol at this interval.
+    pub fn with_tick_interval(mut self, interval: Duration) -> Self {
+        self.tick_interval = Some(interval);
+        self
+    }
+
+    /// Opens a new bar on every series after this many ticks, closing the one before it.
+    pub fn with_ticks_per_bar(mut self, ticks_per_bar: usize) -> Self {
+        self.ticks_per_bar = Some(ticks_per_bar.max(1));
+        self
+    }
+
+    /// Total number of bars available per series before `request_more_data` runs dry.
+    pub fn with_history_depth(mut self, history_depth: usize) -> Self {
+        self.history_depth = history_depth;
+        self
+    }
+}
+
+/// Local websocket server speaking the TradingView protocol, point the client at it with `TradingViewEndpointConfig::new(&server.uri())`.
+pub struct MockTradingViewServer {
+    address: SocketAddr,
+    commands: Arc<Mutex<Vec<TradingViewCommand>>>,
+    upgrade_headers: Arc<Mutex<Vec<HeaderMap>>>,
+    connections: Arc<AtomicUsize>,
+    _accept_handle: Task<()>,
+}
+
+impl MockTradingViewServer {
+    /// Binds to an ephemeral port on localhost and starts accepting connections.
+    pub async fn start(executor: Arc<Executor<'static>>, script: MockTradingViewScript) -> anyhow::Result<Self> {
+        let listener = Async::<TcpListener>::bind(([127, 0, 0, 1], 0))?;
+        let address = listener.get_ref().local_addr()?;
+        let commands = Arc::new(Mutex::new(vec![]));
+        let upgrade_headers = Arc::new(Mutex::new(vec![]));
+        let connections = Arc::new(AtomicUsize::new(0));
+
+        let accept_commands = commands.clone();
+        let accept_upgrade_headers = upgrade_headers.clone();
+        let accept_connections = connections.clone();
+        let accept_executor = executor.clone();
+        let accept_handle = executor.spawn(async move {
+            loop {
+                let (stream, peer) = match listener.accept().await {
+                    Ok(accepted) => accepted,
+                    Err(err) => {
+                        log::error!("mock accept failed: {err:?}");
+                        break;
+                    }
+                };
+                let connection_index = accept_connections.fetch_add(1, Ordering::SeqCst);
+                log::info!("mock connection {connection_index} from {peer}");
+                let connection = MockTradingViewConnection::new(script.clone(), accept_commands.clone(), accept_upgrade_headers.clone());
+                let connection_executor = accept_executor.clone();
+                accept_executor.spawn(async move {
+                    if let Err(err) = connection.run(connection_executor, stream).await {
+                        log::warn!("mock connection {connection_index} failed: {err:?}");
+                    }
+                }).detach();
+            }
+        });
+
+        Ok(Self {
+            address,
+            commands,
+            upgrade_headers,
+            connections,
+            _accept_handle: accept_handle,
+        })
+    }
+
+    pub fn address(&self) -> SocketAddr {
+        self.address
+    }
+
+    /// The `ws://` endpoint to hand to the client.
+    pub fn uri(&self) -> String {
+        format!("ws://{}/socket.io/websocket?type=chart", self.address)
+    }
+
+    /// Every command received so far, across all connections, in arrival order.
+    pub async fn received_commands(&self) -> Vec<TradingViewCommand> {
+        self.commands.lock().await.clone()
+    }
+
+    /// Headers of every upgrade request received so far, one map per connection in arrival order.
+    pub async fn upgrade_headers(&self) -> Vec<HeaderMap> {
+        self.upgrade_headers.lock().await.clone()
+    }
+
+    /// Number of connections accepted so far.
+    pub fn connections(&self) -> usize {
+        self.connections.load(Ordering::SeqCst)
+    }
+}
+
+/// Per-series state so `request_more_data` keeps paging back from where the last page stopped.
+struct MockSeries {
+    timeframe_seconds: u64,
+    loaded: usize,
+    /// Bars opened by ticks since the series was created
+    opened: usize,
+}
+
+impl MockSeries {
+    fn last_timestamp(&self) -> u64 {
+        MOCK_LAST_BAR_TIMESTAMP - MOCK_LAST_BAR_TIMESTAMP % self.timeframe_seconds + self.opened as u64 * self.timeframe_seconds
+    }
+}
+
+enum MockEvent {
+    Command(TradingViewCommand),
+    WebSocketPing(Vec<u8>),
+    Closed,
+    Ping,
+    Tick,
+}
+
+struct MockTradingViewConnection {
+    script: MockTradingViewScript,
+    commands: Arc<Mutex<Vec<TradingViewCommand>>>,
+    upgrade_headers: Arc<Mutex<Vec<HeaderMap>>>,
+    series: HashMap<(String, String), MockSeries>,
+    quote_symbols: Vec<(String, String)>,
+    /// Chart session id, study id and the series id each study runs on
+    studies: Vec<(String, String, String)>,
+    nonce: usize,
+    tick: usize,
+}
+
+impl MockTradingViewConnection {
+    fn new(script: MockTradingViewScript, commands: Arc<Mutex<Vec<TradingViewCommand>>>, upgrade_headers: Arc<Mutex<Vec<HeaderMap>>>) -> Self {
+        Self {
+            script,
+            commands,
+            upgrade_headers,
+            series: HashMap::new(),
+            quote_symbols: vec![],
+            studies: vec![],
+            nonce: 0,
+            tick: 0,
+        }
+    }
+
+    async fn run(mut self, executor: Arc<Executor<'static>>, mut stream: Async<TcpStream>) -> anyhow::Result<()> {
+        let headers = accept_upgrade(&mut stream).await?;
+        self.upgrade_headers.lock().await.push(headers);
+        let (reader, mut writer) = futures_lite::io::split(stream);
+
+        // the reader gets a task of its own so timers never interrupt a half-read frame
+        let (event_sender, event_receiver) = async_channel::unbounded();
+        let _reader_handle = executor.spawn(read_commands(BufReader::new(reader), event_sender, self.commands.clone()));
+
+        if let Some(hello) = &self.script.hello {
+            write_tradingview_message(&mut writer, hello).await?;
+        }
+
+        let mut ping_timer = self.script.ping_interval.map(Timer::interval).unwrap_or_else(Timer::never);
+        let mut tick_timer = self.script.tick_interval.map(Timer::interval).unwrap_or_else(Timer::never);
+        loop {
+            let event = futures_lite::future::or(
+                async { event_receiver.recv().await.unwrap_or(MockEvent::Closed) },
+                futures_lite::future::or(
+                    async {
+                        ping_timer.next().await;
+                        MockEvent::Ping
+                    },
+                    async {
+                        tick_timer.next().await;
+                        MockEvent::Tick
+                    },
+                ),
+            ).await;
+            let replies = match event {
+                MockEvent::Command(command) => self.reply(&command),
+                MockEvent::WebSocketPing(payload) => {
+                    write_frame(&mut writer, OPCODE_PONG, &payload).await?;
+                    vec![]
+                },
+                MockEvent::Closed => return Ok(()),
+                MockEvent::Ping => {
+                    self.nonce += 1;
+                    vec![MockTradingViewReply::Ping(self.nonce)]
+                },
+                MockEvent::Tick => self.tick(),
+            };
+            for reply in replies {
+                match reply {
+                    MockTradingViewReply::Message(payload) => write_tradingview_message(&mut writer, &payload).await?,
+                    MockTradingViewReply::Ping(nonce) => write_tradingview_message(&mut writer, &format!("~h~{nonce}")).await?,
+                    MockTradingViewReply::Close => {
+                        write_frame(&mut writer, OPCODE_CLOSE, &[]).await?;
+                        writer.close().await?;
+                        return Ok(());
+                    }
+                }
+            }
+        }
+    }
+
+    fn reply(&mut self, command: &TradingViewCommand) -> Vec<MockTradingViewReply> {
+        if let Some(handler) = self.script.handlers.get(command.method()) {
+            return handler(command);
+        }
+        if !self.script.canned {
+            return vec![];
+        }
+        match command {
+            TradingViewCommand::ResolveSymbol { chart_session_id, symbol_id, symbol } => {
+                vec![MockTradingViewReply::message("symbol_resolved", vec![string(chart_session_id), string(symbol_id), Value::Object(symbol_info(symbol))])]
+            },
+            TradingViewCommand::CreateSeries { chart_session_id, series_id, timeframe, range, .. } => {
+                let loaded = (*range).min(self.script.history_depth);
+                self.series.insert((chart_session_id.clone(), series_id.clone()), MockSeries {
+                    timeframe_seconds: utilities::timeframe_seconds(timeframe).unwrap_or(60),
+                    loaded,
+                    opened: 0,
+                });
+                let bars = self.bars(chart_session_id, series_id, 0..loaded);
+                vec![
+                    MockTradingViewReply::message("series_loading", vec![string(chart_session_id), string(series_id), string("s1")]),
+                    timescale_update(chart_session_id, series_id, bars, loaded, 0),
+                    MockTradingViewReply::message("series_completed", vec![string(chart_session_id), string(series_id), string("streaming"), string("s1")]),
+                ]
+            },
+            TradingViewCommand::RequestMoreData { chart_session_id, series_id, amount } => {
+                let Some(series) = self.series.get_mut(&(chart_session_id.clone(), series_id.clone())) else {
+                    return vec![MockTradingViewReply::critical_error(chart_session_id, "unknown series", "request_more_data")];
+                };
+                let previously_loaded = series.loaded;
+                series.loaded = (series.loaded + amount).min(self.script.history_depth + series.opened);
+                let added = series.loaded - previously_loaded;
+                let mut replies = vec![MockTradingViewReply::message("series_loading", vec![string(chart_session_id), string(series_id), string("s1")])];
+                if added > 0 {
+                    // the page takes indexes 0..added, everything loaded before moves up behind it
+                    let loaded = series.loaded;
+                    replies.push(timescale_update(chart_session_id, series_id, self.bars(chart_session_id, series_id, 0..added), loaded, added));
+                }
+                replies.push(MockTradingViewReply::message("series_completed", vec![string(chart_session_id), string(series_id), string("streaming"), string("s1")]));
+                replies
+            },
+            TradingViewCommand::RequestMoreTickmarks { chart_session_id, series_id, range } => {
+                let Some(series) = self.series.get(&(chart_session_id.clone(), series_id.clone())) else {
+                    return vec![MockTradingViewReply::critical_error(chart_session_id, "unknown series", "request_more_tickmarks")];
+                };
+                // a label on every hour among the newest `range` bars, heavier on days
+                let loaded = series.loaded;
+                let marks = self.bars(chart_session_id, series_id, loaded.saturating_sub(*range)..loaded)
+                    .into_iter()
+                    .filter(|(_, timestamp, _)| timestamp % 3600 == 0)
+                    .map(|(index, timestamp, _)| {
+                        let weight = if timestamp % 86_400 == 0 { 50 } else { 30 };
+                        array(vec![Value::Number(Number::U64(weight)), Value::Number(Number::U64(index as u64)), Value::Number(Number::U64(timestamp))])
+                    })
+                    .collect::<Vec<_>>();
+                let mut tickmarks = Object::new();
+                tickmarks.insert("index".to_string(), Value::Number(Number::U64(loaded.saturating_sub(1) as u64)));
+                tickmarks.insert("zoffset".to_string(), Value::Number(Number::U64(0)));
+                tickmarks.insert("changes".to_string(), array(vec![]));
+                tickmarks.insert("marks".to_string(), array(marks));
+                vec![MockTradingViewReply::message("tickmark_update", vec![string(chart_session_id), Value::Object(tickmarks)])]
+            },
+            TradingViewCommand::CreateStudy { chart_session_id, study_id, series_id, .. } => {
+                self.studies.push((chart_session_id.clone(), study_id.clone(), series_id.clone()));
+                let loaded = self.series.get(&(chart_session_id.clone(), series_id.clone())).map(|series| series.loaded).unwrap_or(0);
+                let rows = self.bars(chart_session_id, series_id, 0..loaded).into_iter().map(|(index, timestamp, bar)| {
+                    // one plot per row: the close
+                    row(index, vec![Value::Number(Number::U64(timestamp)), Value::Number(Number::F64(bar[3]))])
+                }).collect::<Vec<_>>();
+                let mut update = Object::new();
+                update.insert("st".to_string(), array(rows));
+                update.insert("ns".to_string(), Value::Object(no_graphics()));
+                vec![
+                    MockTradingViewReply::message("study_loading", vec![string(chart_session_id), string(study_id), string("st1")]),
+                    MockTradingViewReply::message("study_completed", vec![string(chart_session_id), string(study_id), string("st1")]),
+                    data_update(chart_session_id, study_id, update),
+                ]
+            },
+            TradingViewCommand::QuoteAddSymbols { quote_session_id, symbol } => {
+                self.quote_symbols.push((quote_session_id.clone(), symbol.clone()));
+                vec![
+                    quote_series_data(quote_session_id, symbol, quote_values(0)),
+                    MockTradingViewReply::message("quote_completed", vec![string(quote_session_id), string(symbol)]),
+                ]
+            },
+            _ => vec![],
+        }
+    }
+
+    /// Live updates: the newest bar of every series moves, the studies on it follow, and every quote symbol gets a new last price.
+    ///
+    /// Study rows go out ahead of the series update, so a row for a bar that just opened arrives before the bar itself.
+    fn tick(&mut self) -> Vec<MockTradingViewReply> {
+        self.tick += 1;
+        let mut replies = vec![];
+        let mut study_replies = vec![];
+        let open_bar = self.script.ticks_per_bar.map(|ticks_per_bar| self.tick.is_multiple_of(ticks_per_bar)).unwrap_or(false);
+        for ((chart_session_id, series_id), series) in &mut self.series {
+            if series.loaded == 0 {
+                continue;
+            }
+            if open_bar {
+                series.opened += 1;
+                series.loaded += 1;
+            }
+            let index = series.loaded - 1;
+            let (open, high, low, close, volume) = bar_values(self.script.history_depth - 1 + series.opened);
+            let close = close + self.tick as f64 * 0.01;
+            let bar = vec![
+                Value::Number(Number::U64(series.last_timestamp())),
+                Value::Number(Number::F64(open)),
+                Value::Number(Number::F64(high.max(close))),
+                Value::Number(Number::F64(low)),
+                Value::Number(Number::F64(close)),
+                Value::Number(Number::F64(volume + self.tick as f64)),
+            ];
+            let mut update = Object::new();
+            update.insert("s".to_string(), array(vec![row(index, bar)]));
+            update.insert("ns".to_string(), Value::Object(no_graphics()));
+            replies.push(data_update(chart_session_id, series_id, update));
+            for (_, study_id, _) in self.studies.iter().filter(|(study_chart_session_id, _, study_series_id)| study_chart_session_id == chart_session_id && study_series_id == series_id) {
+                let mut update = Object::new();
+                update.insert("st".to_string(), array(vec![row(index, vec![Value::Number(Number::U64(series.last_timestamp())), Value::Number(Number::F64(close))])]));
+                update.insert("ns".to_string(), Value::Object(no_graphics()));
+                study_replies.push(data_update(chart_session_id, study_id, update));
+            }
+        }
+        study_replies.append(&mut replies);
+        let mut replies = study_replies;
+        for (quote_session_id, symbol) in &self.quote_symbols {
+            replies.push(quote_series_data(quote_session_id, symbol, quote_values(self.tick)));
+        }
+        replies
+    }
+
+    /// Bars `positions` of the loaded window (0 = oldest loaded) as `(index, timestamp, [open, high, low, close, volume])`.
+    fn bars(&self, chart_session_id: &str, series_id: &str, positions: std::ops::Range<usize>) -> Vec<(usize, u64, [f64; 5])> {
+        let Some(series) = self.series.get(&(chart_session_id.to_string(), series_id.to_string())) else {
+            return vec![];
+        };
+        let last_timestamp = series.last_timestamp();
+        positions.map(|position| {
+            // age 0 is the newest bar
+            let age = series.loaded - 1 - position;
+            let (open, high, low, close, volume) = bar_values(self.script.history_depth - 1 + series.opened - age);
+            (position, last_timestamp - age as u64 * series.timeframe_seconds, [open, high, low, close, volume])
+        }).collect()
+    }
+}
+
+/// Deterministic OHLCV for the `n`th bar of a series, counted from the oldest available.
+fn bar_values(n: usize) -> (f64, f64, f64, f64, f64) {
+    let close = 100.0 + (n % 40) as f64 * 0.25;
+    let open = close - 0.125;
+    (open, close + 0.5, open - 0.5, close, 1000.0 + (n % 100) as f64)
+}
+
+fn quote_values(tick: usize) -> Object {
+    let lp = 100.0 + tick as f64 * 0.01;
+    let mut values = Object::new();
+    values.insert("lp".to_string(), Value::Number(Number::F64(lp)));
+    values.insert("lp_time".to_string(), Value::Number(Number::U64(MOCK_LAST_BAR_TIMESTAMP + tick as u64)));
+    values.insert("ch".to_string(), Value::Number(Number::F64(lp - 99.0)));
+    values.insert("chp".to_string(), Value::Number(Number::F64((lp - 99.0) / 99.0 * 100.0)));
+    values.insert("volume".to_string(), Value::Number(Number::U64(100_000 + tick as u64)));
+    values.insert("bid".to_string(), Value::Number(Number::F64(lp - 0.01)));
+    values.insert("ask".to_string(), Value::Number(Number::F64(lp + 0.01)));
+    if tick == 0 {
+        values.insert("current_session".to_string(), string("market"));
+    }
+    values
+}
+
+fn string(value: &str) -> Value {
+    Value::String(value.to_string())
+}
+
+fn array(values: Vec<Value>) -> Value {
+    let mut array = Array::new();
+    array.extend(values);
+    Value::Array(array)
+}
+
+fn row(index: usize, values: Vec<Value>) -> Value {
+    let mut row = Object::new();
+    row.insert("i".to_string(), Value::Number(Number::U64(index as u64)));
+    row.insert("v".to_string(), array(values));
+    Value::Object(row)
+}
+
+fn no_graphics() -> Object {
+    let mut ns = Object::new();
+    ns.insert("d".to_string(), string(""));
+    ns.insert("indexes".to_string(), string("nochange"));
+    ns
+}
+
+fn data_update(chart_session_id: &str, object_id: &str, update: Object) -> MockTradingViewReply {
+    let mut updates = Object::new();
+    updates.insert(object_id.to_string(), Value::Object(update));
+    MockTradingViewReply::message("du", vec![string(chart_session_id), Value::Object(updates)])
+}
+
+/// A page of `bars` for a series that has `loaded` bars in total now, `zoffset` of them prepended by this page.
+fn timescale_update(chart_session_id: &str, series_id: &str, bars: Vec<(usize, u64, [f64; 5])>, loaded: usize, zoffset: usize) -> MockTradingViewReply {
+    let changes = bars.iter().map(|(_, timestamp, _)| Value::Number(Number::U64(*timestamp))).collect::<Vec<_>>();
+    let rows = bars.into_iter().map(|(index, timestamp, bar)| {
+        let mut values = vec![Value::Number(Number::U64(timestamp))];
+        values.extend(bar.iter().map(|value| Value::Number(Number::F64(*value))));
+        row(index, values)
+    }).collect::<Vec<_>>();
+    let mut series = Object::new();
+    series.insert("s".to_string(), array(rows));
+    series.insert("ns".to_string(), Value::Object(no_graphics()));
+    series.insert("t".to_string(), string("s1"));
+    let mut updates = Object::new();
+    updates.insert(series_id.to_string(), Value::Object(series));
+    let mut marks = Object::new();
+    marks.insert("index".to_string(), Value::Number(Number::U64(loaded.saturating_sub(1) as u64)));
+    marks.insert("zoffset".to_string(), Value::Number(Number::U64(zoffset as u64)));
+    marks.insert("changes".to_string(), array(changes));
+    marks.insert("marks".to_string(), Value::Array(Array::new()));
+    MockTradingViewReply::message("timescale_update", vec![string(chart_session_id), Value::Object(updates), Value::Object(marks)])
+}
+
+fn quote_series_data(quote_session_id: &str, symbol: &str, values: Object) -> MockTradingViewReply {
+    let mut update = Object::new();
+    update.insert("n".to_string(), string(symbol));
+    update.insert("s".to_string(), string("ok"));
+    update.insert("v".to_string(), Value::Object(values));
+    MockTradingViewReply::message("qsd", vec![string(quote_session_id), Value::Object(update)])
+}
+
+/// `=`-prefixed json symbol descriptors carry the ticker in `symbol`, plain tickers are used as is.
+fn ticker(symbol: &str) -> String {
+    symbol.strip_prefix('=')
+        .and_then(|descriptor| miniserde::json::from_str::<Object>(descriptor).ok())
+        .and_then(|descriptor| match descriptor.get("symbol") {
+            Some(Value::String(ticker)) => Some(ticker.clone()),
+            _ => None,
+        })
+        .unwrap_or_else(|| symbol.to_string())
+}
+
+fn symbol_info(symbol: &str) -> Object {
+    let pro_name = ticker(symbol);
+    let (exchange, name) = pro_name.split_once(':').unwrap_or(("MOCK", &pro_name));
+    let mut subsession = Object::new();
+    subsession.insert("id".to_string(), string("regular"));
+    subsession.insert("description".to_string(), string("Regular Trading Hours"));
+    subsession.insert("session".to_string(), string("0930-1600"));
+    subsession.insert("session-display".to_string(), string("0930-1600"));
+    subsession.insert("private".to_string(), Value::Bool(false));
+
+    let mut info = Object::new();
+    info.insert("name".to_string(), string(name));
+    info.insert("full_name".to_string(), string(&pro_name));
+    info.insert("pro_name".to_string(), string(&pro_name));
+    info.insert("description".to_string(), string(&format!("Mock {name}")));
+    info.insert("exchange".to_string(), string(exchange));
+    info.insert("listed_exchange".to_string(), string(exchange));
+    info.insert("type".to_string(), string("stock"));
+    info.insert("currency_code".to_string(), string("USD"));
+    info.insert("pricescale".to_string(), Value::Number(Number::U64(100)));
+    info.insert("minmov".to_string(), Value::Number(Number::U64(1)));
+    info.insert("minmove2".to_string(), Value::Number(Number::U64(0)));
+    info.insert("fractional".to_string(), Value::Bool(false));
+    info.insert("session".to_string(), string("0930-1600"));
+    info.insert("subsession_id".to_string(), string("regular"));
+    info.insert("subsessions".to_string(), array(vec![Value::Object(subsession)]));
+    info.insert("timezone".to_string(), string("America/New_York"));
+    info.insert("has_intraday".to_string(), Value::Bool(true));
+    info
+}
+
+/// Server hello with the server clock at `timestamp` (unix seconds).
+fn hello_at(timestamp: u64) -> String {
+    let mut hello = Object::new();
+    hello.insert("session_id".to_string(), string("<0.1.0>_mock"));
+    hello.insert("timestamp".to_string(), Value::Number(Number::U64(timestamp)));
+    hello.insert("timestampMs".to_string(), Value::Number(Number::U64(timestamp * 1000)));
+    hello.insert("release".to_string(), string("mock"));
+    hello.insert("protocol".to_string(), string("json"));
+    hello.insert("auth_scheme_vsn".to_string(), Value::Number(Number::U64(2)));
+    hello.insert("via".to_string(), string("127.0.0.1:0"));
+    hello.insert("javastudies".to_string(), array(vec![string("3.66")]));
+    miniserde::json::to_string(&hello)
+}
+
+/// Reads the client's upgrade request and answers with `101 Switching Protocols`, returning the request headers.
+async fn accept_upgrade(stream: &mut Async<TcpStream>) -> anyhow::Result<HeaderMap> {
+    let mut head = Vec::new();
+    while !head.ends_with(b"\r\n\r\n") {
+        let mut byte = [0u8; 1];
+        if stream.read(&mut byte).await? == 0 {
+            return Err(anyhow::anyhow!("connection closed during upgrade"));
+        }
+        head.push(byte[0]);
+    }
+    let head = String::from_utf8(head)?;
+    let mut headers = HeaderMap::new();
+    for (name, value) in head.split("\r\n").skip(1).filter_map(|line| line.split_once(':')) {
+        headers.append(HeaderName::from_bytes(name.trim().as_bytes())?, HeaderValue::from_str(value.trim())?);
+    }
+    let key = headers.get("Sec-WebSocket-Key")
+        .map(|value| value.to_str())
+        .transpose()?
+        .ok_or(anyhow::anyhow!("upgrade request without Sec-WebSocket-Key"))?;
+    let response = format!("HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Accept: {}\r\n\r\n", transport::sec_websocket_accept(key));
+    stream.write_all(response.as_bytes()).await?;
+    stream.flush().await?;
+    Ok(headers)
+}
+
+/// Decodes client frames into commands until the client closes or the stream ends.
+async fn read_commands<R>(mut reader: R, event_sender: Sender<MockEvent>, commands: Arc<Mutex<Vec<TradingViewCommand>>>)
+where
+    R: AsyncRead + Unpin,
+{
+    let mut buffer = BytesMut::new();
+    loop {
+        let (opcode, payload) = match read_frame(&mut reader).await {
+            Ok(frame) => frame,
+            Err(err) => {
+                log::debug!("mock read ended: {err}");
+                break;
+            }
+        };
+        match opcode {
+            OPCODE_TEXT => buffer.extend_from_slice(&payload),
+            OPCODE_PING => {
+                let _ = event_sender.send(MockEvent::WebSocketPing(payload)).await;
+                continue;
+            },
+            OPCODE_CLOSE => break,
+            _ => continue,
+        }
+
+        // a single websocket message can carry several ~m~ frames
+        loop {
+            let input = &buffer[..];
+            let (parsed_len, payload) = match TradingViewMessageWrapper::parse_frame(input) {
+                Ok((remaining, payload)) => (input.len() - remaining.len(), payload),
+                Err(nom::Err::Incomplete(_)) => break,
+                Err(err) => {
+                    log::error!("mock failed to parse frame: {err:?}");
+                    buffer.clear();
+                    break;
+                }
+            };
+            buffer.advance(parsed_len);
+            match TradingViewCommand::from_message(&payload) {
+                Ok(command) => {
+                    log::debug!("mock received {command:?}");
+                    commands.lock().await.push(command.clone());
+                    let _ = event_sender.send(MockEvent::Command(command)).await;
+                },
+                Err(err) => log::error!("mock failed to decode command {payload}: {err}"),
+            }
+        }
+    }
+    let _ = event_sender.send(MockEvent::Closed).await;
+}
+
+/// Reads one (possibly fragmented) client frame, unmasking the payload.
+async fn read_frame<R>(reader: &mut R) -> anyhow::Result<(u8, Vec<u8>)>
+where
+    R: AsyncRead + Unpin,
+{
+    let mut message_opcode = None;
+    let mut message = Vec::new();
+    loop {
+        let mut header = [0u8; 2];
+        reader.read_exact(&mut header).await?;
+        let fin = header[0] & 0x80 != 0;
+        let opcode = header[0] & 0x0F;
+        let masked = header[1] & 0x80 != 0;
+        let payload_len = match header[1] & 0x7F {
+            126 => {
+                let mut len = [0u8; 2];
+                reader.read_exact(&mut len).await?;
+                u16::from_be_bytes(len) as usize
+            },
+            127 => {
+                let mut len = [0u8; 8];
+                reader.read_exact(&mut len).await?;
+                u64::from_be_bytes(len) as usize
+            },
+            len => len as usize,
+        };
+        let mut mask = [0u8; 4];
+        if masked {
+            reader.read_exact(&mut mask).await?;
+        }
+        let mut payload = vec![0u8; payload_len];
+        reader.read_exact(&mut payload).await?;
+        if masked {
+            for (index, byte) in payload.iter_mut().enumerate() {
+                *byte ^= mask[index % 4];
+            }
+        }
+
+        // control frames can show up between fragments and are never fragmented themselves
+        if opcode >= OPCODE_CLOSE {
+            return Ok((opcode, payload));
+        }
+        if opcode != OPCODE_CONTINUATION {
+            message_opcode = Some(opcode);
+        }
+        message.extend_from_slice(&payload);
+        if fin {
+            let opcode = message_opcode.ok_or(anyhow::anyhow!("continuation frame without a start"))?;
+            return Ok((opcode, message));
+        }
+    }
+}
+
+/// Writes a single unmasked server frame.
+async fn write_frame<W>(writer: &mut W, opcode: u8, payload: &[u8]) -> anyhow::Result<()>
+where
+    W: AsyncWrite + Unpin,
+{
+    let mut frame = vec![0x80 | opcode];
+    match payload.len() {
+        len if len < 126 => frame.push(len as u8),
+        len if len <= u16::MAX as usize => {
+            frame.push(126);
+            frame.extend_from_slice(&(len as u16).to_be_bytes());
+        },
+        len => {
+            frame.push(127);
+            frame.extend_from_slice(&(len as u64).to_be_bytes());
+        }
+    }
+    frame.extend_from_slice(payload);
+    writer.write_all(&frame).await?;
+    writer.flush().await?;
+    Ok(())
+}
+
+async fn write_tradingview_message<W>(writer: &mut W, payload: &str) -> anyhow::Result<()>
+where
+    W: AsyncWrite + Unpin,
+{
+    write_frame(writer, OPCODE_TEXT, TradingViewMessageWrapper::serialize(payload).as_bytes()).await
+}
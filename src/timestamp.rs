@@ -0,0 +1,112 @@
+use std::cmp::Ordering;
+use std::hash::{Hash, Hasher};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use miniserde::Serialize;
+use miniserde::ser::Fragment;
+
+/// A unix timestamp in the unit the server sent it in. Bars and quote times come in seconds, a few fields in milliseconds.
+///
+/// Comparisons and hashing go through milliseconds, so `Seconds(1)` equals `Milliseconds(1000)`.
+#[derive(Debug, Clone, Copy)]
+pub enum TradingViewTimestamp {
+    Seconds(i64),
+    Milliseconds(i64),
+}
+
+impl TradingViewTimestamp {
+    /// Whole seconds, milliseconds are truncated towards the past.
+    pub fn seconds(&self) -> i64 {
+        match self {
+            TradingViewTimestamp::Seconds(seconds) => *seconds,
+            TradingViewTimestamp::Milliseconds(milliseconds) => milliseconds.div_euclid(1000),
+        }
+    }
+
+    pub fn milliseconds(&self) -> i64 {
+        match self {
+            TradingViewTimestamp::Seconds(seconds) => seconds.saturating_mul(1000),
+            TradingViewTimestamp::Milliseconds(milliseconds) => *milliseconds,
+        }
+    }
+
+    pub fn to_system_time(&self) -> SystemTime {
+        let milliseconds = self.milliseconds();
+        if milliseconds >= 0 {
+            UNIX_EPOCH + Duration::from_millis(milliseconds as u64)
+        } else {
+            UNIX_EPOCH - Duration::from_millis(milliseconds.unsigned_abs())
+        }
+    }
+}
+
+impl PartialEq for TradingViewTimestamp {
+    fn eq(&self, other: &Self) -> bool {
+        self.milliseconds() == other.milliseconds()
+    }
+}
+
+impl Eq for TradingViewTimestamp {}
+
+impl PartialOrd for TradingViewTimestamp {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for TradingViewTimestamp {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.milliseconds().cmp(&other.milliseconds())
+    }
+}
+
+impl Hash for TradingViewTimestamp {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.milliseconds().hash(state);
+    }
+}
+
+// serialized as the number that came over the wire, in its own unit
+impl Serialize for TradingViewTimestamp {
+    fn begin(&self) -> Fragment<'_> {
+        match self {
+            TradingViewTimestamp::Seconds(seconds) => Fragment::I64(*seconds),
+            TradingViewTimestamp::Milliseconds(milliseconds) => Fragment::I64(*milliseconds),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+
+    #[test]
+    fn converts_between_seconds_and_milliseconds() {
+        assert_eq!(TradingViewTimestamp::Seconds(1_700_000_100).milliseconds(), 1_700_000_100_000);
+        assert_eq!(TradingViewTimestamp::Milliseconds(1_700_000_100_999).seconds(), 1_700_000_100);
+        // truncated towards the past, also before the epoch
+        assert_eq!(TradingViewTimestamp::Milliseconds(-1).seconds(), -1);
+        assert_eq!(TradingViewTimestamp::Milliseconds(-1000).seconds(), -1);
+        assert_eq!(TradingViewTimestamp::Seconds(i64::MAX).milliseconds(), i64::MAX);
+    }
+
+    #[test]
+    fn units_compare_and_hash_by_instant() {
+        assert_eq!(TradingViewTimestamp::Seconds(1), TradingViewTimestamp::Milliseconds(1000));
+        assert_ne!(TradingViewTimestamp::Seconds(1), TradingViewTimestamp::Milliseconds(1001));
+        assert!(TradingViewTimestamp::Seconds(1) < TradingViewTimestamp::Milliseconds(1001));
+        let timestamps = HashSet::from([TradingViewTimestamp::Seconds(1), TradingViewTimestamp::Milliseconds(1000)]);
+        assert_eq!(timestamps.len(), 1);
+    }
+
+    #[test]
+    fn system_time_and_serialization() {
+        assert_eq!(TradingViewTimestamp::Milliseconds(1500).to_system_time(), UNIX_EPOCH + Duration::from_millis(1500));
+        assert_eq!(TradingViewTimestamp::Seconds(-2).to_system_time(), UNIX_EPOCH - Duration::from_secs(2));
+        // each unit goes back out as it came in
+        assert_eq!(miniserde::json::to_string(&TradingViewTimestamp::Seconds(1_700_000_100)), "1700000100");
+        assert_eq!(miniserde::json::to_string(&TradingViewTimestamp::Milliseconds(1_700_000_100_000)), "1700000100000");
+    }
+}
@@ -0,0 +1,145 @@
+use std::net::{TcpStream, ToSocketAddrs};
+
+use async_io::Async;
+use base64::Engine;
+use futures_lite::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use http::{Request, Response, StatusCode};
+use http_client::HttpClient;
+use sha1::{Digest, Sha1};
+
+/// Largest response head accepted for the websocket upgrade.
+const MAX_RESPONSE_HEAD_LEN: usize = 16 * 1024;
+
+/// GUID appended to `Sec-WebSocket-Key` before hashing, from RFC 6455.
+const WEBSOCKET_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+/// Byte stream under the websocket, TLS for `wss://` and plain TCP for `ws://`.
+pub trait TradingViewStream: AsyncRead + AsyncWrite + Send + Unpin {}
+
+impl<T> TradingViewStream for T where T: AsyncRead + AsyncWrite + Send + Unpin {}
+
+/// Opens the connection for the scheme, host and port of the request URI.
+pub async fn connect(request: &Request<()>) -> anyhow::Result<Box<dyn TradingViewStream>> {
+    let uri = request.uri();
+    match uri.scheme_str() {
+        Some("wss") => {
+            let stream = HttpClient::connect(request).await?;
+            Ok(Box::new(stream))
+        },
+        Some("ws") => {
+            let host = uri.host().ok_or(anyhow::anyhow!("uri without host: {uri}"))?;
+            let port = uri.port_u16().unwrap_or(80);
+            let address = (host, port).to_socket_addrs()?.next().ok_or(anyhow::anyhow!("failed to resolve {host}:{port}"))?;
+            let stream = Async::<TcpStream>::connect(address).await?;
+            Ok(Box::new(stream))
+        },
+        scheme => Err(anyhow::anyhow!("unsupported scheme {scheme:?}"))
+    }
+}
+
+/// Sends the upgrade request and reads back the response head, leaving the stream positioned at the first websocket frame.
+pub async fn upgrade<S>(stream: &mut S, request: &Request<()>) -> anyhow::Result<Response<()>>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    // write request head
+    let path_and_query = request.uri().path_and_query().map(|path_and_query| path_and_query.as_str()).unwrap_or("/");
+    let mut head = format!("{} {path_and_query} HTTP/1.1\r\n", request.method());
+    for (name, value) in request.headers() {
+        head.push_str(&format!("{name}: {}\r\n", value.to_str()?));
+    }
+    head.push_str("\r\n");
+    stream.write_all(head.as_bytes()).await?;
+    stream.flush().await?;
+
+    // read response head byte by byte so nothing past it gets consumed
+    let mut head = Vec::new();
+    while !head.ends_with(b"\r\n\r\n") {
+        if head.len() >= MAX_RESPONSE_HEAD_LEN {
+            return Err(anyhow::anyhow!("upgrade response head too long"));
+        }
+        let mut byte = [0u8; 1];
+        if stream.read(&mut byte).await? == 0 {
+            return Err(anyhow::anyhow!("connection closed during upgrade"));
+        }
+        head.push(byte[0]);
+    }
+    let head = String::from_utf8(head)?;
+
+    // parse status line + headers
+    let mut lines = head.split("\r\n").filter(|line| !line.is_empty());
+    let status_line = lines.next().ok_or(anyhow::anyhow!("empty upgrade response"))?;
+    let status = status_line.split(' ').nth(1).ok_or(anyhow::anyhow!("malformed status line: {status_line}"))?;
+    let mut response = Response::builder().status(status.parse::<u16>()?);
+    for line in lines {
+        let (name, value) = line.split_once(':').ok_or(anyhow::anyhow!("malformed header: {line}"))?;
+        response = response.header(name.trim(), value.trim());
+    }
+    Ok(response.body(())?)
+}
+
+/// Checks the upgrade was accepted: `101`, `Upgrade: websocket` and the `Sec-WebSocket-Accept` matching the key that was sent.
+pub fn validate_upgrade(response: &Response<()>, sec_websocket_key: &str) -> anyhow::Result<()> {
+    if response.status() != StatusCode::SWITCHING_PROTOCOLS {
+        return Err(anyhow::anyhow!("upgrade rejected with status {}", response.status()));
+    }
+    let upgrade = response.headers().get("Upgrade").map(|value| value.to_str()).transpose()?;
+    if !upgrade.map(|upgrade| upgrade.eq_ignore_ascii_case("websocket")).unwrap_or(false) {
+        return Err(anyhow::anyhow!("unexpected Upgrade header {upgrade:?}"));
+    }
+    let accept = response.headers().get("Sec-WebSocket-Accept").map(|value| value.to_str()).transpose()?;
+    let expected = sec_websocket_accept(sec_websocket_key);
+    if accept != Some(expected.as_str()) {
+        return Err(anyhow::anyhow!("unexpected Sec-WebSocket-Accept {accept:?}, expected {expected}"));
+    }
+    Ok(())
+}
+
+/// `Sec-WebSocket-Accept` value for a `Sec-WebSocket-Key`.
+pub fn sec_websocket_accept(sec_websocket_key: &str) -> String {
+    let mut hasher = Sha1::new();
+    hasher.update(sec_websocket_key.as_bytes());
+    hasher.update(WEBSOCKET_GUID.as_bytes());
+    base64::engine::general_purpose::STANDARD.encode(hasher.finalize())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Key and accept value from the RFC 6455 handshake example.
+    const KEY: &str = "dGhlIHNhbXBsZSBub25jZQ==";
+    const ACCEPT: &str = "s3pPLMBiTxaQ9kYGzzhZRbK+xOo=";
+
+    fn response(status: u16, accept: &str) -> Response<()> {
+        Response::builder()
+            .status(status)
+            .header("Upgrade", "websocket")
+            .header("Connection", "Upgrade")
+            .header("Sec-WebSocket-Accept", accept)
+            .body(())
+            .unwrap()
+    }
+
+    #[test]
+    fn accepts_a_matching_upgrade() {
+        assert_eq!(sec_websocket_accept(KEY), ACCEPT);
+        assert!(validate_upgrade(&response(101, ACCEPT), KEY).is_ok());
+    }
+
+    #[test]
+    fn rejects_a_status_other_than_101() {
+        let err = validate_upgrade(&response(403, ACCEPT), KEY).unwrap_err();
+        assert!(err.to_string().contains("403"), "{err}");
+        assert!(validate_upgrade(&response(200, ACCEPT), KEY).is_err());
+    }
+
+    #[test]
+    fn rejects_a_wrong_sec_websocket_accept() {
+        let err = validate_upgrade(&response(101, &sec_websocket_accept("another key")), KEY).unwrap_err();
+        assert!(err.to_string().contains("Sec-WebSocket-Accept"), "{err}");
+
+        let without_accept = Response::builder().status(101).header("Upgrade", "websocket").body(()).unwrap();
+        assert!(validate_upgrade(&without_accept, KEY).is_err());
+    }
+}
@@ -1,7 +1,9 @@
-use std::{sync::Arc, time::Duration};
+use std::future::Future;
+use std::task::Poll;
+use std::time::Duration;
 
+use async_channel::Receiver;
 use async_io::Timer;
-use async_lock::RwLock;
 
 pub async fn run_with_timeout<F, T>(timeout: Duration, future: F) -> Option<T>
 where
@@ -14,27 +16,36 @@ where
     .await
 }
 
-pub async fn wait_for_message<F, T>(
-    buffer: Arc<RwLock<Vec<T>>>,
-    condition: F,
-) -> Option<T>
-where
-    F: Fn(&T) -> bool,
-{
-    loop {
-        let read_lock = buffer.read().await;
-        if let Some(index) = read_lock.iter().position(|e| condition(e)) {
-            // Drop the read lock before acquiring a write lock
-            drop(read_lock);
-
-            // Acquire write lock
-            let mut write_lock = buffer.write().await;
+/// Length of a bar for a chart resolution: minutes unless suffixed with `S`, `D` or `W`. Months, ticks and ranges have no fixed length.
+pub fn timeframe_seconds(timeframe: &str) -> Option<u64> {
+    let (count, unit) = match timeframe.find(|c: char| c.is_ascii_alphabetic()) {
+        Some(position) => timeframe.split_at(position),
+        None => (timeframe, ""),
+    };
+    let count = if count.is_empty() { 1 } else { count.parse::<u64>().ok()? };
+    let unit_seconds = match unit {
+        "" => 60,
+        "S" => 1,
+        "D" => 86_400,
+        "W" => 604_800,
+        _ => return None,
+    };
+    Some(count * unit_seconds)
+}
 
-            // Remove the item from the buffer
-            return Some(write_lock.remove(index));
-        } else {
-            drop(read_lock);
-            Timer::after(Duration::from_millis(1)).await;
+/// Waits for the first message on any of `receivers`. Closed receivers are skipped, so this never resolves once all of them are.
+pub async fn recv_any<T>(receivers: &[&Receiver<T>]) -> T {
+    let mut pending = receivers.iter().map(|receiver| Some(Box::pin(receiver.recv()))).collect::<Vec<_>>();
+    futures_lite::future::poll_fn(|cx| {
+        for slot in pending.iter_mut() {
+            if let Some(recv) = slot {
+                match recv.as_mut().poll(cx) {
+                    Poll::Ready(Ok(message)) => return Poll::Ready(message),
+                    Poll::Ready(Err(_)) => *slot = None,
+                    Poll::Pending => ()
+                }
+            }
         }
-    }
+        Poll::Pending
+    }).await
 }
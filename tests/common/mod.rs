@@ -0,0 +1,103 @@
+//! Shared setup for the tests that drive the client against `MockTradingViewServer`.
+
+#![allow(dead_code)]
+
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use tradingview_websocket_client::{MockTradingViewServer, NotifyUserMessage, ParsedTradingViewMessage, TradingViewClientConfig, TradingViewClientEvent, TradingViewClientMode, TradingViewEndpointConfig, TradingViewIndicator, TradingViewMessageProcessor, TradingViewReconnectConfig, TradingViewStudyMetadata, SPY5_REG_SYMBOL};
+
+pub const CHART_SESSION_ID: &str = "cs_000000000001";
+pub const SERIES_ID: &str = "sds_1";
+/// The configured indicator, `st1` is the sessions study the client always adds
+pub const STUDY_ID: &str = "st2";
+
+/// One SPY chart with the mock's single plot study and one SPY quote, pointed at `server`.
+pub fn config(server: &MockTradingViewServer, mode: TradingViewClientMode) -> TradingViewClientConfig {
+    TradingViewClientConfig {
+        name: "TEST".to_string(),
+        endpoint: Some(TradingViewEndpointConfig::new(&server.uri())),
+        auth_token: "unauthorized_user_token".to_string(),
+        chart_symbols: vec![SPY5_REG_SYMBOL.to_string()],
+        quote_symbols: vec!["AMEX:SPY".to_string()],
+        quote_fields: None,
+        indicators: vec![TradingViewIndicator::new("{}".to_string()).with_metadata(TradingViewStudyMetadata::from_titles(&["Close"]))],
+        timeframe: "5".to_string(),
+        range: 300,
+        mode,
+        backlog_capacity: None,
+        reconnect: Some(TradingViewReconnectConfig {
+            max_attempts: 1,
+            initial_backoff_ms: 10,
+            max_backoff_ms: 10
+        }),
+        bar_close_delay_ms: None
+    }
+}
+
+/// Keeps every event and notification it is handed.
+#[derive(Default, Clone)]
+pub struct Recorder {
+    pub events: Arc<Mutex<Vec<TradingViewClientEvent>>>,
+    pub notifications: Arc<Mutex<Vec<NotifyUserMessage>>>,
+    /// Raw messages the client didn't consume itself
+    pub messages: Arc<Mutex<Vec<ParsedTradingViewMessage>>>,
+    /// Time spent on every raw message, to make the processor fall behind
+    pub message_delay: Option<Duration>,
+}
+
+impl Recorder {
+    pub fn with_message_delay(message_delay: Duration) -> Self {
+        Self {
+            message_delay: Some(message_delay),
+            ..Self::default()
+        }
+    }
+
+    pub fn processor(&self) -> Arc<Box<dyn TradingViewMessageProcessor + Send + Sync>> {
+        Arc::new(Box::new(self.clone()))
+    }
+
+    pub fn events(&self) -> Vec<TradingViewClientEvent> {
+        self.events.lock().unwrap().clone()
+    }
+
+    pub fn notifications(&self) -> Vec<NotifyUserMessage> {
+        self.notifications.lock().unwrap().clone()
+    }
+
+    pub fn messages(&self) -> Vec<ParsedTradingViewMessage> {
+        self.messages.lock().unwrap().clone()
+    }
+}
+
+#[async_trait]
+impl TradingViewMessageProcessor for Recorder {
+    async fn process_message(&self, _name: String, message: ParsedTradingViewMessage) {
+        self.messages.lock().unwrap().push(message);
+        if let Some(message_delay) = self.message_delay {
+            async_io::Timer::after(message_delay).await;
+        }
+    }
+
+    async fn process_event(&self, _name: String, event: TradingViewClientEvent) {
+        self.events.lock().unwrap().push(event);
+    }
+
+    async fn process_notification(&self, _name: String, notification: NotifyUserMessage) {
+        self.notifications.lock().unwrap().push(notification);
+    }
+}
+
+/// Polls `condition` until it holds, panicking once `timeout` has passed.
+pub async fn wait_until<F>(timeout: Duration, mut condition: F)
+where
+    F: FnMut() -> bool,
+{
+    let started_at = Instant::now();
+    while !condition() {
+        assert!(started_at.elapsed() < timeout, "condition not met within {timeout:?}");
+        async_io::Timer::after(Duration::from_millis(10)).await;
+    }
+}
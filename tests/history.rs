@@ -0,0 +1,77 @@
+mod common;
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use smol_macros::Executor;
+use tradingview_websocket_client::{MockTradingViewReply, MockTradingViewScript, MockTradingViewServer, TradingViewClientMode, TradingViewCommand, TradingViewHistoryTarget, TradingViewTimestamp};
+
+use common::Recorder;
+
+#[macro_rules_attribute::apply(smol_macros::test!)]
+async fn fetch_history_pages_back_to_the_target(executor: Arc<Executor<'static>>) {
+    let server = MockTradingViewServer::start(executor.clone(), MockTradingViewScript::new()).await.unwrap();
+    let client = common::config(&server, TradingViewClientMode::Standard).to_client(Recorder::default().processor());
+
+    let bars = client.fetch_history(executor.clone(), "AMEX:SPY", "5", TradingViewHistoryTarget::Bars(2500)).await.unwrap();
+    assert_eq!(bars.len(), 2500);
+    // pages are prepended, so the indexes of the earlier pages have to follow the time scale
+    assert!(bars.windows(2).all(|pair| pair[1].index == pair[0].index + 1));
+    assert!(bars.windows(2).all(|pair| pair[1].timestamp > pair[0].timestamp));
+    assert!(server.received_commands().await.iter().any(|command| matches!(command, TradingViewCommand::RequestMoreData { .. })));
+}
+
+#[macro_rules_attribute::apply(smol_macros::test!)]
+async fn fetch_history_stops_when_the_server_runs_dry(executor: Arc<Executor<'static>>) {
+    let server = MockTradingViewServer::start(executor.clone(), MockTradingViewScript::new().with_history_depth(700)).await.unwrap();
+    let client = common::config(&server, TradingViewClientMode::Standard).to_client(Recorder::default().processor());
+
+    let bars = client.fetch_history(executor.clone(), "AMEX:SPY", "5", TradingViewHistoryTarget::Bars(2500)).await.unwrap();
+    assert_eq!(bars.len(), 700);
+}
+
+#[macro_rules_attribute::apply(smol_macros::test!)]
+async fn fetch_history_since_stops_once_the_timestamp_is_covered(executor: Arc<Executor<'static>>) {
+    let server = MockTradingViewServer::start(executor.clone(), MockTradingViewScript::new()).await.unwrap();
+    let client = common::config(&server, TradingViewClientMode::Standard).to_client(Recorder::default().processor());
+
+    // 1500 bars back from the newest one at 1_700_000_100, the first page has 1000 so one more is needed
+    let since = TradingViewTimestamp::Seconds(1_700_000_100 - 1500 * 300);
+    let bars = client.fetch_history(executor.clone(), "AMEX:SPY", "5", TradingViewHistoryTarget::Since(since)).await.unwrap();
+    assert_eq!(bars.len(), 1501);
+    assert_eq!(bars.first().map(|bar| bar.timestamp), Some(since));
+    assert_eq!(bars.last().map(|bar| bar.timestamp), Some(TradingViewTimestamp::Seconds(1_700_000_100)));
+    let pages = server.received_commands().await.iter().filter(|command| matches!(command, TradingViewCommand::RequestMoreData { .. })).count();
+    assert_eq!(pages, 1);
+}
+
+#[macro_rules_attribute::apply(smol_macros::test!)]
+async fn fetch_history_collects_a_page_split_across_timescale_updates(executor: Arc<Executor<'static>>) {
+    // the first request_more_data page comes as two updates of two bars each, the second request finds nothing older
+    let requests = Arc::new(AtomicUsize::new(0));
+    let script = MockTradingViewScript::new().with_history_depth(3).on("request_more_data", move |_| {
+        let series_loading = MockTradingViewReply::Message(r#"{"m":"series_loading","p":["cs_000000000001","sds_1","s1"]}"#.to_string());
+        let series_completed = MockTradingViewReply::Message(r#"{"m":"series_completed","p":["cs_000000000001","sds_1","streaming","s1"]}"#.to_string());
+        if requests.fetch_add(1, Ordering::SeqCst) > 0 {
+            return vec![series_loading, series_completed];
+        }
+        // the create_series snapshot starts at 1_700_000_100 - 2 * 300, every update prepends two older bars
+        let update = |first_timestamp: i64, index: i64| MockTradingViewReply::Message(format!(
+            r#"{{"m":"timescale_update","p":["cs_000000000001",{{"sds_1":{{"s":[{{"i":0,"v":[{first_timestamp},1,1,1,1,1]}},{{"i":1,"v":[{},1,1,1,1,1]}}],"ns":{{"d":"","indexes":[]}},"t":"s1"}}}},{{"index":{index},"zoffset":2,"changes":[],"marks":[]}}]}}"#,
+            first_timestamp + 300
+        ));
+        vec![
+            series_loading,
+            update(1_700_000_100 - 4 * 300, 4),
+            update(1_700_000_100 - 6 * 300, 6),
+            series_completed,
+        ]
+    });
+    let server = MockTradingViewServer::start(executor.clone(), script).await.unwrap();
+    let client = common::config(&server, TradingViewClientMode::Standard).to_client(Recorder::default().processor());
+
+    let bars = client.fetch_history(executor.clone(), "AMEX:SPY", "5", TradingViewHistoryTarget::Bars(100)).await.unwrap();
+    assert_eq!(bars.len(), 7);
+    assert_eq!(bars.iter().map(|bar| bar.index).collect::<Vec<_>>(), (0..7).collect::<Vec<_>>());
+    assert!(bars.windows(2).all(|pair| pair[1].timestamp.seconds() == pair[0].timestamp.seconds() + 300));
+}
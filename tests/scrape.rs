@@ -0,0 +1,136 @@
+mod common;
+
+use std::sync::Arc;
+
+use smol_macros::Executor;
+use tradingview_websocket_client::{MockTradingViewReply, MockTradingViewScript, MockTradingViewServer, QuoteField, TradingViewClientConfig, TradingViewClientMode, TradingViewCommand, TradingViewEndpointConfig, TradingViewError, TradingViewNotificationKind, TradingViewObjectStatus, TradingViewUpdateMode};
+
+use common::Recorder;
+
+#[macro_rules_attribute::apply(smol_macros::test!)]
+async fn scrape_resolves_loads_and_joins(executor: Arc<Executor<'static>>) {
+    let server = MockTradingViewServer::start(executor.clone(), MockTradingViewScript::new()).await.unwrap();
+    let client = common::config(&server, TradingViewClientMode::Standard).to_client(Recorder::default().processor());
+    let scrape_result = client.run(executor.clone()).await.unwrap();
+
+    assert_eq!(scrape_result.symbol_resolved_messages[0].symbol_info.pro_name.as_deref(), Some("AMEX:SPY"));
+    let bars = scrape_result.timescale_update_messages[0].updates.as_ref().expect("bars");
+    assert_eq!(bars.len(), 300);
+    let last_bar = bars.last().unwrap();
+
+    // the study row of the last bar carries its close under the plot title from the metadata
+    assert_eq!(scrape_result.study_data_update_messages.len(), 1);
+    let study_rows = scrape_result.study_data_update_messages[0].study_rows.as_ref().expect("study rows");
+    assert_eq!(study_rows.last().and_then(|row| row.plot("Close")), Some(last_bar.close));
+    assert_eq!(study_rows.last().map(|row| row.timestamp), Some(last_bar.timestamp));
+
+    // prices are formatted with the symbol's pricescale
+    let symbol_info = &scrape_result.symbol_resolved_messages[0].symbol_info;
+    assert_eq!(symbol_info.price(last_bar.close).map(|price| price.to_string()), Some(format!("{:.2}", last_bar.close)));
+
+    let series_completed = &scrape_result.series_completed_messages[0];
+    assert_eq!(series_completed.update_mode, Some(TradingViewUpdateMode::Streaming));
+    assert!(matches!(client.object_status(&series_completed.chart_session_id, &series_completed.series_id).await, Some(TradingViewObjectStatus::Completed { .. })));
+
+    assert_eq!(scrape_result.quote_last_price_messages.len(), 1);
+    // the mock only sends prices, so some of the default fields never arrive
+    assert!(scrape_result.quote_missing_fields.iter().all(|missing| !missing.fields.contains(&QuoteField::Lp)));
+    assert_eq!(client.quote("AMEX:SPY").await.and_then(|snapshot| snapshot.lp()), Some(100.0));
+}
+
+#[macro_rules_attribute::apply(smol_macros::test!)]
+async fn scrape_records_the_server_hello(executor: Arc<Executor<'static>>) {
+    let server = MockTradingViewServer::start(executor.clone(), MockTradingViewScript::new()).await.unwrap();
+    let client = common::config(&server, TradingViewClientMode::Standard).to_client(Recorder::default().processor());
+    let scrape_result = client.run(executor.clone()).await.unwrap();
+
+    let server_hello = &scrape_result.server_hello_messages[0];
+    assert_eq!(server_hello.javastudies_version(), Some("3.66"));
+    assert_eq!(server_hello.protocol.as_deref(), Some("json"));
+    // the mock clock sits at its last bar, well behind ours
+    assert!(client.clock_offset_ms().await.is_some_and(|offset_ms| offset_ms < 0));
+}
+
+#[macro_rules_attribute::apply(smol_macros::test!)]
+async fn scrape_sends_the_endpoint_headers_on_the_upgrade(executor: Arc<Executor<'static>>) {
+    let server = MockTradingViewServer::start(executor.clone(), MockTradingViewScript::new()).await.unwrap();
+    let mut endpoint = TradingViewEndpointConfig::new(&server.uri());
+    endpoint.user_agent = "scrape-test/1.0".to_string();
+    endpoint.origin = "https://example.com".to_string();
+    endpoint.headers.insert("X-Scrape-Test".to_string(), "1".to_string());
+    let config = TradingViewClientConfig {
+        endpoint: Some(endpoint),
+        ..common::config(&server, TradingViewClientMode::Standard)
+    };
+    config.to_client(Recorder::default().processor()).run(executor.clone()).await.unwrap();
+
+    let upgrade_headers = server.upgrade_headers().await;
+    assert_eq!(upgrade_headers.len(), 1);
+    let headers = &upgrade_headers[0];
+    let header = |name: &str| headers.get_all(name).iter().map(|value| value.to_str().unwrap().to_string()).collect::<Vec<_>>();
+    assert_eq!(header("User-Agent"), vec!["scrape-test/1.0"]);
+    assert_eq!(header("Origin"), vec!["https://example.com"]);
+    assert_eq!(header("X-Scrape-Test"), vec!["1"]);
+
+    let commands = server.received_commands().await;
+    assert!(matches!(commands.first(), Some(TradingViewCommand::SetAuthToken { auth_token }) if auth_token == "unauthorized_user_token"));
+    assert!(commands.iter().any(|command| matches!(command, TradingViewCommand::QuoteSetFields { fields, .. } if *fields == QuoteField::default_fields())));
+}
+
+#[macro_rules_attribute::apply(smol_macros::test!)]
+async fn scrape_without_last_price_fields_reports_the_missing_ones(executor: Arc<Executor<'static>>) {
+    let server = MockTradingViewServer::start(executor.clone(), MockTradingViewScript::new()).await.unwrap();
+    let config = TradingViewClientConfig {
+        quote_fields: Some(QuoteField::fundamentals_fields()),
+        ..common::config(&server, TradingViewClientMode::Standard)
+    };
+    let scrape_result = config.to_client(Recorder::default().processor()).run(executor.clone()).await.unwrap();
+
+    // no lp or rtc to wait for, and the mock only sends prices, none of which were asked for
+    assert_eq!(server.connections(), 1);
+    assert!(scrape_result.quote_last_price_messages.is_empty());
+    assert_eq!(scrape_result.quote_missing_fields.len(), 1);
+    assert_eq!(scrape_result.quote_missing_fields[0].fields, QuoteField::fundamentals_fields());
+}
+
+#[macro_rules_attribute::apply(smol_macros::test!)]
+async fn study_error_fails_without_reconnecting(executor: Arc<Executor<'static>>) {
+    let server = MockTradingViewServer::start(executor.clone(), MockTradingViewScript::new().on("create_study", |command| {
+        match command {
+            TradingViewCommand::CreateStudy { chart_session_id, study_id, .. } => vec![MockTradingViewReply::study_error(chart_session_id, study_id, "mock study error")],
+            _ => vec![]
+        }
+    })).await.unwrap();
+    let client = common::config(&server, TradingViewClientMode::Standard).to_client(Recorder::default().processor());
+
+    let err = client.run(executor.clone()).await.expect_err("study error");
+    assert!(matches!(err.downcast_ref::<TradingViewError>(), Some(TradingViewError::StudyFailed { error, .. }) if error == "mock study error"));
+    assert_eq!(server.connections(), 1);
+}
+
+#[macro_rules_attribute::apply(smol_macros::test!)]
+async fn rejected_auth_token_fails_without_reconnecting(executor: Arc<Executor<'static>>) {
+    let server = MockTradingViewServer::start(executor.clone(), MockTradingViewScript::new().on("set_auth_token", |_| {
+        vec![MockTradingViewReply::critical_error("", "invalid_auth_token", "set_auth_token")]
+    })).await.unwrap();
+    let client = common::config(&server, TradingViewClientMode::Standard).to_client(Recorder::default().processor());
+
+    let err = client.run(executor.clone()).await.expect_err("auth error");
+    assert!(matches!(err.downcast_ref::<TradingViewError>(), Some(TradingViewError::Unauthorized(_))));
+    assert_eq!(server.connections(), 1);
+}
+
+#[macro_rules_attribute::apply(smol_macros::test!)]
+async fn notify_user_reaches_the_processor(executor: Arc<Executor<'static>>) {
+    let server = MockTradingViewServer::start(executor.clone(), MockTradingViewScript::new().on("set_locale", |_| {
+        vec![MockTradingViewReply::notify_user("session_limit", "Too many sessions open for this account")]
+    })).await.unwrap();
+    let recorder = Recorder::default();
+    let client = common::config(&server, TradingViewClientMode::Standard).to_client(recorder.processor());
+    client.run(executor.clone()).await.unwrap();
+
+    let notifications = recorder.notifications();
+    assert_eq!(notifications.len(), 1);
+    assert_eq!(notifications[0].kind(), TradingViewNotificationKind::TooManySessions);
+    assert_eq!(notifications[0].text, "Too many sessions open for this account");
+}
@@ -0,0 +1,301 @@
+mod common;
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+use smol_macros::Executor;
+use tradingview_websocket_client::{BarSeriesChange, MockTradingViewReply, MockTradingViewScript, MockTradingViewServer, QuoteField, TradingViewClient, TradingViewClientConfig, TradingViewClientEvent, TradingViewClientMode, TradingViewError, TradingViewReconnectConfig, TradingViewTimestamp};
+
+use common::{Recorder, CHART_SESSION_ID, SERIES_ID, STUDY_ID};
+
+/// Three older bars for the mock's 300 bar chart, see `src/parsed_message.rs` for what the frame says.
+const PREPEND_FRAME: &str = include_str!("fixtures/timescale_update_prepend.json");
+
+/// Starts a streaming client against `server` and waits for its first event, i.e. until setup is done.
+async fn start_streaming(executor: &Arc<Executor<'static>>, server: &MockTradingViewServer, recorder: &Recorder) -> Arc<TradingViewClient> {
+    let client = Arc::new(common::config(server, TradingViewClientMode::Streaming).to_client(recorder.processor()));
+    let running_client = client.clone();
+    let running_executor = executor.clone();
+    executor.spawn(async move { running_client.run(running_executor).await }).detach();
+    common::wait_until(Duration::from_secs(5), || !recorder.events().is_empty()).await;
+    client
+}
+
+#[macro_rules_attribute::apply(smol_macros::test!)]
+async fn streaming_ticks_update_bars_studies_and_quotes(executor: Arc<Executor<'static>>) {
+    let script = MockTradingViewScript::new().with_tick_interval(Duration::from_millis(20)).with_ticks_per_bar(3);
+    let server = MockTradingViewServer::start(executor.clone(), script).await.unwrap();
+    let recorder = Recorder::default();
+    let client = start_streaming(&executor, &server, &recorder).await;
+
+    // every third tick opens a bar, closing the one before it, the ticks in between update it in place
+    let bar_updates = |events: &[TradingViewClientEvent]| events.iter().filter_map(|event| match event {
+        TradingViewClientEvent::BarUpdated { update, .. } => Some(update.clone()),
+        _ => None
+    }).collect::<Vec<_>>();
+    let quote_changed = |events: &[TradingViewClientEvent]| events.iter().any(|event| matches!(event, TradingViewClientEvent::QuoteChanged { changed_fields, .. } if changed_fields.contains(&QuoteField::Lp)));
+    common::wait_until(Duration::from_secs(5), || {
+        let events = recorder.events();
+        let updates = bar_updates(&events);
+        quote_changed(&events) && updates.iter().any(|new_bar| new_bar.change == BarSeriesChange::NewBar && updates.iter().any(|update| update.change == BarSeriesChange::UpdatedInPlace && update.bar.index == new_bar.bar.index))
+    }).await;
+    let events = recorder.events();
+    let updates = bar_updates(&events);
+    let new_bar = updates.iter().find(|update| update.change == BarSeriesChange::NewBar).expect("new bar");
+    let previous_bar = client.bar_series(CHART_SESSION_ID, SERIES_ID).await.and_then(|bar_series| bar_series.get(new_bar.bar.index - 1).cloned()).expect("previous bar");
+    assert_eq!(new_bar.bar.timestamp.seconds(), previous_bar.timestamp.seconds() + 300);
+
+    let closed = events.iter().filter_map(|event| match event {
+        TradingViewClientEvent::BarClosed { bar, by_timer, .. } => Some((bar.index, *by_timer)),
+        _ => None
+    }).collect::<Vec<_>>();
+    assert_eq!(closed.first(), Some(&(new_bar.bar.index - 1, false)));
+    assert!(closed.windows(2).all(|pair| pair[1].0 > pair[0].0));
+
+    // study rows go out ahead of the bar they belong to, held back rows are stamped once it arrives
+    let study_rows = events.iter().filter_map(|event| match event {
+        TradingViewClientEvent::StudyUpdated { study_id, rows, .. } if study_id == STUDY_ID => Some(rows.clone()),
+        _ => None
+    }).flatten().collect::<Vec<_>>();
+    assert!(study_rows.iter().any(|row| row.row.index == new_bar.bar.index));
+    assert!(study_rows.iter().all(|row| row.row.index == row.bar.index && row.row.timestamp == row.bar.timestamp));
+
+    assert!(client.quote("AMEX:SPY").await.and_then(|snapshot| snapshot.lp()).is_some_and(|lp| lp > 100.0));
+}
+
+#[macro_rules_attribute::apply(smol_macros::test!)]
+async fn bar_close_timer_closes_each_bar_once(executor: Arc<Executor<'static>>) {
+    // the server clock is past the end of bar 299 (plus the delay) but well inside bar 300
+    let script = MockTradingViewScript::new().with_clock_offset(302).with_tick_interval(Duration::from_millis(25)).with_ticks_per_bar(20);
+    let server = MockTradingViewServer::start(executor.clone(), script).await.unwrap();
+    let recorder = Recorder::default();
+    let config = TradingViewClientConfig {
+        bar_close_delay_ms: Some(1000),
+        ..common::config(&server, TradingViewClientMode::Streaming)
+    };
+    let client = Arc::new(config.to_client(recorder.processor()));
+    let running_client = client.clone();
+    let running_executor = executor.clone();
+    executor.spawn(async move { running_client.run(running_executor).await }).detach();
+
+    let closed = |events: &[TradingViewClientEvent]| events.iter().filter_map(|event| match event {
+        TradingViewClientEvent::BarClosed { bar, by_timer, .. } => Some((bar.index, *by_timer)),
+        _ => None
+    }).collect::<Vec<_>>();
+    common::wait_until(Duration::from_secs(5), || closed(&recorder.events()).len() >= 2).await;
+    let events = recorder.events();
+
+    // 299 ran out on the timer, 300 closed when 301 opened
+    let closed = closed(&events);
+    assert_eq!(closed[..2], [(299, true), (300, false)]);
+    assert!(closed.windows(2).all(|pair| pair[1].0 > pair[0].0));
+
+    // ticks for 299 after the timer closed it patch it in place without closing it again, and 300 opening doesn't either
+    let timer_close = events.iter().position(|event| matches!(event, TradingViewClientEvent::BarClosed { by_timer: true, .. })).unwrap();
+    let late_ticks = events[timer_close..].iter().filter(|event| matches!(event, TradingViewClientEvent::BarUpdated { update, .. } if update.bar.index == 299)).count();
+    assert!(late_ticks > 0);
+    let opened = events.iter().find_map(|event| match event {
+        TradingViewClientEvent::BarUpdated { update, .. } if update.change == BarSeriesChange::NewBar && update.bar.index == 300 => Some(update.clone()),
+        _ => None
+    }).expect("bar 300");
+    assert_eq!(opened.closed, None);
+    assert!(client.bar_series(CHART_SESSION_ID, SERIES_ID).await.is_some_and(|bar_series| bar_series.get(299).is_some()));
+}
+
+#[macro_rules_attribute::apply(smol_macros::test!)]
+async fn prepended_history_renumbers_bars_study_rows_and_closes(executor: Arc<Executor<'static>>) {
+    // the tickmark request is only the trigger: a study row ahead of its bar, a page of 3 older bars, then the bar the row belongs
+    // to under its new index
+    let script = MockTradingViewScript::new().with_clock_offset(302).on("request_more_tickmarks", |_| vec![
+        MockTradingViewReply::Message(format!(r#"{{"m":"du","p":["{CHART_SESSION_ID}",{{"{STUDY_ID}":{{"st":[{{"i":300,"v":[1700000400,123.0]}}],"ns":{{"d":"","indexes":"nochange"}}}}}}]}}"#)),
+        MockTradingViewReply::Message(PREPEND_FRAME.trim().to_string()),
+        MockTradingViewReply::Message(format!(r#"{{"m":"du","p":["{CHART_SESSION_ID}",{{"{SERIES_ID}":{{"s":[{{"i":303,"v":[1700000400,100.0,101.0,99.0,100.5,10.0]}}],"ns":{{"d":"","indexes":"nochange"}},"t":"s1"}}}}]}}"#)),
+        MockTradingViewReply::Message(format!(r#"{{"m":"tickmark_update","p":["{CHART_SESSION_ID}",{{"index":303,"zoffset":0,"changes":[],"marks":[]}}]}}"#)),
+    ]);
+    let server = MockTradingViewServer::start(executor.clone(), script).await.unwrap();
+    let recorder = Recorder::default();
+    let config = TradingViewClientConfig {
+        bar_close_delay_ms: Some(1000),
+        ..common::config(&server, TradingViewClientMode::Streaming)
+    };
+    let client = Arc::new(config.to_client(recorder.processor()));
+    let running_client = client.clone();
+    let running_executor = executor.clone();
+    executor.spawn(async move { running_client.run(running_executor).await }).detach();
+
+    // the timer closes 299 first, then the page moves it to 302
+    common::wait_until(Duration::from_secs(5), || recorder.events().iter().any(|event| matches!(event, TradingViewClientEvent::BarClosed { by_timer: true, .. }))).await;
+    client.request_more_tickmarks(CHART_SESSION_ID, SERIES_ID, 10).await.unwrap();
+    common::wait_until(Duration::from_secs(5), || recorder.events().iter().any(|event| matches!(event, TradingViewClientEvent::StudyUpdated { .. }))).await;
+
+    let bar_series = client.bar_series(CHART_SESSION_ID, SERIES_ID).await.expect("bar series");
+    assert_eq!(bar_series.len(), 304);
+    assert_eq!(bar_series.get(0).map(|bar| bar.timestamp), Some(TradingViewTimestamp::Seconds(1_699_909_500)));
+    assert_eq!(bar_series.get(302).map(|bar| bar.timestamp), Some(TradingViewTimestamp::Seconds(1_700_000_100)));
+    assert!(bar_series.bars().windows(2).all(|pair| pair[1].index == pair[0].index + 1 && pair[1].timestamp > pair[0].timestamp));
+
+    // the held row moved along with the bars and was stamped with the bar it was computed on
+    let rows = recorder.events().into_iter().filter_map(|event| match event {
+        TradingViewClientEvent::StudyUpdated { rows, .. } => Some(rows),
+        _ => None
+    }).flatten().collect::<Vec<_>>();
+    assert_eq!(rows.len(), 1);
+    assert_eq!(rows[0].row.index, 303);
+    assert_eq!(rows[0].timestamp(), TradingViewTimestamp::Seconds(1_700_000_400));
+    assert_eq!(rows[0].plot("Close"), Some(123.0));
+
+    // 302 was closed as 299, neither opening 303 nor the next timer check closes it again
+    async_io::Timer::after(Duration::from_millis(1100)).await;
+    let events = recorder.events();
+    let new_bars = events.iter().filter_map(|event| match event {
+        TradingViewClientEvent::BarUpdated { update, .. } if update.change == BarSeriesChange::NewBar => Some(update.clone()),
+        _ => None
+    }).collect::<Vec<_>>();
+    assert_eq!(new_bars.iter().map(|update| update.bar.index).collect::<Vec<_>>(), vec![0, 1, 2, 303]);
+    assert!(new_bars.iter().all(|update| update.closed.is_none()));
+    let closed = events.iter().filter(|event| matches!(event, TradingViewClientEvent::BarClosed { .. })).count();
+    assert_eq!(closed, 1);
+}
+
+#[macro_rules_attribute::apply(smol_macros::test!)]
+async fn request_more_tickmarks_returns_the_marks(executor: Arc<Executor<'static>>) {
+    let server = MockTradingViewServer::start(executor.clone(), MockTradingViewScript::new().with_tick_interval(Duration::from_millis(20))).await.unwrap();
+    let recorder = Recorder::default();
+    let client = start_streaming(&executor, &server, &recorder).await;
+
+    let tickmark_update = client.request_more_tickmarks(CHART_SESSION_ID, SERIES_ID, 100).await.unwrap();
+    assert_eq!(tickmark_update.chart_session_id, CHART_SESSION_ID);
+    assert_eq!(tickmark_update.index, 299);
+    // 5 minute bars, so one in every 12 lands on the hour
+    assert!(!tickmark_update.marks.is_empty());
+    assert!(tickmark_update.marks.iter().all(|mark| mark.timestamp.seconds() % 3600 == 0));
+}
+
+#[macro_rules_attribute::apply(smol_macros::test!)]
+async fn request_more_tickmarks_fails_on_an_unknown_series(executor: Arc<Executor<'static>>) {
+    let server = MockTradingViewServer::start(executor.clone(), MockTradingViewScript::new().with_tick_interval(Duration::from_millis(20))).await.unwrap();
+    let recorder = Recorder::default();
+    let client = start_streaming(&executor, &server, &recorder).await;
+
+    let err = client.request_more_tickmarks(CHART_SESSION_ID, "sds_9", 100).await.expect_err("unknown series");
+    assert!(matches!(err.downcast_ref::<TradingViewError>(), Some(TradingViewError::Rejected { code, .. }) if code == "unknown series"));
+}
+
+#[macro_rules_attribute::apply(smol_macros::test!)]
+async fn dropped_connection_reconnects_and_replays_every_session(executor: Arc<Executor<'static>>) {
+    // the first connection goes down right after the last setup command, the second one stays up
+    let closed_once = Arc::new(AtomicBool::new(false));
+    let script = MockTradingViewScript::new().on("quote_fast_symbols", move |_| {
+        if closed_once.swap(true, Ordering::SeqCst) { vec![] } else { vec![MockTradingViewReply::Close] }
+    });
+    let server = MockTradingViewServer::start(executor.clone(), script).await.unwrap();
+    let recorder = Recorder::default();
+    let config = TradingViewClientConfig {
+        reconnect: Some(TradingViewReconnectConfig {
+            max_attempts: 3,
+            initial_backoff_ms: 50,
+            max_backoff_ms: 50
+        }),
+        ..common::config(&server, TradingViewClientMode::Streaming)
+    };
+    let client = Arc::new(config.to_client(recorder.processor()));
+    let running_client = client.clone();
+    let running_executor = executor.clone();
+    executor.spawn(async move { running_client.run(running_executor).await }).detach();
+
+    let reconnected = |events: &[TradingViewClientEvent]| events.iter().filter_map(|event| match event {
+        TradingViewClientEvent::Reconnected { attempts, downtime } => Some((*attempts, *downtime)),
+        _ => None
+    }).collect::<Vec<_>>();
+    common::wait_until(Duration::from_secs(5), || !reconnected(&recorder.events()).is_empty()).await;
+    async_io::Timer::after(Duration::from_millis(200)).await;
+    assert_eq!(server.connections(), 2);
+
+    // one event for the one outage, after at least one backoff
+    let reconnected = reconnected(&recorder.events());
+    assert_eq!(reconnected.len(), 1);
+    assert_eq!(reconnected[0].0, 1);
+    assert!(reconnected[0].1 >= Duration::from_millis(50));
+
+    // the second connection gets the same auth, locale, chart, series, study and quote commands in the same order
+    let commands = server.received_commands().await.iter().map(|command| command.to_message()).collect::<Vec<_>>();
+    assert_eq!(commands.len() % 2, 0);
+    let (first, second) = commands.split_at(commands.len() / 2);
+    assert_eq!(first, second);
+    let methods = server.received_commands().await[commands.len() / 2..].iter().map(|command| command.method()).collect::<Vec<_>>();
+    assert_eq!(methods, vec![
+        "set_auth_token", "set_locale",
+        "chart_create_session", "resolve_symbol", "create_series", "switch_timezone", "create_study", "create_study",
+        "quote_create_session", "quote_set_fields", "quote_add_symbols", "quote_fast_symbols",
+    ]);
+
+    // the series was rebuilt from the new connection's snapshot
+    assert_eq!(client.bar_series(CHART_SESSION_ID, SERIES_ID).await.map(|bar_series| bar_series.len()), Some(300));
+}
+
+#[macro_rules_attribute::apply(smol_macros::test!)]
+async fn slow_processor_is_told_about_dropped_messages(executor: Arc<Executor<'static>>) {
+    // ticks arrive far faster than the processor takes them and the backlog only holds a few
+    let server = MockTradingViewServer::start(executor.clone(), MockTradingViewScript::new().with_tick_interval(Duration::from_millis(1))).await.unwrap();
+    let recorder = Recorder::with_message_delay(Duration::from_millis(20));
+    let config = TradingViewClientConfig {
+        backlog_capacity: Some(4),
+        ..common::config(&server, TradingViewClientMode::Streaming)
+    };
+    let client = Arc::new(config.to_client(recorder.processor()));
+    let running_client = client.clone();
+    let running_executor = executor.clone();
+    executor.spawn(async move { running_client.run(running_executor).await }).detach();
+
+    let drops = |events: &[TradingViewClientEvent]| events.iter().filter_map(|event| match event {
+        TradingViewClientEvent::MessagesDropped { dropped, total } => Some((*dropped, *total)),
+        _ => None
+    }).collect::<Vec<_>>();
+    common::wait_until(Duration::from_secs(5), || drops(&recorder.events()).len() >= 2).await;
+
+    // every event carries the drops since the one before it, and the totals add up to what the backlog counted
+    let drops = drops(&recorder.events());
+    assert!(drops.iter().all(|(dropped, _)| *dropped > 0));
+    assert!(drops.windows(2).all(|pair| pair[1].1 == pair[0].1 + pair[1].0));
+    assert_eq!(drops[0].0, drops[0].1);
+    assert!(client.backlog_stats().await.is_some_and(|stats| stats.dropped >= drops.last().unwrap().1));
+}
+
+#[macro_rules_attribute::apply(smol_macros::test!)]
+async fn first_quote_reaches_the_quote_book_when_setup_does_not_wait_for_a_last_price(executor: Arc<Executor<'static>>) {
+    // no ticks, so the qsd sent with quote_add_symbols is the only one there will be
+    let server = MockTradingViewServer::start(executor.clone(), MockTradingViewScript::new()).await.unwrap();
+    let recorder = Recorder::default();
+    let config = TradingViewClientConfig {
+        quote_fields: Some(QuoteField::fundamentals_fields()),
+        ..common::config(&server, TradingViewClientMode::Streaming)
+    };
+    let client = Arc::new(config.to_client(recorder.processor()));
+    let running_client = client.clone();
+    let running_executor = executor.clone();
+    executor.spawn(async move { running_client.run(running_executor).await }).detach();
+
+    common::wait_until(Duration::from_secs(5), || recorder.events().iter().any(|event| matches!(event, TradingViewClientEvent::QuoteChanged { .. }))).await;
+    assert!(client.quote("AMEX:SPY").await.and_then(|snapshot| snapshot.lp()).is_some());
+}
+
+#[macro_rules_attribute::apply(smol_macros::test!)]
+async fn request_more_tickmarks_ignores_errors_for_other_chart_sessions(executor: Arc<Executor<'static>>) {
+    // an error for another chart session lands while the tickmarks are pending
+    let script = MockTradingViewScript::new().with_tick_interval(Duration::from_millis(20)).on("request_more_tickmarks", |_| vec![
+        MockTradingViewReply::critical_error("cs_000000000002", "invalid_parameters", "create_series"),
+        MockTradingViewReply::Message(format!(r#"{{"m":"tickmark_update","p":["{CHART_SESSION_ID}",{{"index":299,"zoffset":0,"changes":[],"marks":[]}}]}}"#)),
+    ]);
+    let server = MockTradingViewServer::start(executor.clone(), script).await.unwrap();
+    let recorder = Recorder::default();
+    let client = start_streaming(&executor, &server, &recorder).await;
+
+    let tickmark_update = client.request_more_tickmarks(CHART_SESSION_ID, SERIES_ID, 100).await.unwrap();
+    assert_eq!(tickmark_update.index, 299);
+
+    // the error wasn't taken by the tickmark request, it went on to the processor
+    common::wait_until(Duration::from_secs(5), || recorder.messages().iter().any(|message| {
+        message.as_critical_error().is_some_and(|message| message.session_id == "cs_000000000002")
+    })).await;
+}